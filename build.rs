@@ -36,9 +36,14 @@ struct EnumValue {
     description: String,
 }
 
-fn compile_controls(out_dir: &Path) {
+/// Generates the definitions for all controls in a libcamera control/property
+/// ids yaml file.
+///
+/// 'namespace' is the C++ namespace under libcamera:: which contains the
+/// ControlId variables (either "controls" or "properties").
+fn compile_controls(yaml_path: &str, namespace: &str, out_path: &Path) {
     let control_ids: ControlIds = {
-        let yaml = std::fs::read_to_string("data/control_ids.yaml").unwrap();
+        let yaml = std::fs::read_to_string(yaml_path).unwrap();
         serde_yaml::from_str(&yaml).unwrap()
     };
 
@@ -55,6 +60,7 @@ fn compile_controls(out_dir: &Path) {
             "float" => ("f32", false),
             "Rectangle" => ("Rectangle", false),
             "Size" => ("Size", false),
+            "string" => ("String", false),
             _ => panic!("Unsupported control type: {}", control.typ),
         };
 
@@ -99,13 +105,19 @@ fn compile_controls(out_dir: &Path) {
             }
 
             if is_static_size {
-                control_type = format!("[{}]", control_type);
-            } else {
                 control_type = format!("[{}; {}]", control_type, size);
+            } else {
+                control_type = format!("[{}]", control_type);
             }
         }
 
-        let namespace = if control.draft { "draft" } else { "stable" };
+        let namespace = match (namespace, control.draft) {
+            ("controls", false) => "stable",
+            ("controls", true) => "draft",
+            ("properties", false) => "property",
+            ("properties", true) => "property_draft",
+            _ => panic!("Unsupported control namespace: {}", namespace),
+        };
 
         out.push_str(&format!(
             "control!({}, {}, {});\n\n",
@@ -121,18 +133,45 @@ fn compile_controls(out_dir: &Path) {
         // ));
     }
 
-    std::fs::write(out_dir.join("controls.rs"), out).unwrap();
+    std::fs::write(out_path, out).unwrap();
+}
+
+/// Adds derives to bindgen generated types which can't be implemented by hand
+/// (e.g. constants can only be used in match patterns if PartialEq is derived).
+#[derive(Debug)]
+struct DeriveCallbacks;
+
+impl bindgen::callbacks::ParseCallbacks for DeriveCallbacks {
+    fn add_derives(&self, info: &bindgen::callbacks::DeriveInfo<'_>) -> Vec<String> {
+        let derives: &[&str] = match info.name {
+            "PixelFormat" => &["PartialEq", "Eq", "PartialOrd", "Ord", "Hash"],
+            "Size" | "SizeRange" | "Rectangle" => &["PartialEq", "Eq", "Hash"],
+            _ => &[],
+        };
+
+        derives.iter().map(|s| s.to_string()).collect()
+    }
 }
 
 fn main() {
     let out_path = PathBuf::from(std::env::var("OUT_DIR").unwrap());
 
-    compile_controls(&out_path);
+    compile_controls(
+        "data/control_ids.yaml",
+        "controls",
+        &out_path.join("controls.rs"),
+    );
+    compile_controls(
+        "data/property_ids.yaml",
+        "properties",
+        &out_path.join("properties.rs"),
+    );
 
     // todo!();
 
     let bindings = bindgen::Builder::default()
         .parse_callbacks(Box::new(bindgen::CargoCallbacks))
+        .parse_callbacks(Box::new(DeriveCallbacks))
         .header("src/wrapper.h")
         .newtype_enum(".*")
         .allowlist_type("libcamera.*StreamRole")
@@ -147,8 +186,8 @@ fn main() {
         .allowlist_type("libcamera.*ControlType")
         .allowlist_type("libcamera.*Rectangle")
         .allowlist_type("libcamera.*ControlValuePrimitive")
-        .allowlist_var("libcamera.*formats.*")
         .allowlist_var("libcamera.*controls.*")
+        .allowlist_var("libcamera.*properties.*")
         .opaque_type("libcamera.*Control.*")
         .no_debug("libcamera.*PixelFormat")
         .enable_cxx_namespaces()
@@ -0,0 +1,435 @@
+use std::sync::{Arc, Mutex};
+
+use crate::camera::RunningCamera;
+use crate::completion::{Completion, CompletionFuture};
+use crate::controls::{self, AfMeteringEnum, AfModeEnum, AfStateEnum, AfTriggerEnum};
+use crate::geometry::Rectangle;
+use crate::request::{Request, RequestStatus};
+
+/// Outcome of an autofocus scan started with Autofocus::trigger_once().
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FocusResult {
+    /// The algorithm believes the image is in focus. The lens position (in
+    /// dioptres) is included if the camera reports it.
+    Focused { lens_position: Option<f32> },
+
+    /// The scan completed without finding a good focus position.
+    Failed { lens_position: Option<f32> },
+
+    /// The scan was cancelled, superseded by another trigger or the AF mode
+    /// was changed before it finished.
+    Cancelled,
+}
+
+/// Drives the autofocus controls of a running camera.
+///
+/// The helper doesn't queue requests itself. Instead the application must
+/// call apply() on every request before it is enqueued and observe() on every
+/// request once it completes. Controls set on the helper are sent with the
+/// next request passed to apply().
+#[derive(Clone)]
+pub struct Autofocus {
+    state: Arc<Mutex<AutofocusState>>,
+    completion: Arc<Mutex<Completion<FocusResult>>>,
+
+    /// Valid range of LensPosition values (if the camera advertises one).
+    lens_limits: Option<(f32, f32)>,
+}
+
+#[derive(Default)]
+struct AutofocusState {
+    mode: Option<AfModeEnum>,
+    trigger: Option<AfTriggerEnum>,
+    lens_position: Option<f32>,
+    window: Option<Rectangle>,
+
+    scan: ScanState,
+
+    /// Camera's max_latency().
+    latency: u32,
+
+    last_state: Option<AfStateEnum>,
+    last_lens_position: Option<f32>,
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Eq)]
+enum ScanState {
+    #[default]
+    Idle,
+
+    /// A trigger is waiting to be sent by apply().
+    Queued,
+
+    /// The trigger was sent in the request with the given cookie.
+    Sent {
+        cookie: u64,
+    },
+
+    /// The trigger request has completed 'frames' requests ago, but AfState
+    /// hasn't reported scanning yet.
+    Started {
+        frames: u32,
+    },
+
+    Scanning,
+}
+
+impl Autofocus {
+    /// Creates a helper for a running camera. Returns None if the camera
+    /// doesn't support the AfMode control.
+    pub fn new(camera: &RunningCamera) -> Option<Self> {
+        let info_map = camera.controls();
+        info_map.get(controls::AfMode)?;
+
+        let lens_limits = info_map
+            .get(controls::LensPosition)
+            .and_then(|info| info.limits());
+
+        Some(Self {
+            state: Arc::new(Mutex::new(AutofocusState {
+                latency: camera.max_latency(),
+                ..Default::default()
+            })),
+            completion: Completion::new(FocusResult::Cancelled),
+            lens_limits,
+        })
+    }
+
+    /// Starts a single autofocus scan and waits for it to finish.
+    ///
+    /// The scan starts with the next request passed to apply() and the future
+    /// completes once observe() sees AfState settle on focused or failed. It
+    /// completes with Cancelled if the request carrying the trigger is
+    /// cancelled.
+    pub fn trigger_once(&self) -> FocusFuture {
+        let mut state = self.state.lock().unwrap();
+        self.abandon_scan(&mut state);
+
+        state.mode = Some(AfModeEnum::AfModeAuto);
+        state.trigger = Some(AfTriggerEnum::AfTriggerStart);
+        state.scan = ScanState::Queued;
+
+        Completion::future(&self.completion)
+    }
+
+    /// Cancels any in-progress scan started by trigger_once().
+    pub fn cancel(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.scan != ScanState::Idle {
+            self.abandon_scan(&mut state);
+            state.trigger = Some(AfTriggerEnum::AfTriggerCancel);
+        }
+    }
+
+    /// Lets the camera refocus whenever it decides that it is needed.
+    pub fn set_continuous(&self) {
+        let mut state = self.state.lock().unwrap();
+        self.abandon_scan(&mut state);
+        state.trigger = None;
+        state.mode = Some(AfModeEnum::AfModeContinuous);
+    }
+
+    /// Moves the lens to a fixed position given in dioptres (the reciprocal
+    /// of the focus distance in metres, so 0 focuses at infinity).
+    ///
+    /// The position is clamped to the range supported by the camera.
+    pub fn set_manual(&self, dioptres: f32) {
+        let dioptres = match self.lens_limits {
+            Some((min, max)) => dioptres.clamp(min, max),
+            None => dioptres,
+        };
+
+        let mut state = self.state.lock().unwrap();
+        self.abandon_scan(&mut state);
+        state.trigger = None;
+        state.mode = Some(AfModeEnum::AfModeManual);
+        state.lens_position = Some(dioptres);
+    }
+
+    /// Restricts focus measurement to a region of the image, given in the
+    /// coordinates of the ScalerCropMaximum rectangle.
+    pub fn set_window(&self, window: Rectangle) {
+        self.state.lock().unwrap().window = Some(window);
+    }
+
+    /// Gets the last AfState reported by a request passed to observe().
+    pub fn state(&self) -> Option<AfStateEnum> {
+        self.state.lock().unwrap().last_state
+    }
+
+    /// Gets the last LensPosition reported by a request passed to observe().
+    pub fn lens_position(&self) -> Option<f32> {
+        self.state.lock().unwrap().last_lens_position
+    }
+
+    /// Adds any autofocus controls which have changed since the last call to
+    /// a request which is about to be enqueued.
+    pub fn apply(&self, request: &mut Request) {
+        let mut state = self.state.lock().unwrap();
+        let list = request.controls_mut();
+
+        if let Some(mode) = state.mode.take() {
+            list.set(controls::AfMode, mode);
+        }
+
+        if let Some(window) = state.window.take() {
+            list.set(controls::AfMetering, AfMeteringEnum::AfMeteringWindows);
+            list.set_slice(controls::AfWindows, &[window]);
+        }
+
+        if let Some(lens_position) = state.lens_position.take() {
+            list.set(controls::LensPosition, lens_position);
+        }
+
+        if let Some(trigger) = state.trigger.take() {
+            list.set(controls::AfTrigger, trigger);
+            state.trigger_sent(request.cookie());
+        }
+    }
+
+    /// Updates the autofocus state from the metadata of a completed request.
+    pub fn observe(&self, request: &Request) {
+        let metadata = request.metadata();
+        self.observe_values(
+            request.cookie(),
+            request.status(),
+            metadata.get(controls::AfState),
+            metadata.get(controls::LensPosition),
+        );
+    }
+
+    fn observe_values(
+        &self,
+        cookie: u64,
+        status: RequestStatus,
+        af_state: Option<AfStateEnum>,
+        lens_position: Option<f32>,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        if af_state.is_some() {
+            state.last_state = af_state;
+        }
+        if lens_position.is_some() {
+            state.last_lens_position = lens_position;
+        }
+
+        if let ScanState::Sent { cookie: c } = state.scan {
+            if c != cookie {
+                return;
+            }
+
+            // The trigger is never applied if its request was cancelled.
+            if status != RequestStatus::RequestComplete {
+                self.finish_scan(&mut state, FocusResult::Cancelled);
+                return;
+            }
+
+            state.scan = ScanState::Started { frames: 0 };
+        }
+
+        // NOTE: AfState may still report the result of a previous scan until
+        // the trigger takes effect, so a result is only accepted once the
+        // scan has been seen to start or once the camera's latency has passed
+        // (a short scan may never be reported as scanning).
+        let done = match state.scan {
+            ScanState::Started { .. } if af_state == Some(AfStateEnum::AfStateScanning) => {
+                state.scan = ScanState::Scanning;
+                false
+            }
+            ScanState::Started { frames } if frames < state.latency => {
+                state.scan = ScanState::Started { frames: frames + 1 };
+                false
+            }
+            ScanState::Started { .. } | ScanState::Scanning => true,
+            _ => false,
+        };
+
+        if !done {
+            return;
+        }
+
+        let result = match af_state {
+            Some(AfStateEnum::AfStateFocused) => FocusResult::Focused {
+                lens_position: state.last_lens_position,
+            },
+            Some(AfStateEnum::AfStateFailed) => FocusResult::Failed {
+                lens_position: state.last_lens_position,
+            },
+            Some(AfStateEnum::AfStateIdle) => FocusResult::Cancelled,
+            _ => return,
+        };
+
+        self.finish_scan(&mut state, result);
+    }
+
+    fn finish_scan(&self, state: &mut AutofocusState, result: FocusResult) {
+        state.scan = ScanState::Idle;
+        self.completion.lock().unwrap().finish(result);
+    }
+
+    /// Stops waiting for the current scan (if any) so that a new one can be
+    /// started.
+    fn abandon_scan(&self, state: &mut AutofocusState) {
+        state.scan = ScanState::Idle;
+        self.completion.lock().unwrap().supersede();
+    }
+}
+
+impl AutofocusState {
+    /// Records that the trigger was sent with the request with the given
+    /// cookie.
+    fn trigger_sent(&mut self, cookie: u64) {
+        if self.scan == ScanState::Queued {
+            self.scan = ScanState::Sent { cookie };
+        }
+    }
+}
+
+/// Future returned by Autofocus::trigger_once().
+pub type FocusFuture = CompletionFuture<FocusResult>;
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+
+    use super::*;
+
+    const COMPLETE: RequestStatus = RequestStatus::RequestComplete;
+    const CANCELLED: RequestStatus = RequestStatus::RequestCancelled;
+
+    fn autofocus(latency: u32) -> Autofocus {
+        Autofocus {
+            state: Arc::new(Mutex::new(AutofocusState {
+                latency,
+                ..Default::default()
+            })),
+            completion: Completion::new(FocusResult::Cancelled),
+            lens_limits: None,
+        }
+    }
+
+    /// Does what apply() does with the trigger for the request with the
+    /// given cookie.
+    fn send_trigger(autofocus: &Autofocus, cookie: u64) -> Option<AfTriggerEnum> {
+        let mut state = autofocus.state.lock().unwrap();
+        let trigger = state.trigger.take();
+        if trigger.is_some() {
+            state.trigger_sent(cookie);
+        }
+        trigger
+    }
+
+    fn poll(future: &mut FocusFuture) -> Poll<FocusResult> {
+        pin!(future).poll(&mut Context::from_waker(Waker::noop()))
+    }
+
+    #[test]
+    fn scan_completes_when_focused() {
+        let autofocus = autofocus(2);
+        let mut future = autofocus.trigger_once();
+        assert_eq!(
+            send_trigger(&autofocus, 1),
+            Some(AfTriggerEnum::AfTriggerStart)
+        );
+        assert_eq!(send_trigger(&autofocus, 2), None);
+
+        autofocus.observe_values(1, COMPLETE, Some(AfStateEnum::AfStateScanning), Some(0.5));
+        assert_eq!(poll(&mut future), Poll::Pending);
+
+        autofocus.observe_values(2, COMPLETE, Some(AfStateEnum::AfStateFocused), Some(1.5));
+        assert_eq!(
+            poll(&mut future),
+            Poll::Ready(FocusResult::Focused {
+                lens_position: Some(1.5)
+            })
+        );
+        assert_eq!(autofocus.state(), Some(AfStateEnum::AfStateFocused));
+    }
+
+    #[test]
+    fn scan_reports_failure() {
+        let autofocus = autofocus(2);
+        let mut future = autofocus.trigger_once();
+        send_trigger(&autofocus, 1);
+
+        autofocus.observe_values(1, COMPLETE, Some(AfStateEnum::AfStateScanning), None);
+        autofocus.observe_values(2, COMPLETE, Some(AfStateEnum::AfStateFailed), None);
+        assert_eq!(
+            poll(&mut future),
+            Poll::Ready(FocusResult::Failed {
+                lens_position: None
+            })
+        );
+    }
+
+    #[test]
+    fn requests_before_the_trigger_are_ignored() {
+        let autofocus = autofocus(0);
+        let mut future = autofocus.trigger_once();
+        send_trigger(&autofocus, 2);
+
+        autofocus.observe_values(1, COMPLETE, Some(AfStateEnum::AfStateFocused), None);
+        assert_eq!(poll(&mut future), Poll::Pending);
+    }
+
+    #[test]
+    fn stale_result_is_accepted_after_latency() {
+        let autofocus = autofocus(2);
+        let mut future = autofocus.trigger_once();
+        send_trigger(&autofocus, 1);
+
+        // A scan which is too short to be reported as scanning can't be told
+        // apart from the result of a previous scan until the latency passes.
+        for cookie in 1..=2 {
+            autofocus.observe_values(cookie, COMPLETE, Some(AfStateEnum::AfStateFocused), None);
+            assert_eq!(poll(&mut future), Poll::Pending);
+        }
+
+        autofocus.observe_values(3, COMPLETE, Some(AfStateEnum::AfStateFocused), None);
+        assert_eq!(
+            poll(&mut future),
+            Poll::Ready(FocusResult::Focused {
+                lens_position: None
+            })
+        );
+    }
+
+    #[test]
+    fn cancelled_trigger_request_cancels_scan() {
+        let autofocus = autofocus(2);
+        let mut future = autofocus.trigger_once();
+        send_trigger(&autofocus, 1);
+
+        autofocus.observe_values(1, CANCELLED, None, None);
+        assert_eq!(poll(&mut future), Poll::Ready(FocusResult::Cancelled));
+    }
+
+    #[test]
+    fn idle_after_scanning_cancels_scan() {
+        let autofocus = autofocus(2);
+        let mut future = autofocus.trigger_once();
+        send_trigger(&autofocus, 1);
+
+        autofocus.observe_values(1, COMPLETE, Some(AfStateEnum::AfStateScanning), None);
+        autofocus.observe_values(2, COMPLETE, Some(AfStateEnum::AfStateIdle), None);
+        assert_eq!(poll(&mut future), Poll::Ready(FocusResult::Cancelled));
+    }
+
+    #[test]
+    fn superseded_scan_is_cancelled() {
+        let autofocus = autofocus(2);
+        let mut future = autofocus.trigger_once();
+        send_trigger(&autofocus, 1);
+
+        autofocus.set_continuous();
+        assert_eq!(poll(&mut future), Poll::Ready(FocusResult::Cancelled));
+
+        // The old scan's result doesn't complete the next one.
+        let mut next = autofocus.trigger_once();
+        autofocus.observe_values(1, COMPLETE, Some(AfStateEnum::AfStateScanning), None);
+        autofocus.observe_values(2, COMPLETE, Some(AfStateEnum::AfStateFocused), None);
+        assert_eq!(poll(&mut next), Poll::Pending);
+    }
+}
@@ -0,0 +1,491 @@
+//! Unpacking and basic processing of raw Bayer frames.
+//!
+//! This is intended for inspecting raw captures rather than for high quality
+//! image processing: demosaicing is bilinear and no colour correction, lens
+//! shading or denoising is applied.
+
+use crate::control_list::ControlList;
+use crate::controls;
+use crate::convert::ConvertError;
+use crate::pixel_format::PixelFormat;
+use crate::pixel_format_info::{BayerOrder, ColourEncoding, Packing, PixelFormatInfo};
+use crate::stream_configuration::Size;
+
+/// Colour of a single sample in a Bayer pattern.
+///
+/// The numeric values of these are the order used by the SensorBlackLevels
+/// control.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BayerChannel {
+    Red = 0,
+    /// Green sample on a line with red samples.
+    GreenRed = 1,
+    /// Green sample on a line with blue samples.
+    GreenBlue = 2,
+    Blue = 3,
+}
+
+impl BayerChannel {
+    pub fn is_green(&self) -> bool {
+        matches!(self, BayerChannel::GreenRed | BayerChannel::GreenBlue)
+    }
+}
+
+impl BayerOrder {
+    /// Gets the colour of the sample at the given coordinates.
+    pub fn channel_at(&self, x: usize, y: usize) -> BayerChannel {
+        use BayerChannel::*;
+
+        let pattern = match self {
+            BayerOrder::Rggb => [Red, GreenRed, GreenBlue, Blue],
+            BayerOrder::Grbg => [GreenRed, Red, Blue, GreenBlue],
+            BayerOrder::Gbrg => [GreenBlue, Blue, Red, GreenRed],
+            BayerOrder::Bggr => [Blue, GreenBlue, GreenRed, Red],
+        };
+
+        pattern[(y & 1) * 2 + (x & 1)]
+    }
+}
+
+/// Raw sensor samples unpacked to one u16 per pixel.
+#[derive(Debug, Clone)]
+pub struct RawImage {
+    pub size: Size,
+
+    /// None for monochrome sensors.
+    pub bayer_order: Option<BayerOrder>,
+
+    /// Number of significant bits in each sample.
+    pub bit_depth: u32,
+
+    /// Row major samples with no padding between lines.
+    pub data: Vec<u16>,
+}
+
+impl RawImage {
+    /// Unpacks a frame stored in a raw pixel format.
+    ///
+    /// 'stride' is the number of bytes in each line of 'data'. If zero, lines
+    /// are assumed to be tightly packed.
+    pub fn unpack(
+        data: &[u8],
+        format: PixelFormat,
+        size: Size,
+        stride: u32,
+    ) -> Result<Self, ConvertError> {
+        let info = PixelFormatInfo::lookup(format)
+            .filter(|info| info.colour_encoding == ColourEncoding::Raw)
+            .ok_or(ConvertError::UnsupportedFormat(format))?;
+
+        let bit_depth = info.bits_per_pixel;
+        let unpack_line: fn(&[u8], &mut [u16]) = match (info.packing, bit_depth) {
+            (Packing::None, 8) => unpack_line_8,
+            (Packing::None, 10..=16) => unpack_line_16,
+            (Packing::Csi2, 10) => unpack_line_csi2_10,
+            (Packing::Csi2, 12) => unpack_line_csi2_12,
+            (Packing::Csi2, 14) => unpack_line_csi2_14,
+            _ => return Err(ConvertError::UnsupportedFormat(format)),
+        };
+
+        let width = size.width as usize;
+        let height = size.height as usize;
+
+        let line_size = info.stride(size.width, 0, 1) as usize;
+        let stride = if stride != 0 {
+            stride as usize
+        } else {
+            line_size
+        };
+
+        let expected = if height > 0 {
+            (height - 1) * stride + line_size
+        } else {
+            0
+        };
+        if data.len() < expected {
+            return Err(ConvertError::BufferTooSmall {
+                plane: 0,
+                expected,
+                actual: data.len(),
+            });
+        }
+
+        let mut samples = vec![0; width * height];
+        if width > 0 {
+            for (y, line) in samples.chunks_exact_mut(width).enumerate() {
+                unpack_line(&data[(y * stride)..(y * stride + line_size)], line);
+            }
+        }
+
+        Ok(Self {
+            size,
+            bayer_order: info.bayer_order,
+            bit_depth,
+            data: samples,
+        })
+    }
+
+    /// Largest value a sample can have.
+    pub fn white_level(&self) -> u16 {
+        ((1u32 << self.bit_depth) - 1) as u16
+    }
+
+    /// Gets the colour of the sample at the given coordinates. Monochrome
+    /// images are treated as entirely green.
+    pub fn channel_at(&self, x: usize, y: usize) -> BayerChannel {
+        self.bayer_order
+            .map(|order| order.channel_at(x, y))
+            .unwrap_or(BayerChannel::GreenRed)
+    }
+
+    /// Subtracts a per channel black level from every sample and rescales the
+    /// remaining range so that the white level is preserved.
+    ///
+    /// 'levels' are in the order R, Gr, Gb, B and are scaled to a 16-bit
+    /// range, as reported by the SensorBlackLevels control.
+    pub fn subtract_black_level(&mut self, levels: [i32; 4]) {
+        let white = self.white_level() as u32;
+        let shift = 16 - self.bit_depth;
+        let levels = levels.map(|l| ((l.max(0) as u32) >> shift).min(white - 1));
+
+        self.for_each_sample(|channel, sample| {
+            let black = levels[channel as usize];
+            let v = (*sample as u32).saturating_sub(black);
+            *sample = ((v * white + (white - black) / 2) / (white - black)).min(white) as u16;
+        });
+    }
+
+    /// Multiplies the red and blue samples by the given gains, as reported by
+    /// the ColourGains control.
+    pub fn apply_white_balance(&mut self, red_gain: f32, blue_gain: f32) {
+        let white = self.white_level() as f32;
+
+        self.for_each_sample(|channel, sample| {
+            let gain = match channel {
+                BayerChannel::Red => red_gain,
+                BayerChannel::Blue => blue_gain,
+                _ => return,
+            };
+
+            *sample = (*sample as f32 * gain).round().clamp(0.0, white) as u16;
+        });
+    }
+
+    /// Applies the black level and white balance reported in a completed
+    /// request's metadata. Controls missing from the metadata are skipped.
+    pub fn apply_metadata(&mut self, metadata: &ControlList) {
+        if let Some(levels) = metadata.get(controls::SensorBlackLevels) {
+            self.subtract_black_level(levels);
+        }
+
+        if let Some([red_gain, blue_gain]) = metadata.get(controls::ColourGains) {
+            self.apply_white_balance(red_gain, blue_gain);
+        }
+    }
+
+    /// Interpolates the missing colours at each sample using the average of
+    /// neighbouring samples of that colour.
+    ///
+    /// Returns R, G, B samples for each pixel with the same bit depth as the
+    /// raw image.
+    pub fn debayer(&self) -> Vec<u16> {
+        let width = self.size.width as usize;
+        let height = self.size.height as usize;
+
+        let mut out = Vec::with_capacity(width * height * 3);
+
+        for y in 0..height {
+            for x in 0..width {
+                let own = self.channel_at(x, y);
+                let own_value = self.data[y * width + x] as u32;
+
+                // Sums and counts of neighbouring samples for R, G, B.
+                let mut sums = [0u32; 3];
+                let mut counts = [0u32; 3];
+
+                for ny in y.saturating_sub(1)..(y + 2).min(height) {
+                    for nx in x.saturating_sub(1)..(x + 2).min(width) {
+                        let i = rgb_index(self.channel_at(nx, ny));
+                        sums[i] += self.data[ny * width + nx] as u32;
+                        counts[i] += 1;
+                    }
+                }
+
+                for i in 0..3 {
+                    let value = if i == rgb_index(own) {
+                        own_value
+                    } else if counts[i] > 0 {
+                        (sums[i] + counts[i] / 2) / counts[i]
+                    } else {
+                        own_value
+                    };
+
+                    out.push(value as u16);
+                }
+            }
+        }
+
+        out
+    }
+
+    /// Same as debayer() but scales each sample down to 8 bits.
+    pub fn debayer_rgb8(&self) -> Vec<u8> {
+        let shift = self.bit_depth.saturating_sub(8);
+        self.debayer()
+            .into_iter()
+            .map(|v| (v >> shift) as u8)
+            .collect()
+    }
+
+    fn for_each_sample<F: FnMut(BayerChannel, &mut u16)>(&mut self, mut f: F) {
+        let width = self.size.width as usize;
+        if width == 0 {
+            return;
+        }
+
+        let order = self.bayer_order;
+        for (y, line) in self.data.chunks_exact_mut(width).enumerate() {
+            for (x, sample) in line.iter_mut().enumerate() {
+                let channel = order
+                    .map(|order| order.channel_at(x, y))
+                    .unwrap_or(BayerChannel::GreenRed);
+                f(channel, sample);
+            }
+        }
+    }
+}
+
+fn rgb_index(channel: BayerChannel) -> usize {
+    match channel {
+        BayerChannel::Red => 0,
+        BayerChannel::GreenRed | BayerChannel::GreenBlue => 1,
+        BayerChannel::Blue => 2,
+    }
+}
+
+fn unpack_line_8(data: &[u8], out: &mut [u16]) {
+    for (o, v) in out.iter_mut().zip(data) {
+        *o = *v as u16;
+    }
+}
+
+fn unpack_line_16(data: &[u8], out: &mut [u16]) {
+    for (o, v) in out.iter_mut().zip(data.chunks_exact(2)) {
+        *o = u16::from_le_bytes([v[0], v[1]]);
+    }
+}
+
+/// Every 4 pixels are stored in 5 bytes: the upper 8 bits of each pixel
+/// followed by a byte containing the lower 2 bits of each.
+fn unpack_line_csi2_10(data: &[u8], out: &mut [u16]) {
+    for (pixels, group) in out.chunks_mut(4).zip(data.chunks_exact(5)) {
+        for (i, p) in pixels.iter_mut().enumerate() {
+            *p = ((group[i] as u16) << 2) | ((group[4] as u16 >> (2 * i)) & 0x3);
+        }
+    }
+}
+
+/// Every 2 pixels are stored in 3 bytes: the upper 8 bits of each pixel
+/// followed by a byte containing the lower 4 bits of each.
+fn unpack_line_csi2_12(data: &[u8], out: &mut [u16]) {
+    for (pixels, group) in out.chunks_mut(2).zip(data.chunks_exact(3)) {
+        for (i, p) in pixels.iter_mut().enumerate() {
+            *p = ((group[i] as u16) << 4) | ((group[2] as u16 >> (4 * i)) & 0xf);
+        }
+    }
+}
+
+/// Every 4 pixels are stored in 7 bytes: the upper 8 bits of each pixel
+/// followed by 3 bytes containing the lower 6 bits of each as a little endian
+/// bit field.
+fn unpack_line_csi2_14(data: &[u8], out: &mut [u16]) {
+    for (pixels, group) in out.chunks_mut(4).zip(data.chunks_exact(7)) {
+        let low = u32::from_le_bytes([group[4], group[5], group[6], 0]);
+        for (i, p) in pixels.iter_mut().enumerate() {
+            *p = ((group[i] as u16) << 6) | ((low >> (6 * i)) & 0x3f) as u16;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formats;
+
+    fn pack_csi2_10(values: &[u16]) -> Vec<u8> {
+        let mut out = vec![];
+        for group in values.chunks(4) {
+            let mut low = 0;
+            for (i, v) in group.iter().enumerate() {
+                out.push((v >> 2) as u8);
+                low |= ((v & 0x3) as u8) << (2 * i);
+            }
+            out.push(low);
+        }
+        out
+    }
+
+    fn pack_csi2_12(values: &[u16]) -> Vec<u8> {
+        let mut out = vec![];
+        for pair in values.chunks(2) {
+            out.push((pair[0] >> 4) as u8);
+            out.push((pair[1] >> 4) as u8);
+            out.push(((pair[0] & 0xf) | ((pair[1] & 0xf) << 4)) as u8);
+        }
+        out
+    }
+
+    fn pack_csi2_14(values: &[u16]) -> Vec<u8> {
+        let mut out = vec![];
+        for group in values.chunks(4) {
+            let mut low = 0u32;
+            for (i, v) in group.iter().enumerate() {
+                out.push((v >> 6) as u8);
+                low |= ((v & 0x3f) as u32) << (6 * i);
+            }
+            out.extend_from_slice(&low.to_le_bytes()[..3]);
+        }
+        out
+    }
+
+    /// Packs each line of 'lines' and pads it to 'stride' bytes.
+    fn pack_frame(lines: &[Vec<u16>], pack: fn(&[u16]) -> Vec<u8>, stride: usize) -> Vec<u8> {
+        let mut out = vec![];
+        for line in lines {
+            let mut packed = pack(line);
+            assert!(packed.len() <= stride);
+            packed.resize(stride, 0xee);
+            out.extend_from_slice(&packed);
+        }
+        out
+    }
+
+    fn test_lines(bit_depth: u32) -> Vec<Vec<u16>> {
+        let max = (1u16 << bit_depth) - 1;
+        vec![
+            vec![0, 1, 2, 3, max, max - 1, max / 2, 0x155 & max],
+            vec![max, 0, max / 3, 7, 0x2aa & max, 1, max, 0],
+        ]
+    }
+
+    fn check_round_trip(
+        format: PixelFormat,
+        bit_depth: u32,
+        pack: fn(&[u16]) -> Vec<u8>,
+        stride: usize,
+    ) {
+        let lines = test_lines(bit_depth);
+        let data = pack_frame(&lines, pack, stride);
+        let size = Size {
+            width: 8,
+            height: 2,
+        };
+
+        let image = RawImage::unpack(&data, format, size, stride as u32).unwrap();
+        assert_eq!(image.bit_depth, bit_depth);
+        assert_eq!(image.bayer_order, Some(BayerOrder::Rggb));
+        assert_eq!(image.data, lines.concat());
+    }
+
+    #[test]
+    fn unpack_csi2_10() {
+        // Each line takes 10 bytes.
+        check_round_trip(formats::SRGGB10_CSI2P, 10, pack_csi2_10, 16);
+    }
+
+    #[test]
+    fn unpack_csi2_12() {
+        // Each line takes 12 bytes.
+        check_round_trip(formats::SRGGB12_CSI2P, 12, pack_csi2_12, 16);
+    }
+
+    #[test]
+    fn unpack_csi2_14() {
+        // Each line takes 14 bytes.
+        check_round_trip(formats::SRGGB14_CSI2P, 14, pack_csi2_14, 20);
+    }
+
+    #[test]
+    fn unpack_omits_last_line_padding() {
+        let lines = test_lines(10);
+        let mut data = pack_frame(&lines, pack_csi2_10, 16);
+        data.truncate(16 + 10);
+
+        let size = Size {
+            width: 8,
+            height: 2,
+        };
+        let image = RawImage::unpack(&data, formats::SRGGB10_CSI2P, size, 16).unwrap();
+        assert_eq!(image.data, lines.concat());
+
+        data.pop();
+        assert!(matches!(
+            RawImage::unpack(&data, formats::SRGGB10_CSI2P, size, 16),
+            Err(ConvertError::BufferTooSmall { .. })
+        ));
+    }
+
+    fn image(order: BayerOrder, data: Vec<u16>) -> RawImage {
+        RawImage {
+            size: Size {
+                width: 4,
+                height: 4,
+            },
+            bayer_order: Some(order),
+            bit_depth: 10,
+            data,
+        }
+    }
+
+    #[test]
+    fn black_level() {
+        let mut image = image(BayerOrder::Rggb, vec![64; 16]);
+        image.data[0] = 1023;
+        image.data[1] = 544;
+        image.data[2] = 10;
+
+        // A black level of 64 in 10 bits.
+        image.subtract_black_level([64 << 6; 4]);
+
+        assert_eq!(image.data[0], 1023);
+        assert_eq!(image.data[1], 512);
+        assert_eq!(image.data[2], 0);
+        assert_eq!(image.data[3], 0);
+    }
+
+    #[test]
+    fn white_balance_clamps() {
+        let mut image = image(BayerOrder::Rggb, vec![500; 16]);
+        image.apply_white_balance(4.0, 0.5);
+
+        // R at (0, 0), Gr at (1, 0), Gb at (0, 1) and B at (1, 1).
+        assert_eq!(image.data[0], 1023);
+        assert_eq!(image.data[1], 500);
+        assert_eq!(image.data[4], 500);
+        assert_eq!(image.data[5], 250);
+
+        image.apply_white_balance(-1.0, 1.0);
+        assert_eq!(image.data[0], 0);
+    }
+
+    #[test]
+    fn debayer_uniform_patch() {
+        for order in [
+            BayerOrder::Rggb,
+            BayerOrder::Grbg,
+            BayerOrder::Gbrg,
+            BayerOrder::Bggr,
+        ] {
+            let data = (0..16)
+                .map(|i| match order.channel_at(i % 4, i / 4) {
+                    BayerChannel::Red => 100,
+                    BayerChannel::GreenRed | BayerChannel::GreenBlue => 200,
+                    BayerChannel::Blue => 300,
+                })
+                .collect();
+
+            let rgb = image(order, data).debayer();
+            assert_eq!(rgb, [100, 200, 300].repeat(16), "{:?}", order);
+        }
+    }
+}
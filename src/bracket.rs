@@ -0,0 +1,544 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::Duration;
+
+use crate::camera::Camera;
+use crate::controls;
+use crate::errors::Error;
+use crate::exposure::{settled, ExposureSettings};
+use crate::request::{CompletedRequest, NewRequest, PendingRequest, Request, RequestStatus};
+
+/// Largest difference between requested and reported ExposureValue for them
+/// to be considered the same.
+const EV_TOLERANCE: f32 = 0.05;
+
+/// Exposure setting for a single frame of a bracket.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BracketSetting {
+    /// Exposure compensation (in stops) applied on top of AE.
+    ExposureValue(f32),
+
+    /// Fixed exposure with AE disabled.
+    Manual {
+        exposure_time: Duration,
+        analogue_gain: f32,
+    },
+}
+
+/// A frame captured with one of the settings of a bracket.
+pub struct BracketFrame {
+    /// Index of the setting in the list given to Bracket::new().
+    pub index: usize,
+    pub setting: BracketSetting,
+    pub request: CompletedRequest,
+}
+
+/// Result of Bracket::capture().
+pub struct BracketCapture {
+    /// One frame per setting, in the same order as the settings.
+    pub frames: Vec<BracketFrame>,
+
+    /// Requests which weren't used for a frame. These can be enqueued again.
+    pub unused: Vec<NewRequest>,
+
+    /// Number of frames which completed before the settings they were
+    /// captured with had taken effect.
+    pub discarded: usize,
+}
+
+impl fmt::Debug for BracketCapture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BracketCapture")
+            .field("frames", &self.frames.len())
+            .field("unused", &self.unused.len())
+            .field("discarded", &self.discarded)
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+pub enum BracketError {
+    /// Bracketing needs at least one request per setting.
+    NotEnoughRequests {
+        required: usize,
+        available: usize,
+    },
+
+    /// A setting wasn't reported as being in effect within the allowed number
+    /// of frames.
+    ///
+    /// 'capture' contains the frames captured for the earlier settings and
+    /// all other requests (which can be enqueued again) in 'unused'.
+    Timeout {
+        index: usize,
+        capture: BracketCapture,
+    },
+
+    /// A request was cancelled (e.g. because the camera was stopped).
+    ///
+    /// 'capture' is the same as for Timeout.
+    RequestCancelled {
+        capture: BracketCapture,
+    },
+
+    Camera(Error),
+}
+
+impl fmt::Display for BracketError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BracketError::NotEnoughRequests {
+                required,
+                available,
+            } => write!(
+                f,
+                "bracketing needs {} requests but only {} were given",
+                required, available
+            ),
+            BracketError::Timeout { index, .. } => {
+                write!(f, "bracket setting {} never took effect", index)
+            }
+            BracketError::RequestCancelled { .. } => write!(f, "request was cancelled"),
+            BracketError::Camera(e) => write!(f, "failed to enqueue request: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for BracketError {}
+
+impl From<Error> for BracketError {
+    fn from(e: Error) -> Self {
+        BracketError::Camera(e)
+    }
+}
+
+/// Captures a sequence of frames with different exposure settings.
+///
+/// Frames are only accepted once their metadata shows that the requested
+/// setting is in effect, so the result is correct even if frames are dropped
+/// or the camera's control latency varies.
+pub struct Bracket {
+    settings: Vec<BracketSetting>,
+    latency: u32,
+
+    /// Number of frames to wait for each setting before giving up.
+    max_frames: u32,
+}
+
+impl Bracket {
+    pub fn new(camera: &Camera, settings: Vec<BracketSetting>) -> Self {
+        let latency = camera.max_latency();
+
+        Self {
+            settings,
+            latency,
+            max_frames: 4 * (latency + 1),
+        }
+    }
+
+    /// Sets the number of frames to wait for each setting to take effect
+    /// before capture() fails with BracketError::Timeout.
+    pub fn set_max_frames(&mut self, max_frames: u32) {
+        self.max_frames = max_frames;
+    }
+
+    /// Runs the bracket on a running camera.
+    ///
+    /// 'requests' should have buffers attached and must contain at least one
+    /// request per setting. All of them are kept in flight to avoid stalling
+    /// the camera. Once complete, AE is left in the state of the last setting.
+    pub async fn capture(&self, requests: Vec<NewRequest>) -> Result<BracketCapture, BracketError> {
+        if requests.len() < self.settings.len() {
+            return Err(BracketError::NotEnoughRequests {
+                required: self.settings.len(),
+                available: requests.len(),
+            });
+        }
+
+        let mut frames = vec![];
+        let mut unused = vec![];
+        let mut progress = Progress::new(self.settings.len(), self.max_frames);
+
+        let mut in_flight: VecDeque<(usize, PendingRequest)> = VecDeque::new();
+        for mut request in requests {
+            if let Some(setting) = self.settings.first() {
+                apply_setting(&mut request, setting);
+            }
+            in_flight.push_back((0, request.enqueue()?));
+        }
+
+        while let Some((request_index, pending)) = in_flight.pop_front() {
+            let completed = pending.await;
+
+            let step = if completed.status() == RequestStatus::RequestComplete {
+                let report = BracketReport::from_metadata(&completed);
+                progress.step(request_index, completed.sequence(), |index, age| {
+                    self.in_effect(&self.settings[index], &report, age)
+                })
+            } else {
+                Step::Cancelled
+            };
+
+            match step {
+                Step::Accept(index) => frames.push(BracketFrame {
+                    index,
+                    setting: self.settings[index],
+                    request: completed,
+                }),
+                Step::Unused => unused.push(reuse(completed)),
+                Step::Retry(index) => {
+                    let mut request = reuse(completed);
+                    apply_setting(&mut request, &self.settings[index]);
+                    in_flight.push_back((index, request.enqueue()?));
+                }
+                Step::Timeout(_) | Step::Cancelled => {
+                    // Wait for the rest to drain before bailing so that no
+                    // requests are dropped while still owned by libcamera.
+                    unused.push(reuse(completed));
+                    for (_, pending) in in_flight {
+                        unused.push(reuse(pending.await));
+                    }
+
+                    let capture = BracketCapture {
+                        frames,
+                        unused,
+                        discarded: progress.discarded,
+                    };
+                    return Err(match step {
+                        Step::Timeout(index) => BracketError::Timeout { index, capture },
+                        _ => BracketError::RequestCancelled { capture },
+                    });
+                }
+            }
+        }
+
+        Ok(BracketCapture {
+            frames,
+            unused,
+            discarded: progress.discarded,
+        })
+    }
+
+    /// Checks if a completed request which was sent with 'setting' shows it
+    /// being in effect.
+    ///
+    /// 'age' is the number of requests since the first one with this setting
+    /// completed.
+    fn in_effect(&self, setting: &BracketSetting, report: &BracketReport, age: u32) -> bool {
+        match *setting {
+            BracketSetting::ExposureValue(ev) => {
+                // Not all cameras report the EV in use, so also wait until AE
+                // has had time to react to it and has converged.
+                let ev_matches = report
+                    .exposure_value
+                    .map_or(age >= self.latency, |v| (v - ev).abs() <= EV_TOLERANCE);
+                let converged = report.ae_locked.unwrap_or(true);
+
+                ev_matches && converged
+            }
+            BracketSetting::Manual {
+                exposure_time,
+                analogue_gain,
+            } => match (report.exposure_time, report.analogue_gain) {
+                (Some(reported_time), Some(reported_gain)) => settled(
+                    &ExposureSettings {
+                        exposure_time,
+                        analogue_gain,
+                        colour_gains: None,
+                    },
+                    &ExposureSettings {
+                        exposure_time: reported_time,
+                        analogue_gain: reported_gain,
+                        colour_gains: None,
+                    },
+                ),
+                // Without metadata, assume it has taken effect after the
+                // camera's latency.
+                _ => age >= self.latency,
+            },
+        }
+    }
+}
+
+/// Values from the metadata of a completed request used to decide if a
+/// setting is in effect.
+#[derive(Debug, Default, Clone, Copy)]
+struct BracketReport {
+    exposure_value: Option<f32>,
+    ae_locked: Option<bool>,
+    exposure_time: Option<Duration>,
+    analogue_gain: Option<f32>,
+}
+
+impl BracketReport {
+    fn from_metadata(request: &Request) -> Self {
+        let metadata = request.metadata();
+
+        Self {
+            exposure_value: metadata.get(controls::ExposureValue),
+            ae_locked: metadata.get(controls::AeLocked),
+            exposure_time: metadata
+                .get(controls::ExposureTime)
+                .map(|v| Duration::from_micros(v.max(0) as u64)),
+            analogue_gain: metadata.get(controls::AnalogueGain),
+        }
+    }
+}
+
+/// What Bracket::capture() does with a completed request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    /// The request is the frame for the setting with the given index.
+    Accept(usize),
+
+    /// All settings have already been captured.
+    Unused,
+
+    /// The request is discarded and enqueued again with the setting with the
+    /// given index.
+    Retry(usize),
+
+    /// The setting with the given index didn't take effect in time.
+    Timeout(usize),
+
+    Cancelled,
+}
+
+/// Tracks which setting of a bracket is being captured.
+struct Progress {
+    settings: usize,
+    max_frames: u32,
+
+    /// Index of the setting currently being captured.
+    index: usize,
+
+    /// Sequence number of the first completed request which carried the
+    /// current setting, and the number of frames waited for it so far.
+    first_sequence: Option<u32>,
+    waited: u32,
+
+    discarded: usize,
+}
+
+impl Progress {
+    fn new(settings: usize, max_frames: u32) -> Self {
+        Self {
+            settings,
+            max_frames,
+            index: 0,
+            first_sequence: None,
+            waited: 0,
+            discarded: 0,
+        }
+    }
+
+    /// Decides what to do with a request which completed successfully.
+    ///
+    /// 'request_index' is the index of the setting the request was sent
+    /// with. 'in_effect' is called with the index of the current setting and
+    /// the number of requests since the first one with it completed.
+    fn step(
+        &mut self,
+        request_index: usize,
+        sequence: u32,
+        in_effect: impl FnOnce(usize, u32) -> bool,
+    ) -> Step {
+        if self.index >= self.settings {
+            return Step::Unused;
+        }
+
+        let index = self.index;
+        if request_index == index {
+            let first = *self.first_sequence.get_or_insert(sequence);
+            if in_effect(index, sequence.wrapping_sub(first)) {
+                self.index += 1;
+                self.first_sequence = None;
+                self.waited = 0;
+                return Step::Accept(index);
+            }
+
+            self.waited += 1;
+        }
+
+        self.discarded += 1;
+        if self.waited > self.max_frames {
+            return Step::Timeout(index);
+        }
+
+        Step::Retry(index)
+    }
+}
+
+/// Reuses a completed request without the controls it was sent with, so that
+/// e.g. the ExposureTime of a Manual setting isn't sent along with the next
+/// setting.
+fn reuse(request: CompletedRequest) -> NewRequest {
+    let mut request = request.reuse();
+    request.controls_mut().clear();
+    request
+}
+
+fn apply_setting(request: &mut Request, setting: &BracketSetting) {
+    let list = request.controls_mut();
+
+    match *setting {
+        BracketSetting::ExposureValue(ev) => {
+            list.set(controls::AeEnable, true);
+            list.set(controls::ExposureValue, ev);
+        }
+        BracketSetting::Manual {
+            exposure_time,
+            analogue_gain,
+        } => {
+            list.set(controls::AeEnable, false);
+            list.set(
+                controls::ExposureTime,
+                exposure_time.as_micros().min(i32::MAX as u128) as i32,
+            );
+            list.set(controls::AnalogueGain, analogue_gain);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bracket(latency: u32) -> Bracket {
+        Bracket {
+            settings: vec![],
+            latency,
+            max_frames: 4 * (latency + 1),
+        }
+    }
+
+    /// Feeds completed requests, given as (setting index the request was sent
+    /// with, whether the setting is in effect), with consecutive sequence
+    /// numbers.
+    fn run(progress: &mut Progress, requests: &[(usize, bool)]) -> Vec<Step> {
+        requests
+            .iter()
+            .enumerate()
+            .map(|(sequence, &(request_index, in_effect))| {
+                progress.step(request_index, sequence as u32, |index, _| {
+                    assert_eq!(index, request_index);
+                    in_effect
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn accepts_each_setting_in_turn() {
+        let mut progress = Progress::new(2, 4);
+
+        let steps = run(
+            &mut progress,
+            &[(0, true), (0, false), (1, true), (1, false)],
+        );
+        assert_eq!(
+            steps,
+            [
+                Step::Accept(0),
+                Step::Retry(1),
+                Step::Accept(1),
+                Step::Unused
+            ]
+        );
+        assert_eq!(progress.discarded, 1);
+    }
+
+    #[test]
+    fn discards_frames_until_setting_takes_effect() {
+        let mut progress = Progress::new(1, 4);
+
+        let steps = run(&mut progress, &[(0, false), (0, false), (0, true)]);
+        assert_eq!(steps, [Step::Retry(0), Step::Retry(0), Step::Accept(0)]);
+        assert_eq!(progress.discarded, 2);
+    }
+
+    #[test]
+    fn requests_with_an_earlier_setting_are_retried() {
+        let mut progress = Progress::new(2, 1);
+        assert_eq!(progress.step(0, 0, |_, _| true), Step::Accept(0));
+
+        // Requests still carrying setting 0 don't count towards the timeout
+        // and aren't checked.
+        for sequence in 1..4 {
+            let step = progress.step(0, sequence, |_, _| panic!("not the current setting"));
+            assert_eq!(step, Step::Retry(1));
+        }
+        assert_eq!(progress.step(1, 4, |_, _| true), Step::Accept(1));
+        assert_eq!(progress.discarded, 3);
+    }
+
+    #[test]
+    fn age_counts_from_first_request_with_setting() {
+        let mut progress = Progress::new(2, 8);
+        assert_eq!(progress.step(0, 10, |_, age| age == 0), Step::Accept(0));
+
+        let mut ages = vec![];
+        for sequence in [11, 12, 14] {
+            progress.step(1, sequence, |_, age| {
+                ages.push(age);
+                false
+            });
+        }
+        assert_eq!(ages, [0, 1, 3]);
+    }
+
+    #[test]
+    fn times_out_after_max_frames() {
+        let mut progress = Progress::new(2, 2);
+        assert_eq!(progress.step(0, 0, |_, _| true), Step::Accept(0));
+
+        let steps = run(&mut progress, &[(1, false), (1, false), (1, false)]);
+        assert_eq!(steps, [Step::Retry(1), Step::Retry(1), Step::Timeout(1)]);
+    }
+
+    #[test]
+    fn exposure_value_in_effect() {
+        let bracket = bracket(2);
+        let setting = BracketSetting::ExposureValue(1.0);
+
+        let mut report = BracketReport {
+            exposure_value: Some(0.0),
+            ..Default::default()
+        };
+        assert!(!bracket.in_effect(&setting, &report, 5));
+
+        report.exposure_value = Some(1.02);
+        assert!(bracket.in_effect(&setting, &report, 0));
+
+        report.ae_locked = Some(false);
+        assert!(!bracket.in_effect(&setting, &report, 0));
+
+        // Without a reported EV, wait for the latency.
+        let report = BracketReport::default();
+        assert!(!bracket.in_effect(&setting, &report, 1));
+        assert!(bracket.in_effect(&setting, &report, 2));
+    }
+
+    #[test]
+    fn manual_in_effect() {
+        let bracket = bracket(2);
+        let setting = BracketSetting::Manual {
+            exposure_time: Duration::from_millis(10),
+            analogue_gain: 2.0,
+        };
+
+        let mut report = BracketReport {
+            exposure_time: Some(Duration::from_micros(10_050)),
+            analogue_gain: Some(2.0),
+            ..Default::default()
+        };
+        assert!(bracket.in_effect(&setting, &report, 0));
+
+        report.analogue_gain = Some(1.0);
+        assert!(!bracket.in_effect(&setting, &report, 5));
+
+        let report = BracketReport::default();
+        assert!(!bracket.in_effect(&setting, &report, 1));
+        assert!(bracket.in_effect(&setting, &report, 2));
+    }
+}
@@ -1,6 +1,6 @@
 use std::cell::RefCell;
 use std::collections::HashMap;
-use std::ops::Deref;
+use std::ops::{Deref, RangeInclusive};
 use std::pin::Pin;
 use std::sync::Arc;
 use std::sync::Mutex;
@@ -13,14 +13,23 @@ use crate::camera_configuration::*;
 use crate::camera_manager::CameraManager;
 use crate::control_info_map::ControlInfoMap;
 use crate::control_list::ControlList;
+use crate::control_value::{ControlPrimitiveValue, ControlValue};
+use crate::controls;
 use crate::errors::*;
 use crate::ffi;
 use crate::frame_buffer_allocator::FrameBufferAllocator;
+use crate::frame_rate::FrameRateError;
+use crate::geometry::Rectangle;
 use crate::request::{NewRequest, Request};
+use crate::sensor_configuration::SensorMode;
 use crate::stream::Stream;
 
 pub use crate::ffi::StreamRole;
 
+/// Number of frames it is assumed to take for controls to take effect if the
+/// camera doesn't advertise MaxLatency.
+pub const DEFAULT_MAX_LATENCY: u32 = 4;
+
 // TODO: On drop, do release/stop?
 
 pub struct Camera {
@@ -39,6 +48,12 @@ struct CameraState {
     ///
     /// The key is each request's ID (stored in the cookie).
     pending_requests: HashMap<u64, Arc<Mutex<RequestQueueEntry>>>,
+
+    /// FrameDurationLimits value to be added to the next enqueued request.
+    frame_duration_limits: Option<[i64; 2]>,
+
+    /// ScalerCrop value to be added to the next enqueued request.
+    scaler_crop: Option<Rectangle>,
 }
 
 pub(crate) struct RequestQueueEntry {
@@ -58,6 +73,8 @@ impl Camera {
             raw,
             state: Arc::new(Mutex::new(CameraState {
                 pending_requests: HashMap::new(),
+                frame_duration_limits: None,
+                scaler_crop: None,
             })),
         }
     }
@@ -87,6 +104,17 @@ impl Camera {
         self.raw.properties().into()
     }
 
+    /// Gets the maximum number of frames it may take for controls set on a
+    /// request to take effect, as advertised by the camera via the MaxLatency
+    /// control. Falls back to DEFAULT_MAX_LATENCY if it isn't advertised.
+    pub fn max_latency(&self) -> u32 {
+        let info = self.controls().get(controls::MaxLatency);
+        match info.map(|info| info.max()) {
+            Some(ControlValue::Primitive(ControlPrimitiveValue::Int32(v))) if v >= 0 => v as u32,
+            _ => DEFAULT_MAX_LATENCY,
+        }
+    }
+
     fn get_mut(&self) -> Pin<&mut ffi::Camera> {
         unsafe {
             Pin::<&mut ffi::Camera>::new_unchecked(
@@ -104,7 +132,7 @@ impl Camera {
         ok_if_zero(self.get_mut().release())
     }
 
-    fn generate_configuration(
+    pub(crate) fn generate_configuration(
         self: &Arc<Self>,
         stream_roles: &[StreamRole],
     ) -> Option<CameraConfiguration> {
@@ -113,7 +141,22 @@ impl Camera {
             return None;
         }
 
-        Some(CameraConfiguration::new(self.clone(), raw))
+        Some(CameraConfiguration::new(self.clone(), raw, stream_roles))
+    }
+
+    /// Builds a copy of a configuration without the stream configuration at
+    /// the given index.
+    pub(crate) fn configuration_without(
+        &self,
+        config: &ffi::CameraConfiguration,
+        index: usize,
+    ) -> Option<UniquePtr<ffi::CameraConfiguration>> {
+        let raw = ffi::camera_configuration_without(self.get_mut(), config, index as u32);
+        if raw.is_null() {
+            return None;
+        }
+
+        Some(raw)
     }
 
     // Only allowed for Configured and Running cameras
@@ -126,6 +169,51 @@ impl Camera {
         NewRequest::new(Request::new(self.clone(), raw))
     }
 
+    fn set_frame_rate(
+        &self,
+        range: RangeInclusive<f64>,
+    ) -> std::result::Result<(), FrameRateError> {
+        let limits = self.frame_rate_to_durations(range)?;
+        self.state.lock().unwrap().frame_duration_limits = Some(limits);
+        Ok(())
+    }
+
+    /// Fixes the frame duration (in microseconds) from the next enqueued
+    /// request onwards. Returns false if the duration isn't supported.
+    pub(crate) fn set_frame_duration(&self, duration: i64) -> bool {
+        match self.frame_duration_limits() {
+            Some((min, max)) if (min..=max).contains(&duration) => {
+                self.state.lock().unwrap().frame_duration_limits = Some([duration, duration]);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Sets the ScalerCrop of the next enqueued request.
+    pub(crate) fn set_scaler_crop(&self, crop: Rectangle) {
+        self.state.lock().unwrap().scaler_crop = Some(crop);
+    }
+
+    /// Adds controls set through the camera (rather than on individual
+    /// requests) to a request which is about to be enqueued.
+    pub(crate) fn apply_pending_controls(&self, request: &mut Request) {
+        let (limits, crop) = {
+            let mut state = self.state.lock().unwrap();
+            (state.frame_duration_limits.take(), state.scaler_crop.take())
+        };
+
+        if let Some(limits) = limits {
+            request
+                .controls_mut()
+                .set(controls::FrameDurationLimits, limits);
+        }
+
+        if let Some(crop) = crop {
+            request.controls_mut().set(controls::ScalerCrop, crop);
+        }
+    }
+
     pub(crate) fn queue_request(
         &self,
         request: &mut Request,
@@ -208,6 +296,23 @@ impl AcquiredCamera {
         self.camera.generate_configuration(stream_roles)
     }
 
+    /// Lists the modes supported by the camera's sensor. Returns an empty
+    /// list if the camera doesn't support Raw streams.
+    pub fn sensor_modes(&self) -> Vec<SensorMode> {
+        match self.camera.generate_configuration(&[StreamRole::Raw]) {
+            Some(config) if config.stream_configs_len() > 0 => {
+                SensorMode::from_stream_formats(config.stream_config(0).formats())
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Starts building a configuration from individually specified streams
+    /// (see CameraConfigurationBuilder).
+    pub fn configure_streams(&self) -> CameraConfigurationBuilder<'_> {
+        CameraConfigurationBuilder::new(&self.camera)
+    }
+
     pub fn configure(self, config: &mut CameraConfiguration) -> Result<ConfiguredCamera> {
         ok_if_zero(unsafe {
             self.camera
@@ -247,8 +352,21 @@ impl ConfiguredCamera {
         // Use cookie to identify request in CameraState::pending_requests.
         // Request sequence number is updated asynchronously after call to FFI
         // in Camera::queue_event() so it cannot be used as an index.
-        (*self.request_id.borrow_mut()).wrapping_add(1);
-        self.camera.create_request(*self.request_id.borrow())
+        self.camera.create_request(next_cookie(&self.request_id))
+    }
+
+    /// Limits the frame rate (in frames per second) the camera will run at
+    /// once started. A fixed rate can be requested with a range containing a
+    /// single value.
+    ///
+    /// The range is checked against the camera's FrameDurationLimits and is
+    /// applied with the first request enqueued after the camera is started.
+    /// The rate actually achieved is reported by FrameInfo::frame_rate().
+    pub fn set_frame_rate(
+        &self,
+        range: RangeInclusive<f64>,
+    ) -> std::result::Result<(), FrameRateError> {
+        self.camera.set_frame_rate(range)
     }
 
     pub fn start(self) -> Result<RunningCamera> {
@@ -306,5 +424,39 @@ impl RunningCamera {
         }
     }
 
+    /// Changes the range of frame rates (in frames per second) the camera may
+    /// run at. This takes effect with the next request which is enqueued.
+    ///
+    /// See ConfiguredCamera::set_frame_rate().
+    pub fn set_frame_rate(
+        &self,
+        range: RangeInclusive<f64>,
+    ) -> std::result::Result<(), FrameRateError> {
+        self.camera.set_frame_rate(range)
+    }
+
     // TODO: Verify that when stopped, all requests get marked as cancelled.
 }
+
+/// Advances the counter used to give each request created by a camera a
+/// distinct cookie and returns the new value.
+fn next_cookie(counter: &RefCell<u64>) -> u64 {
+    let mut cookie = counter.borrow_mut();
+    *cookie = cookie.wrapping_add(1);
+    *cookie
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_cookies_are_distinct() {
+        let counter = RefCell::new(0);
+        assert_eq!(next_cookie(&counter), 1);
+        assert_eq!(next_cookie(&counter), 2);
+
+        let counter = RefCell::new(u64::MAX);
+        assert_eq!(next_cookie(&counter), 0);
+    }
+}
@@ -1,31 +1,52 @@
+use std::fmt;
 use std::sync::Arc;
 
 use cxx::UniquePtr;
 
-use crate::camera::Camera;
+use crate::camera::{Camera, StreamRole};
+use crate::errors::*;
 use crate::ffi;
-use crate::stream_configuration::StreamConfigurationOpaque;
+use crate::pixel_format::PixelFormat;
+use crate::sensor_configuration::SensorConfiguration;
+use crate::stream_configuration::{Size, StreamConfigurationOpaque};
+use crate::transform::{Orientation, Transform};
 
 pub use crate::ffi::CameraConfigurationStatus;
 
 pub struct CameraConfiguration {
     /// Used to ensure that the ffi::CameraConfiguration outlives and
     /// ffi::Camera.
-    #[allow(unused)]
     camera: Arc<Camera>,
 
     pub(crate) raw: UniquePtr<ffi::CameraConfiguration>,
+
+    /// Role used to generate each stream configuration. None for
+    /// configurations added with add_stream_config().
+    roles: Vec<Option<StreamRole>>,
 }
 
 impl CameraConfiguration {
-    pub(crate) fn new(camera: Arc<Camera>, raw: UniquePtr<ffi::CameraConfiguration>) -> Self {
-        Self { camera, raw }
+    pub(crate) fn new(
+        camera: Arc<Camera>,
+        raw: UniquePtr<ffi::CameraConfiguration>,
+        roles: &[StreamRole],
+    ) -> Self {
+        let mut roles = roles.iter().cloned().map(Some).collect::<Vec<_>>();
+        roles.resize(raw.as_ref().unwrap().size(), None);
+
+        Self { camera, raw, roles }
     }
 
     pub fn stream_configs_len(&self) -> usize {
         self.raw.as_ref().unwrap().size()
     }
 
+    /// Gets the role which was used to generate the stream configuration at
+    /// the given index.
+    pub fn stream_role(&self, index: usize) -> Option<StreamRole> {
+        self.roles.get(index).cloned().flatten()
+    }
+
     pub fn stream_config<'a>(&'a self, index: usize) -> &'a StreamConfigurationOpaque {
         unsafe { core::mem::transmute(self.raw.as_ref().unwrap().at(index as u32)) }
     }
@@ -34,7 +55,468 @@ impl CameraConfiguration {
         unsafe { core::mem::transmute(self.raw.as_mut().unwrap().at_mut(index as u32)) }
     }
 
+    /// Appends a new stream configuration with all fields unset. Its size and
+    /// pixel format must be set before the configuration is validated.
+    pub fn add_stream_config<'a>(&'a mut self) -> &'a mut StreamConfigurationOpaque {
+        ffi::camera_configuration_add(self.raw.as_mut().unwrap());
+        self.roles.push(None);
+        let index = self.stream_configs_len() - 1;
+        self.stream_config_mut(index)
+    }
+
+    /// Removes the stream configuration at the given index. Later
+    /// configurations are shifted down by one.
+    pub fn remove_stream_config(&mut self, index: usize) -> Result<()> {
+        if index >= self.stream_configs_len() {
+            return Err(Error::EINVAL);
+        }
+
+        self.raw = self
+            .camera
+            .configuration_without(self.raw.as_ref().unwrap(), index)
+            .ok_or(Error::ENOMEM)?;
+        self.roles.remove(index);
+        Ok(())
+    }
+
+    /// Requested sensor mode. If None, the pipeline handler picks a mode
+    /// based on the stream configurations.
+    pub fn sensor_config(&self) -> Option<SensorConfiguration> {
+        let raw = self.raw.as_ref().unwrap();
+        if ffi::camera_configuration_has_sensor_config(raw) {
+            Some(ffi::camera_configuration_sensor_config(raw))
+        } else {
+            None
+        }
+    }
+
+    /// NOTE: validate() will fail if the sensor doesn't support the requested
+    /// mode. See AcquiredCamera::sensor_modes().
+    pub fn set_sensor_config(&mut self, value: Option<SensorConfiguration>) {
+        match value {
+            Some(value) => {
+                ffi::camera_configuration_set_sensor_config(self.raw.as_mut().unwrap(), &value)
+            }
+            None => ffi::camera_configuration_clear_sensor_config(self.raw.as_mut().unwrap()),
+        }
+    }
+
+    /// Desired orientation of the images produced by the camera relative to
+    /// the scene, taking into account the camera's mounting rotation.
+    pub fn orientation(&self) -> Orientation {
+        Orientation::from_exif(ffi::camera_configuration_orientation(
+            self.raw.as_ref().unwrap(),
+        ))
+        .unwrap_or_default()
+    }
+
+    /// NOTE: validate() will adjust the orientation if the camera can't
+    /// produce it (e.g. if the sensor doesn't support transposition).
+    pub fn set_orientation(&mut self, orientation: Orientation) {
+        ffi::camera_configuration_set_orientation(self.raw.as_mut().unwrap(), orientation.to_exif())
+    }
+
+    /// Sets the orientation to the result of applying a transform to an
+    /// upright image (e.g. Transform::HFLIP * Transform::VFLIP for a camera
+    /// mounted upside down).
+    pub fn set_transform(&mut self, transform: Transform) {
+        self.set_orientation(Orientation::Rotate0 * transform)
+    }
+
     pub fn validate(&mut self) -> CameraConfigurationStatus {
         self.raw.as_mut().unwrap().validate()
     }
 }
+
+/// Builds and validates a CameraConfiguration from a list of requested streams.
+///
+/// Created with AcquiredCamera::configure_streams(). Fields which aren't
+/// explicitly requested keep the defaults chosen by libcamera for each stream
+/// role.
+pub struct CameraConfigurationBuilder<'a> {
+    camera: &'a Arc<Camera>,
+    roles: Vec<StreamRole>,
+    streams: Vec<StreamRequest>,
+    strict: bool,
+}
+
+/// Values requested for a single stream. Unset fields keep libcamera's
+/// defaults.
+#[derive(Default)]
+pub(crate) struct StreamRequest {
+    pub size: Option<Size>,
+    pub pixel_format: Option<PixelFormat>,
+    pub stride: Option<u32>,
+    pub buffer_count: Option<u32>,
+}
+
+impl StreamRequest {
+    fn apply(&self, stream_config: &mut StreamConfigurationOpaque) {
+        if let Some(size) = self.size {
+            stream_config.set_size(size);
+        }
+        if let Some(pixel_format) = self.pixel_format {
+            stream_config.set_pixel_format(pixel_format);
+        }
+        if let Some(stride) = self.stride {
+            stream_config.set_stride(stride);
+        }
+        if let Some(buffer_count) = self.buffer_count {
+            stream_config.set_buffer_count(buffer_count);
+        }
+    }
+
+    /// Compares the requested values to a validated stream configuration.
+    fn diff(
+        &self,
+        stream_index: usize,
+        stream_config: &StreamConfigurationOpaque,
+        adjustments: &mut Vec<Adjustment>,
+    ) {
+        let mut push = |field| {
+            adjustments.push(Adjustment {
+                stream_index: Some(stream_index),
+                field,
+            })
+        };
+
+        if let Some(requested) = self.size {
+            let actual = stream_config.size();
+            if requested.width != actual.width || requested.height != actual.height {
+                push(AdjustedField::Size { requested, actual });
+            }
+        }
+
+        if let Some(requested) = self.pixel_format {
+            let actual = stream_config.pixel_format();
+            if requested != actual {
+                push(AdjustedField::PixelFormat { requested, actual });
+            }
+        }
+
+        if let Some(requested) = self.stride {
+            let actual = stream_config.stride();
+            if requested != actual {
+                push(AdjustedField::Stride { requested, actual });
+            }
+        }
+
+        if let Some(requested) = self.buffer_count {
+            let actual = stream_config.buffer_count();
+            if requested != actual {
+                push(AdjustedField::BufferCount { requested, actual });
+            }
+        }
+    }
+}
+
+/// Applies the requested values to each stream in a generated configuration,
+/// validates it and returns the requested values which libcamera changed.
+pub(crate) fn apply_stream_requests(
+    config: &mut CameraConfiguration,
+    streams: &[StreamRequest],
+    orientation: Option<Orientation>,
+) -> std::result::Result<(CameraConfigurationStatus, Vec<Adjustment>), ConfigurationError> {
+    if config.stream_configs_len() != streams.len() {
+        return Err(ConfigurationError::StreamCountMismatch {
+            expected: streams.len(),
+            actual: config.stream_configs_len(),
+        });
+    }
+
+    for (i, request) in streams.iter().enumerate() {
+        request.apply(config.stream_config_mut(i));
+    }
+
+    if let Some(orientation) = orientation {
+        config.set_orientation(orientation);
+    }
+
+    let status = config.validate();
+    if status == CameraConfigurationStatus::Invalid {
+        return Err(ConfigurationError::Invalid);
+    }
+
+    let mut adjustments = vec![];
+    for (i, request) in streams.iter().enumerate() {
+        request.diff(i, config.stream_config(i), &mut adjustments);
+    }
+
+    if let Some(requested) = orientation {
+        let actual = config.orientation();
+        if requested != actual {
+            // Orientation applies to all streams.
+            adjustments.push(Adjustment {
+                stream_index: None,
+                field: AdjustedField::Orientation { requested, actual },
+            });
+        }
+    }
+
+    Ok((status, adjustments))
+}
+
+impl<'a> CameraConfigurationBuilder<'a> {
+    pub(crate) fn new(camera: &'a Arc<Camera>) -> Self {
+        Self {
+            camera,
+            roles: vec![],
+            streams: vec![],
+            strict: false,
+        }
+    }
+
+    /// Adds a new stream. Subsequent calls to size(), format(), etc. apply to
+    /// this stream.
+    pub fn add(mut self, role: StreamRole) -> Self {
+        self.roles.push(role);
+        self.streams.push(StreamRequest::default());
+        self
+    }
+
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.last_stream().size = Some(Size { width, height });
+        self
+    }
+
+    pub fn format(mut self, pixel_format: PixelFormat) -> Self {
+        self.last_stream().pixel_format = Some(pixel_format);
+        self
+    }
+
+    /// Requests a specific line stride in bytes. A value of 0 lets libcamera
+    /// pick the stride.
+    pub fn stride(mut self, stride: u32) -> Self {
+        self.last_stream().stride = if stride != 0 { Some(stride) } else { None };
+        self
+    }
+
+    pub fn buffers(mut self, count: u32) -> Self {
+        self.last_stream().buffer_count = Some(count);
+        self
+    }
+
+    /// If true, build() will fail if libcamera had to adjust the
+    /// configuration instead of returning the adjusted configuration. This
+    /// includes changes to fields which weren't explicitly requested.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    fn last_stream(&mut self) -> &mut StreamRequest {
+        self.streams
+            .last_mut()
+            .expect("add() must be called before configuring a stream")
+    }
+
+    /// Generates and validates the configuration.
+    ///
+    /// On success, the returned configuration can be passed to
+    /// AcquiredCamera::configure().
+    pub fn build(self) -> std::result::Result<ValidatedConfiguration, ConfigurationError> {
+        if self.streams.is_empty() {
+            return Err(ConfigurationError::NoStreams);
+        }
+
+        let mut config = self
+            .camera
+            .generate_configuration(&self.roles)
+            .ok_or(ConfigurationError::UnsupportedRoles)?;
+
+        if config.stream_configs_len() != self.streams.len() {
+            return Err(ConfigurationError::UnsupportedRoles);
+        }
+
+        let (status, adjustments) = apply_stream_requests(&mut config, &self.streams, None)?;
+
+        if self.strict {
+            check_strict(status, &adjustments)?;
+        }
+
+        Ok(ValidatedConfiguration {
+            config,
+            status,
+            adjustments,
+        })
+    }
+}
+
+/// Fails if libcamera changed anything, including fields which aren't
+/// tracked as adjustments.
+fn check_strict(
+    status: CameraConfigurationStatus,
+    adjustments: &[Adjustment],
+) -> std::result::Result<(), ConfigurationError> {
+    if status == CameraConfigurationStatus::Adjusted || !adjustments.is_empty() {
+        return Err(ConfigurationError::Adjusted(adjustments.to_vec()));
+    }
+
+    Ok(())
+}
+
+/// Result of successfully building a configuration.
+pub struct ValidatedConfiguration {
+    pub config: CameraConfiguration,
+
+    /// Either Valid or Adjusted. Note that libcamera may report Adjusted
+    /// because of changes to fields not tracked in 'adjustments'.
+    pub status: CameraConfigurationStatus,
+
+    /// Requested fields which libcamera changed during validation.
+    pub adjustments: Vec<Adjustment>,
+}
+
+/// A single field of a stream configuration which was changed by libcamera
+/// during validation.
+#[derive(Debug, Clone)]
+pub struct Adjustment {
+    /// Index of the stream in the order in which they were added to the
+    /// builder, or None for fields which apply to the whole configuration
+    /// (e.g. orientation).
+    pub stream_index: Option<usize>,
+
+    pub field: AdjustedField,
+}
+
+#[derive(Debug, Clone)]
+pub enum AdjustedField {
+    Size {
+        requested: Size,
+        actual: Size,
+    },
+    PixelFormat {
+        requested: PixelFormat,
+        actual: PixelFormat,
+    },
+    Stride {
+        requested: u32,
+        actual: u32,
+    },
+    BufferCount {
+        requested: u32,
+        actual: u32,
+    },
+    Orientation {
+        requested: Orientation,
+        actual: Orientation,
+    },
+}
+
+impl fmt::Display for Adjustment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.stream_index {
+            Some(index) => write!(f, "stream {}: ", index)?,
+            None => write!(f, "configuration: ")?,
+        }
+        match &self.field {
+            AdjustedField::Size { requested, actual } => write!(
+                f,
+                "size {}x{} -> {}x{}",
+                requested.width, requested.height, actual.width, actual.height
+            ),
+            AdjustedField::PixelFormat { requested, actual } => {
+                write!(f, "pixel format {:?} -> {:?}", requested, actual)
+            }
+            AdjustedField::Stride { requested, actual } => {
+                write!(f, "stride {} -> {}", requested, actual)
+            }
+            AdjustedField::BufferCount { requested, actual } => {
+                write!(f, "buffer count {} -> {}", requested, actual)
+            }
+            AdjustedField::Orientation { requested, actual } => {
+                write!(f, "orientation {:?} -> {:?}", requested, actual)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigurationError {
+    /// No streams were added to the builder.
+    NoStreams,
+
+    /// The camera can't provide streams for the requested combination of
+    /// roles.
+    UnsupportedRoles,
+
+    /// libcamera rejected the configuration.
+    Invalid,
+
+    /// A CameraConfigurationSpec contains a role which isn't recognized.
+    UnknownRole(String),
+
+    /// A stream in a CameraConfigurationSpec has no role.
+    MissingRole { stream_index: usize },
+
+    /// A CameraConfigurationSpec contains a pixel format which isn't
+    /// recognized.
+    UnknownPixelFormat(String),
+
+    /// The configuration doesn't have the same number of streams as was
+    /// requested.
+    StreamCountMismatch { expected: usize, actual: usize },
+
+    /// Only returned in strict mode: libcamera changed the configuration. The
+    /// list may be empty if only fields which aren't tracked were changed.
+    Adjusted(Vec<Adjustment>),
+}
+
+impl fmt::Display for ConfigurationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigurationError::NoStreams => write!(f, "No streams requested"),
+            ConfigurationError::UnsupportedRoles => {
+                write!(f, "Camera doesn't support the requested stream roles")
+            }
+            ConfigurationError::Invalid => write!(f, "Camera configuration is invalid"),
+            ConfigurationError::UnknownRole(role) => write!(f, "Unknown stream role: {}", role),
+            ConfigurationError::MissingRole { stream_index } => {
+                write!(f, "Stream {} has no role", stream_index)
+            }
+            ConfigurationError::UnknownPixelFormat(format) => {
+                write!(f, "Unknown pixel format: {}", format)
+            }
+            ConfigurationError::StreamCountMismatch { expected, actual } => write!(
+                f,
+                "Expected {} stream configurations but got {}",
+                expected, actual
+            ),
+            ConfigurationError::Adjusted(adjustments) => {
+                write!(f, "Camera configuration was adjusted")?;
+                for (i, adjustment) in adjustments.iter().enumerate() {
+                    write!(f, "{}{}", if i == 0 { ": " } else { ", " }, adjustment)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigurationError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strict_rejects_any_adjustment() {
+        assert!(check_strict(CameraConfigurationStatus::Valid, &[]).is_ok());
+
+        // libcamera may adjust fields which aren't tracked (e.g. the color
+        // space) without a corresponding Adjustment.
+        let err = check_strict(CameraConfigurationStatus::Adjusted, &[]).unwrap_err();
+        assert!(matches!(&err, ConfigurationError::Adjusted(a) if a.is_empty()));
+        assert_eq!(err.to_string(), "Camera configuration was adjusted");
+
+        let adjustment = Adjustment {
+            stream_index: Some(0),
+            field: AdjustedField::BufferCount {
+                requested: 2,
+                actual: 4,
+            },
+        };
+        let err = check_strict(CameraConfigurationStatus::Valid, &[adjustment]).unwrap_err();
+        assert!(matches!(&err, ConfigurationError::Adjusted(a) if a.len() == 1));
+    }
+}
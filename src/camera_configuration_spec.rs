@@ -0,0 +1,219 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::camera::StreamRole;
+use crate::camera_configuration::*;
+use crate::pixel_format::PixelFormat;
+use crate::stream_configuration::Size;
+use crate::transform::Orientation;
+
+/// Plain data description of a validated CameraConfiguration.
+///
+/// With the "serde" feature, this can be stored in a file and later used to
+/// configure another camera of the same model:
+///
+/// - Use roles() to generate a configuration with
+///   AcquiredCamera::generate_configuration().
+/// - Call apply() on the generated configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CameraConfigurationSpec {
+    pub orientation: Orientation,
+    pub streams: Vec<StreamConfigurationSpec>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StreamConfigurationSpec {
+    /// One of "Raw", "StillCapture", "VideoRecording" or "Viewfinder". None if
+    /// the stream wasn't generated from a role or the role isn't known to this
+    /// crate.
+    pub role: Option<String>,
+
+    pub width: u32,
+    pub height: u32,
+
+    /// libcamera's name for the pixel format (e.g. "NV12").
+    pub pixel_format: String,
+
+    /// Bytes per line. If 0, libcamera's default stride is kept.
+    pub stride: u32,
+
+    pub buffer_count: u32,
+}
+
+impl CameraConfigurationSpec {
+    /// Extracts the current values from a configuration. This should be
+    /// called after the configuration has been validated.
+    pub fn from_config(config: &CameraConfiguration) -> Self {
+        let streams = (0..config.stream_configs_len())
+            .map(|i| {
+                let stream_config = config.stream_config(i);
+                let size = stream_config.size();
+
+                StreamConfigurationSpec {
+                    role: config
+                        .stream_role(i)
+                        .and_then(role_name)
+                        .map(str::to_string),
+                    width: size.width,
+                    height: size.height,
+                    pixel_format: stream_config.pixel_format().to_string(),
+                    stride: stream_config.stride(),
+                    buffer_count: stream_config.buffer_count(),
+                }
+            })
+            .collect();
+
+        Self {
+            orientation: config.orientation(),
+            streams,
+        }
+    }
+
+    /// Gets the roles which should be passed to generate_configuration() to
+    /// create a configuration to which this spec can be applied.
+    pub fn roles(&self) -> Result<Vec<StreamRole>, ConfigurationError> {
+        self.streams
+            .iter()
+            .enumerate()
+            .map(|(stream_index, stream)| {
+                let name = stream
+                    .role
+                    .as_deref()
+                    .ok_or(ConfigurationError::MissingRole { stream_index })?;
+                parse_role(name).ok_or_else(|| ConfigurationError::UnknownRole(name.to_string()))
+            })
+            .collect()
+    }
+
+    /// Applies the spec to a configuration generated for this spec's roles
+    /// and validates it.
+    ///
+    /// Returns the list of fields which differ from the spec after
+    /// validation (e.g. because the camera doesn't support the stored size).
+    pub fn apply(
+        &self,
+        config: &mut CameraConfiguration,
+    ) -> Result<Vec<Adjustment>, ConfigurationError> {
+        let mut requests = vec![];
+        for stream in &self.streams {
+            let pixel_format = stream
+                .pixel_format
+                .parse::<PixelFormat>()
+                .map_err(|_| ConfigurationError::UnknownPixelFormat(stream.pixel_format.clone()))?;
+
+            requests.push(StreamRequest {
+                size: Some(Size {
+                    width: stream.width,
+                    height: stream.height,
+                }),
+                pixel_format: Some(pixel_format),
+                stride: if stream.stride != 0 {
+                    Some(stream.stride)
+                } else {
+                    None
+                },
+                buffer_count: Some(stream.buffer_count),
+            });
+        }
+
+        let (_, adjustments) = apply_stream_requests(config, &requests, Some(self.orientation))?;
+        Ok(adjustments)
+    }
+}
+
+/// Returns None for roles added to libcamera after this crate.
+fn role_name(role: StreamRole) -> Option<&'static str> {
+    Some(match role {
+        StreamRole::Raw => "Raw",
+        StreamRole::StillCapture => "StillCapture",
+        StreamRole::VideoRecording => "VideoRecording",
+        StreamRole::Viewfinder => "Viewfinder",
+        _ => return None,
+    })
+}
+
+fn parse_role(name: &str) -> Option<StreamRole> {
+    Some(match name {
+        "Raw" => StreamRole::Raw,
+        "StillCapture" => StreamRole::StillCapture,
+        "VideoRecording" => StreamRole::VideoRecording,
+        "Viewfinder" => StreamRole::Viewfinder,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec() -> CameraConfigurationSpec {
+        CameraConfigurationSpec {
+            orientation: Orientation::Rotate180,
+            streams: vec![
+                StreamConfigurationSpec {
+                    role: Some("Viewfinder".to_string()),
+                    width: 1280,
+                    height: 720,
+                    pixel_format: "NV12".to_string(),
+                    stride: 1280,
+                    buffer_count: 4,
+                },
+                StreamConfigurationSpec {
+                    role: None,
+                    width: 640,
+                    height: 480,
+                    pixel_format: "MJPEG".to_string(),
+                    stride: 0,
+                    buffer_count: 2,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn role_names_round_trip() {
+        for role in [
+            StreamRole::Raw,
+            StreamRole::StillCapture,
+            StreamRole::VideoRecording,
+            StreamRole::Viewfinder,
+        ] {
+            assert_eq!(parse_role(role_name(role).unwrap()), Some(role));
+        }
+        assert_eq!(parse_role("Unknown"), None);
+    }
+
+    #[test]
+    fn roles_require_known_role_for_every_stream() {
+        let mut spec = spec();
+        assert!(matches!(
+            spec.roles(),
+            Err(ConfigurationError::MissingRole { stream_index: 1 })
+        ));
+
+        spec.streams[1].role = Some("Thumbnail".to_string());
+        assert!(matches!(
+            spec.roles(),
+            Err(ConfigurationError::UnknownRole(role)) if role == "Thumbnail"
+        ));
+
+        spec.streams[1].role = Some("Raw".to_string());
+        assert_eq!(
+            spec.roles().unwrap(),
+            [StreamRole::Viewfinder, StreamRole::Raw]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let spec = spec();
+        let yaml = serde_yaml::to_string(&spec).unwrap();
+        assert_eq!(
+            serde_yaml::from_str::<CameraConfigurationSpec>(&yaml).unwrap(),
+            spec
+        );
+    }
+}
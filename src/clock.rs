@@ -0,0 +1,264 @@
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use nix::time::{clock_gettime, ClockId};
+
+use crate::frame_metadata::Timestamp;
+
+/// Number of times each clock offset is sampled when re-estimating. The
+/// sample read in the shortest time is used.
+const OFFSET_SAMPLES: usize = 3;
+
+/// System clocks which frame timestamps can be converted to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Clock {
+    /// CLOCK_BOOTTIME, which libcamera timestamps are in.
+    Boottime,
+
+    /// CLOCK_MONOTONIC, which stops while the system is suspended.
+    Monotonic,
+
+    /// CLOCK_REALTIME (wall clock time).
+    Realtime,
+}
+
+impl Clock {
+    fn id(&self) -> ClockId {
+        match self {
+            Clock::Boottime => ClockId::CLOCK_BOOTTIME,
+            Clock::Monotonic => ClockId::CLOCK_MONOTONIC,
+            Clock::Realtime => ClockId::CLOCK_REALTIME,
+        }
+    }
+
+    /// Reads the current time of the clock in nanoseconds.
+    pub fn now(&self) -> i128 {
+        // Reading the standard clocks can't fail on Linux.
+        let t = clock_gettime(self.id()).unwrap();
+        t.tv_sec() as i128 * 1_000_000_000 + t.tv_nsec() as i128
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Offsets {
+    /// CLOCK_MONOTONIC time at which the offsets were measured.
+    measured_at: i128,
+
+    /// Nanoseconds to add to a CLOCK_BOOTTIME time to get the time in each
+    /// clock.
+    monotonic: i128,
+    realtime: i128,
+}
+
+/// Converts timestamps between CLOCK_BOOTTIME and the other system clocks.
+///
+/// The offsets between clocks change when the system is suspended or the wall
+/// clock is adjusted, so they are re-measured whenever they are older than
+/// the refresh interval.
+#[derive(Debug)]
+pub struct ClockMapper {
+    offsets: Mutex<Option<Offsets>>,
+    refresh_interval: Duration,
+}
+
+/// Shared mapper used by the conversion methods on Timestamp.
+static DEFAULT_MAPPER: ClockMapper = ClockMapper::with_refresh_interval(Duration::from_secs(1));
+
+impl ClockMapper {
+    pub const fn new() -> Self {
+        Self::with_refresh_interval(Duration::from_secs(1))
+    }
+
+    pub const fn with_refresh_interval(refresh_interval: Duration) -> Self {
+        Self {
+            offsets: Mutex::new(None),
+            refresh_interval,
+        }
+    }
+
+    /// Gets the mapper shared by Timestamp::to_clock() and friends.
+    pub fn global() -> &'static ClockMapper {
+        &DEFAULT_MAPPER
+    }
+
+    /// Forces the clock offsets to be measured again.
+    pub fn refresh(&self) {
+        *self.offsets.lock().unwrap() = Some(measure_offsets());
+    }
+
+    fn offsets(&self) -> Offsets {
+        let mut offsets = self.offsets.lock().unwrap();
+
+        let now = Clock::Monotonic.now();
+        match *offsets {
+            Some(o) if now - o.measured_at < self.refresh_interval.as_nanos() as i128 => o,
+            _ => *offsets.insert(measure_offsets()),
+        }
+    }
+
+    fn offset(&self, clock: Clock) -> i128 {
+        match clock {
+            Clock::Boottime => 0,
+            Clock::Monotonic => self.offsets().monotonic,
+            Clock::Realtime => self.offsets().realtime,
+        }
+    }
+
+    /// Converts a CLOCK_BOOTTIME timestamp to nanoseconds in another clock.
+    ///
+    /// The result may be negative, e.g. for a boot time which is before the
+    /// system last resumed when converting to CLOCK_MONOTONIC.
+    pub fn to_clock(&self, timestamp: Timestamp, clock: Clock) -> i128 {
+        timestamp.as_nanos() as i128 + self.offset(clock)
+    }
+
+    /// Converts a time in nanoseconds in another clock to a CLOCK_BOOTTIME
+    /// timestamp. Returns None if the time is before boot.
+    pub fn from_clock(&self, nanos: i128, clock: Clock) -> Option<Timestamp> {
+        u64::try_from(nanos - self.offset(clock))
+            .ok()
+            .map(Timestamp::from_nanos)
+    }
+
+    /// Converts a timestamp to CLOCK_MONOTONIC (as the time since the clock
+    /// started). Returns None if it was captured before the system last
+    /// resumed from suspend.
+    pub fn to_monotonic(&self, timestamp: Timestamp) -> Option<Duration> {
+        u64::try_from(self.to_clock(timestamp, Clock::Monotonic))
+            .ok()
+            .map(Duration::from_nanos)
+    }
+
+    /// Converts a timestamp to wall clock time.
+    pub fn to_system_time(&self, timestamp: Timestamp) -> SystemTime {
+        let nanos = self.to_clock(timestamp, Clock::Realtime);
+        if nanos >= 0 {
+            UNIX_EPOCH + Duration::from_nanos(nanos as u64)
+        } else {
+            UNIX_EPOCH - Duration::from_nanos(nanos.unsigned_abs() as u64)
+        }
+    }
+
+    /// Converts a wall clock time to a CLOCK_BOOTTIME timestamp.
+    pub fn from_system_time(&self, time: SystemTime) -> Option<Timestamp> {
+        let nanos = match time.duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_nanos() as i128,
+            Err(e) => -(e.duration().as_nanos() as i128),
+        };
+
+        self.from_clock(nanos, Clock::Realtime)
+    }
+}
+
+impl Default for ClockMapper {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn measure_offsets() -> Offsets {
+    Offsets {
+        measured_at: Clock::Monotonic.now(),
+        monotonic: measure_offset(Clock::Monotonic),
+        realtime: measure_offset(Clock::Realtime),
+    }
+}
+
+/// Measures the offset from CLOCK_BOOTTIME to another clock by reading the
+/// other clock between two reads of CLOCK_BOOTTIME.
+fn measure_offset(clock: Clock) -> i128 {
+    let mut best: Option<(i128, i128)> = None;
+
+    for _ in 0..OFFSET_SAMPLES {
+        let before = Clock::Boottime.now();
+        let time = clock.now();
+        let after = Clock::Boottime.now();
+
+        let width = after - before;
+        let offset = time - (before + width / 2);
+
+        if best.map_or(true, |(best_width, _)| width < best_width) {
+            best = Some((width, offset));
+        }
+    }
+
+    best.unwrap().1
+}
+
+impl Timestamp {
+    /// Converts the timestamp to nanoseconds in another clock using the
+    /// global ClockMapper.
+    pub fn to_clock(&self, clock: Clock) -> i128 {
+        ClockMapper::global().to_clock(*self, clock)
+    }
+
+    /// Converts the timestamp to CLOCK_MONOTONIC using the global
+    /// ClockMapper.
+    pub fn to_monotonic(&self) -> Option<Duration> {
+        ClockMapper::global().to_monotonic(*self)
+    }
+
+    /// Converts the timestamp to wall clock time using the global
+    /// ClockMapper.
+    pub fn to_system_time(&self) -> SystemTime {
+        ClockMapper::global().to_system_time(*self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> Timestamp {
+        Timestamp::from_nanos(Clock::Boottime.now() as u64)
+    }
+
+    #[test]
+    fn clock_round_trip() {
+        // Keep the offsets fixed between conversions.
+        let mapper = ClockMapper::with_refresh_interval(Duration::from_secs(3600));
+        let timestamp = now();
+
+        for clock in [Clock::Boottime, Clock::Monotonic, Clock::Realtime] {
+            let nanos = mapper.to_clock(timestamp, clock);
+            assert_eq!(mapper.from_clock(nanos, clock), Some(timestamp));
+        }
+
+        let time = mapper.to_system_time(timestamp);
+        assert_eq!(mapper.from_system_time(time), Some(timestamp));
+    }
+
+    #[test]
+    fn converts_to_current_time() {
+        let mapper = ClockMapper::new();
+
+        let monotonic = mapper.to_monotonic(now()).unwrap().as_nanos() as i128;
+        assert!((Clock::Monotonic.now() - monotonic).abs() < 5_000_000);
+
+        let system_time = mapper.to_system_time(now());
+        let now = SystemTime::now();
+        let error = match now.duration_since(system_time) {
+            Ok(d) => d,
+            Err(e) => e.duration(),
+        };
+        assert!(error < Duration::from_millis(5), "{:?}", error);
+    }
+
+    #[test]
+    fn times_before_boot_are_rejected() {
+        let mapper = ClockMapper::new();
+
+        assert_eq!(mapper.from_clock(-1, Clock::Boottime), None);
+        assert_eq!(mapper.from_system_time(UNIX_EPOCH), None);
+        assert_eq!(
+            mapper.from_system_time(UNIX_EPOCH - Duration::from_secs(86400)),
+            None
+        );
+
+        let boot = SystemTime::now() - Duration::from_nanos(Clock::Boottime.now() as u64);
+        assert_eq!(mapper.from_system_time(boot - Duration::from_secs(1)), None);
+        assert!(mapper
+            .from_system_time(boot + Duration::from_secs(1))
+            .is_some());
+    }
+}
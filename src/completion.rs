@@ -0,0 +1,136 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+/// Result of the latest operation started on a helper such as Autofocus or
+/// Exposure, which is completed from the helper's observe().
+pub(crate) struct Completion<T> {
+    /// Incremented whenever an operation is superseded so that futures for
+    /// older operations can tell that they are no longer current.
+    generation: u64,
+    result: Option<(u64, T)>,
+    waker: Option<Waker>,
+
+    /// Output of futures for operations which were superseded before they
+    /// completed.
+    superseded: T,
+}
+
+impl<T: Clone> Completion<T> {
+    pub(crate) fn new(superseded: T) -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self {
+            generation: 0,
+            result: None,
+            waker: None,
+            superseded,
+        }))
+    }
+
+    /// Gets a future which completes with the result of the current
+    /// operation.
+    pub(crate) fn future(completion: &Arc<Mutex<Self>>) -> CompletionFuture<T> {
+        CompletionFuture {
+            completion: completion.clone(),
+            generation: completion.lock().unwrap().generation,
+        }
+    }
+
+    /// Completes the current operation.
+    pub(crate) fn finish(&mut self, result: T) {
+        self.result = Some((self.generation, result));
+        self.wake();
+    }
+
+    /// Abandons the current operation (if any) so that a new one can start.
+    pub(crate) fn supersede(&mut self) {
+        self.generation += 1;
+        self.wake();
+    }
+
+    fn wake(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Future returned by the Autofocus and Exposure helper methods.
+pub struct CompletionFuture<T> {
+    completion: Arc<Mutex<Completion<T>>>,
+    generation: u64,
+}
+
+impl<T: Clone> Future for CompletionFuture<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut completion = self.completion.lock().unwrap();
+
+        match &completion.result {
+            Some((generation, result)) if *generation == self.generation => {
+                return Poll::Ready(result.clone())
+            }
+            _ => {}
+        }
+
+        if completion.generation != self.generation {
+            return Poll::Ready(completion.superseded.clone());
+        }
+
+        completion.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::Wake;
+
+    use super::*;
+
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn poll<T: Clone>(future: &mut CompletionFuture<T>, waker: &Arc<CountingWaker>) -> Poll<T> {
+        let waker = Waker::from(waker.clone());
+        Pin::new(future).poll(&mut Context::from_waker(&waker))
+    }
+
+    #[test]
+    fn finish_completes_current_future() {
+        let completion = Completion::new("superseded");
+        let waker = Arc::new(CountingWaker(AtomicUsize::new(0)));
+
+        let mut future = Completion::future(&completion);
+        assert_eq!(poll(&mut future, &waker), Poll::Pending);
+
+        completion.lock().unwrap().finish("done");
+        assert_eq!(waker.0.load(Ordering::SeqCst), 1);
+        assert_eq!(poll(&mut future, &waker), Poll::Ready("done"));
+    }
+
+    #[test]
+    fn supersede_completes_older_futures() {
+        let completion = Completion::new("superseded");
+        let waker = Arc::new(CountingWaker(AtomicUsize::new(0)));
+
+        let mut old = Completion::future(&completion);
+        assert_eq!(poll(&mut old, &waker), Poll::Pending);
+
+        completion.lock().unwrap().supersede();
+        assert_eq!(waker.0.load(Ordering::SeqCst), 1);
+
+        let mut new = Completion::future(&completion);
+        completion.lock().unwrap().finish("done");
+
+        assert_eq!(poll(&mut old, &waker), Poll::Ready("superseded"));
+        assert_eq!(poll(&mut new, &waker), Poll::Ready("done"));
+    }
+}
@@ -1,8 +1,32 @@
 use std::fmt::{write, Debug};
 
-use crate::control_value::ControlValue;
+use crate::control_value::{ControlPrimitiveValue, ControlValue};
 use crate::ffi;
 
+/// Scalar control value types whose limits can be read with
+/// ControlInfo::limits().
+pub(crate) trait ScalarLimit: PartialOrd + Sized {
+    fn from_control_value(value: ControlValue) -> Option<Self>;
+}
+
+impl ScalarLimit for i32 {
+    fn from_control_value(value: ControlValue) -> Option<Self> {
+        match value {
+            ControlValue::Primitive(ControlPrimitiveValue::Int32(v)) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+impl ScalarLimit for f32 {
+    fn from_control_value(value: ControlValue) -> Option<Self> {
+        match value {
+            ControlValue::Primitive(ControlPrimitiveValue::Float(v)) => Some(v),
+            _ => None,
+        }
+    }
+}
+
 #[repr(transparent)]
 pub struct ControlInfo {
     raw: ffi::ControlInfo,
@@ -27,6 +51,18 @@ impl ControlInfo {
         self.raw.max().into()
     }
 
+    /// Gets the (min, max) range of a scalar control, or None if the limits
+    /// aren't of type T or don't form a range.
+    pub(crate) fn limits<T: ScalarLimit>(&self) -> Option<(T, T)> {
+        let min = T::from_control_value(self.min())?;
+        let max = T::from_control_value(self.max())?;
+        if min <= max {
+            Some((min, max))
+        } else {
+            None
+        }
+    }
+
     pub fn values(&self) -> Vec<ControlValue> {
         self.raw
             .values()
@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use std::fmt::Debug;
 
+use crate::control::Control;
 use crate::control_info::ControlInfo;
 use crate::control_value::ControlValue;
 use crate::ffi;
@@ -22,6 +23,14 @@ impl ControlInfoMap {
             .into_iter()
             .map(|entry| (entry.key, entry.value.into()))
     }
+
+    /// Gets the limits of a control, or None if the camera doesn't support it.
+    pub fn get<T: ?Sized>(&self, control: Control<T>) -> Option<&ControlInfo> {
+        let id = control.id();
+        self.iter()
+            .find(|(key, _)| key.id() == id)
+            .map(|(_, info)| info)
+    }
 }
 
 impl Debug for ControlInfoMap {
@@ -0,0 +1,317 @@
+use std::collections::HashMap;
+
+use crate::camera::Camera;
+use crate::control::Control;
+use crate::control_value::{ControlArrayValue, ControlPrimitiveValue, ControlValue};
+use crate::request::{Request, RequestStatus};
+
+/// Relative difference below which a numeric value reported in metadata is
+/// considered to be the requested one (e.g. exposure times are quantized to
+/// sensor lines).
+const VALUE_TOLERANCE: f64 = 0.02;
+
+/// A control which was set on a request and is now reported as being in
+/// effect by the metadata of a later (or the same) request.
+#[derive(Debug, Clone)]
+pub struct EffectiveControl {
+    /// Id of the control.
+    pub id: u32,
+
+    /// Value which was set on the request.
+    pub requested: ControlValue,
+
+    /// Value reported in the metadata.
+    pub reported: ControlValue,
+
+    /// Cookie of the request which set the control.
+    pub request_cookie: u64,
+
+    /// Sequence number of the request which set the control.
+    pub request_sequence: u32,
+
+    /// Number of requests after the one which set the control before the
+    /// value was reported (0 if it was reported in the same request).
+    pub latency: u32,
+}
+
+impl EffectiveControl {
+    pub fn is<T: ?Sized>(&self, control: Control<T>) -> bool {
+        self.id == control.id()
+    }
+}
+
+struct PendingControl {
+    id: u32,
+    value: ControlValue,
+    request_cookie: u64,
+    request_sequence: u32,
+}
+
+/// Matches the controls set on each request against the metadata of
+/// completed requests to find out when each setting took effect.
+///
+/// Every completed request must be passed to observe() in order of
+/// completion. A control is reported as effective as soon as the metadata
+/// changes to its value. If the value was already in effect when it was
+/// requested, this can't be told apart from the control taking effect, so
+/// it is only reported once the camera's maximum latency has passed.
+/// Controls which never appear in metadata (e.g. triggers) are dropped once
+/// the latency has passed.
+pub struct ControlLatencyTracker {
+    pending: Vec<PendingControl>,
+
+    /// Value of each control in the metadata of the last observed request.
+    reported: HashMap<u32, ControlValue>,
+    latency: u32,
+}
+
+impl ControlLatencyTracker {
+    pub fn new(camera: &Camera) -> Self {
+        Self::with_latency(camera.max_latency())
+    }
+
+    /// Creates a tracker for a camera which takes at most 'latency' requests
+    /// to apply controls.
+    pub fn with_latency(latency: u32) -> Self {
+        Self {
+            pending: vec![],
+            reported: HashMap::new(),
+            latency,
+        }
+    }
+
+    /// Records the controls set on a completed request and returns any
+    /// previously requested controls which its metadata shows are now in
+    /// effect.
+    pub fn observe(&mut self, request: &Request) -> Vec<EffectiveControl> {
+        // Controls from cancelled requests are never applied.
+        let requested = match request.status() {
+            RequestStatus::RequestComplete => request.requested_controls(),
+            _ => &[],
+        };
+
+        self.observe_values(
+            request.sequence(),
+            request.cookie(),
+            requested,
+            request.metadata().iter(),
+        )
+    }
+
+    fn observe_values(
+        &mut self,
+        sequence: u32,
+        cookie: u64,
+        requested: &[(u32, ControlValue)],
+        metadata: impl IntoIterator<Item = (u32, ControlValue)>,
+    ) -> Vec<EffectiveControl> {
+        for (id, value) in requested {
+            self.pending.push(PendingControl {
+                id: *id,
+                value: value.clone(),
+                request_cookie: cookie,
+                request_sequence: sequence,
+            });
+        }
+
+        let mut effective = vec![];
+        for (id, reported) in metadata {
+            // The first report of a control can't be compared to anything, so
+            // it is treated like an unchanged value.
+            let changed = self
+                .reported
+                .insert(id, reported.clone())
+                .map_or(false, |last| !values_match(&last, &reported));
+
+            // Controls take effect in the order they were requested, so the
+            // earliest pending control with the reported value is the one in
+            // effect. An unchanged value may have been in effect before the
+            // control was requested, so wait for the latency to pass.
+            let latency = self.latency;
+            let index = self.pending.iter().position(|p| {
+                p.id == id
+                    && values_match(&p.value, &reported)
+                    && (changed || sequence.wrapping_sub(p.request_sequence) >= latency)
+            });
+
+            if let Some(index) = index {
+                let pending = self.pending.remove(index);
+
+                // Anything requested earlier for the same control has been
+                // overridden.
+                self.pending
+                    .retain(|p| p.id != id || p.request_sequence > pending.request_sequence);
+
+                effective.push(EffectiveControl {
+                    id,
+                    requested: pending.value,
+                    reported,
+                    request_cookie: pending.request_cookie,
+                    request_sequence: pending.request_sequence,
+                    latency: sequence.wrapping_sub(pending.request_sequence),
+                });
+            }
+        }
+
+        let latency = self.latency;
+        self.pending
+            .retain(|p| sequence.wrapping_sub(p.request_sequence) <= latency);
+
+        effective
+    }
+}
+
+/// Checks if a numeric value reported in metadata is the same as the requested
+/// one, allowing for it to be quantized by the camera.
+pub(crate) fn close(requested: f64, reported: f64) -> bool {
+    (requested - reported).abs() <= requested.abs() * VALUE_TOLERANCE
+}
+
+/// Checks if a value reported in metadata is the same as a requested value,
+/// allowing for numeric values to be rounded by the camera.
+fn values_match(requested: &ControlValue, reported: &ControlValue) -> bool {
+    use ControlArrayValue as A;
+    use ControlPrimitiveValue as P;
+
+    fn all_close<T: Copy + Into<f64>>(a: &[T], b: &[T]) -> bool {
+        a.len() == b.len()
+            && a.iter()
+                .zip(b)
+                .all(|(a, b)| close((*a).into(), (*b).into()))
+    }
+
+    match (requested, reported) {
+        (ControlValue::Primitive(P::Int32(a)), ControlValue::Primitive(P::Int32(b))) => {
+            close(*a as f64, *b as f64)
+        }
+        (ControlValue::Primitive(P::Int64(a)), ControlValue::Primitive(P::Int64(b))) => {
+            close(*a as f64, *b as f64)
+        }
+        (ControlValue::Primitive(P::Float(a)), ControlValue::Primitive(P::Float(b))) => {
+            close(*a as f64, *b as f64)
+        }
+        (ControlValue::Array(A::Int32(a)), ControlValue::Array(A::Int32(b))) => all_close(a, b),
+        (ControlValue::Array(A::Float(a)), ControlValue::Array(A::Float(b))) => all_close(a, b),
+        (ControlValue::Array(A::Int64(a)), ControlValue::Array(A::Int64(b))) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| close(*a as f64, *b as f64))
+        }
+        _ => requested == reported,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: u32 = 1;
+
+    fn int(value: i32) -> ControlValue {
+        ControlValue::Primitive(ControlPrimitiveValue::Int32(value))
+    }
+
+    /// Feeds requests with sequence numbers 0, 1, ... which each optionally
+    /// set the control and report a value in their metadata. Returns
+    /// (request_sequence, latency) for every effective control.
+    fn run(latency: u32, frames: &[(Option<i32>, i32)]) -> Vec<(u32, u32)> {
+        let mut tracker = ControlLatencyTracker::with_latency(latency);
+        let mut effective = vec![];
+
+        for (sequence, (requested, reported)) in frames.iter().enumerate() {
+            let requested: Vec<_> = requested.iter().map(|v| (ID, int(*v))).collect();
+            let found = tracker.observe_values(
+                sequence as u32,
+                sequence as u64,
+                &requested,
+                [(ID, int(*reported))],
+            );
+
+            for control in found {
+                assert_eq!(control.request_cookie, control.request_sequence as u64);
+                assert!(values_match(&control.requested, &control.reported));
+                effective.push((control.request_sequence, control.latency));
+            }
+        }
+
+        effective
+    }
+
+    #[test]
+    fn change_is_reported_when_it_appears() {
+        let frames = [
+            (None, 100),
+            (Some(200), 100),
+            (None, 100),
+            (None, 200),
+            (None, 200),
+        ];
+        assert_eq!(run(4, &frames), [(1, 2)]);
+    }
+
+    #[test]
+    fn value_within_tolerance_matches() {
+        let frames = [(None, 10000), (Some(20000), 10000), (None, 19990)];
+        assert_eq!(run(4, &frames), [(1, 1)]);
+    }
+
+    #[test]
+    fn value_already_in_effect_waits_for_latency() {
+        let frames = [
+            (None, 100),
+            (Some(100), 100),
+            (None, 100),
+            (None, 100),
+            (None, 100),
+            (None, 100),
+        ];
+        assert_eq!(run(3, &frames), [(1, 3)]);
+    }
+
+    #[test]
+    fn first_report_waits_for_latency() {
+        let frames = [(Some(100), 100), (None, 100), (None, 100)];
+        assert_eq!(run(2, &frames), [(0, 2)]);
+    }
+
+    #[test]
+    fn earliest_matching_request_is_credited() {
+        // 100 is requested twice around 200. When 100 first appears, only
+        // the first request can have taken effect.
+        let frames = [
+            (None, 50),
+            (Some(100), 50),
+            (Some(200), 50),
+            (Some(100), 50),
+            (None, 100),
+            (None, 200),
+            (None, 100),
+        ];
+        assert_eq!(run(4, &frames), [(1, 3), (2, 3), (3, 3)]);
+    }
+
+    #[test]
+    fn overridden_requests_are_dropped() {
+        // 200 is overridden by 300 before it is ever reported.
+        let frames = [
+            (None, 100),
+            (Some(200), 100),
+            (Some(300), 100),
+            (None, 100),
+            (None, 300),
+            (None, 200),
+        ];
+        assert_eq!(run(4, &frames), [(2, 2)]);
+    }
+
+    #[test]
+    fn unreported_controls_expire() {
+        let frames = [
+            (None, 100),
+            (Some(200), 100),
+            (None, 100),
+            (None, 100),
+            (None, 100),
+            (None, 200),
+        ];
+        assert_eq!(run(2, &frames), []);
+    }
+}
@@ -27,20 +27,52 @@ impl ControlList {
     }
     */
 
-    pub fn get<'a, T: FromRawControlValue<'a>>(&'a self, control: Control<T>) -> Option<T::Target> {
+    /// Gets the value of a control. Returns None if the control isn't in the
+    /// list or its value doesn't match T (e.g. an array control with an
+    /// unexpected number of elements).
+    pub fn get<'a, T: ?Sized + FromRawControlValue<'a>>(
+        &'a self,
+        control: Control<T>,
+    ) -> Option<T::Target> {
         if !self.raw.contains(control.id()) {
             return None;
         }
 
-        Some(T::from_value(self.raw.get(control.id())))
+        T::from_value(self.raw.get(control.id()))
     }
 
     pub fn set<T: AssignToRawControlValue, V: Into<T>>(&mut self, control: Control<T>, value: V) {
+        self.set_raw(control.id(), &value.into());
+    }
+
+    /// Sets the value of a variable length array control (e.g. AfWindows)
+    /// from a slice.
+    pub fn set_slice<T>(&mut self, control: Control<[T]>, value: &[T])
+    where
+        [T]: AssignToRawControlValue,
+    {
+        self.set_raw(control.id(), value);
+    }
+
+    fn set_raw<T: ?Sized + AssignToRawControlValue>(&mut self, id: u32, value: &T) {
         let mut raw_value = ffi::new_control_value();
-        value.into().assign_to(raw_value.as_mut().unwrap());
+        value.assign_to(raw_value.as_mut().unwrap());
 
         let p = unsafe { Pin::new_unchecked(&mut self.raw) };
-        p.set(control.id(), &raw_value);
+        p.set(id, &raw_value);
+    }
+
+    /// Removes all values from the list.
+    pub fn clear(&mut self) {
+        let p = unsafe { Pin::new_unchecked(&mut self.raw) };
+        p.clear();
+    }
+
+    /// Iterates over all values in the list keyed by control id.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, ControlValue)> + '_ {
+        ffi::control_list_entries(&self.raw)
+            .into_iter()
+            .map(|entry| (entry.key, entry.value.into()))
     }
 
     /*
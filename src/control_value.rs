@@ -2,14 +2,13 @@ use std::pin::Pin;
 
 use paste::paste;
 
-use crate::{ffi, Size};
-
-pub use ffi::Rectangle;
+use crate::ffi;
+use crate::geometry::{Rectangle, Size};
 
 // TODO: For consistency, use the C++ toString when debugging this?
 
 // TODO: Remove this?
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ControlValue {
     None,
     Primitive(ControlPrimitiveValue),
@@ -17,7 +16,7 @@ pub enum ControlValue {
     Unknown,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ControlPrimitiveValue {
     Bool(bool),
     Byte(u8),
@@ -29,7 +28,7 @@ pub enum ControlPrimitiveValue {
     String(String),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ControlArrayValue {
     Bool(Vec<bool>),
     Byte(Vec<u8>),
@@ -55,14 +54,13 @@ impl ControlValue {
             ControlValue::None => todo!(),
             ControlValue::Primitive(p) => match p {
                 ControlPrimitiveValue::Bool(v) => value.set_bool(v),
-                ControlPrimitiveValue::Byte(_) => todo!(),
-                ControlPrimitiveValue::Int32(_) => todo!(),
-                ControlPrimitiveValue::Int64(_) => todo!(),
-                ControlPrimitiveValue::Float(_) => todo!(),
-                ControlPrimitiveValue::Rectangle(_) => todo!(),
-                ControlPrimitiveValue::Size(_) => todo!(),
-                ControlPrimitiveValue::String(_) => todo!(),
-                //
+                ControlPrimitiveValue::Byte(v) => value.set_byte(v),
+                ControlPrimitiveValue::Int32(v) => value.set_i32(v),
+                ControlPrimitiveValue::Int64(v) => value.set_i64(v),
+                ControlPrimitiveValue::Float(v) => value.set_float(v),
+                ControlPrimitiveValue::Rectangle(v) => value.set_rectangle(v),
+                ControlPrimitiveValue::Size(v) => value.set_size(v),
+                ControlPrimitiveValue::String(v) => ffi::control_value_set_string(value, v),
             },
             ControlValue::Array(_) => todo!(),
             ControlValue::Unknown => todo!(),
@@ -107,29 +105,16 @@ impl From<&ffi::ControlValue> for ControlValue {
             use ControlPrimitiveValue::*;
 
             ControlValue::Primitive(match value.typ() {
-                // ffi::ControlType::ControlTypeNone => return ControlValue::None,
-                // ffi::ControlType::ControlTypeBool =>
-                // Bool(FromRawControlValue::from_value(value)),
-                // ffi::ControlType::ControlTypeByte =>
-                // Byte(FromRawControlValue::from_value(value)),
-                // ffi::ControlType::ControlTypeInteger32 => {
-                //     Int32(FromRawControlValue::from_value(value))
-                // }
-                // ffi::ControlType::ControlTypeInteger64 => {
-                //     Int64(FromRawControlValue::from_value(value))
-                // }
-                // ffi::ControlType::ControlTypeFloat =>
-                // Float(FromRawControlValue::from_value(value)),
-                // ffi::ControlType::ControlTypeString =>
-                // String(ffi::control_value_get_string(value)),
-                // ffi::ControlType::ControlTypeRectangle => {
-                //     Rectangle(FromRawControlValue::from_value(value))
-                // }
-                // ffi::ControlType::ControlTypeSize =>
-                // Size(FromRawControlValue::from_value(value)),
-                _ => {
-                    return ControlValue::Unknown;
-                }
+                ffi::ControlType::ControlTypeNone => return ControlValue::None,
+                ffi::ControlType::ControlTypeBool => Bool(value.get_bool()),
+                ffi::ControlType::ControlTypeByte => Byte(value.get_byte()),
+                ffi::ControlType::ControlTypeInteger32 => Int32(value.get_i32()),
+                ffi::ControlType::ControlTypeInteger64 => Int64(value.get_i64()),
+                ffi::ControlType::ControlTypeFloat => Float(value.get_float()),
+                ffi::ControlType::ControlTypeString => String(ffi::control_value_get_string(value)),
+                ffi::ControlType::ControlTypeRectangle => Rectangle(value.get_rectangle()),
+                ffi::ControlType::ControlTypeSize => Size(value.get_size()),
+                _ => return ControlValue::Unknown,
             })
         }
     }
@@ -140,7 +125,9 @@ impl From<&ffi::ControlValue> for ControlValue {
 pub trait FromRawControlValue<'a> {
     type Target;
 
-    fn from_value(value: &'a ffi::ControlValue) -> Self::Target;
+    /// Returns None if the value can't be represented as Self::Target (e.g.
+    /// an array control with an unexpected number of elements).
+    fn from_value(value: &'a ffi::ControlValue) -> Option<Self::Target>;
 }
 
 pub trait AssignToRawControlValue {
@@ -164,24 +151,26 @@ macro_rules! impl_control_value_type {
             impl<'a> FromRawControlValue<'a> for $typ {
                 type Target = Self;
 
-                fn from_value(value: &'a ffi::ControlValue) -> Self {
-                    value.[<get_ $ffi_typ>]()
+                fn from_value(value: &'a ffi::ControlValue) -> Option<Self> {
+                    Some(value.[<get_ $ffi_typ>]())
                 }
             }
 
             impl<'a> FromRawControlValue<'a> for [$typ] {
                 type Target = &'a Self;
 
-                fn from_value(value: &'a ffi::ControlValue) -> &'a Self {
-                    ffi::[<control_value_get_ $ffi_typ _array>](value)
+                fn from_value(value: &'a ffi::ControlValue) -> Option<&'a Self> {
+                    Some(ffi::[<control_value_get_ $ffi_typ _array>](value))
                 }
             }
 
-            impl<'a> FromRawControlValue<'a> for [$typ; 1] {
-                type Target = &'a [$typ];
+            impl<'a, const LEN: usize> FromRawControlValue<'a> for [$typ; LEN] {
+                type Target = Self;
 
-                fn from_value(value: &'a ffi::ControlValue) -> &'a [$typ] {
+                fn from_value(value: &'a ffi::ControlValue) -> Option<Self> {
                     ffi::[<control_value_get_ $ffi_typ _array>](value)
+                        .try_into()
+                        .ok()
                 }
             }
 
@@ -214,6 +203,14 @@ impl_control_value_type!(f32, float);
 impl_control_value_type!(Rectangle, rectangle);
 impl_control_value_type!(Size, size);
 
+impl<'a> FromRawControlValue<'a> for String {
+    type Target = Self;
+
+    fn from_value(value: &'a ffi::ControlValue) -> Option<Self> {
+        Some(ffi::control_value_get_string(value))
+    }
+}
+
 impl AssignToRawControlValue for String {
     fn assign_to(&self, value: Pin<&mut ffi::ControlValue>) {
         ffi::control_value_set_string(value, self);
@@ -11,6 +11,12 @@ macro_rules! control {
     ($name:ident, $t:ty, draft) => {
         control!($name, $t, $crate::bindings::controls::draft::$name);
     };
+    ($name:ident, $t:ty, property) => {
+        control!($name, $t, $crate::bindings::properties::$name);
+    };
+    ($name:ident, $t:ty, property_draft) => {
+        control!($name, $t, $crate::bindings::properties::draft::$name);
+    };
     ($name:ident, $t:ty, $extern_var:expr) => {
         pub const $name: $crate::Control<$t> =
             unsafe { $crate::Control::new(|| ::core::mem::transmute(&$extern_var)) };
@@ -21,6 +27,8 @@ macro_rules! control {
 macro_rules! control_enum {
     ($name:ident $t:ty { $($case:ident = $val:expr,)* }) => {
         #[derive(Clone, Copy, PartialEq, Eq, Hash)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+        #[cfg_attr(feature = "serde", serde(transparent))]
         #[repr(transparent)]
         pub struct $name {
             value: $t,
@@ -39,8 +47,8 @@ macro_rules! control_enum {
         impl<'a> FromRawControlValue<'a> for $name {
             type Target = Self;
 
-            fn from_value(value: &'a ffi::ControlValue) -> Self {
-                Self::new(<$t as FromRawControlValue>::from_value(value))
+            fn from_value(value: &'a ffi::ControlValue) -> Option<Self> {
+                <$t as FromRawControlValue>::from_value(value).map(Self::new)
             }
         }
 
@@ -50,8 +58,20 @@ macro_rules! control_enum {
             }
         }
 
-        // TODO: also need stringification
+        impl ::core::fmt::Debug for $name {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                $(
+                    if *self == Self::$case {
+                        return f.write_str(stringify!($case));
+                    }
+                )*
+
+                write!(f, "{}({})", stringify!($name), self.value)
+            }
+        }
     };
 }
 
+pub(crate) use {control, control_enum};
+
 include!(concat!(env!("OUT_DIR"), "/controls.rs"));
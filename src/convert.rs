@@ -0,0 +1,716 @@
+//! Conversion of frames in common YUV and RGB formats to tightly packed 8-bit
+//! RGB, RGBA or greyscale images.
+
+use std::fmt;
+
+use crate::formats;
+use crate::pixel_format::PixelFormat;
+use crate::pixel_format_info::PixelFormatInfo;
+use crate::stream_configuration::{Size, StreamConfigurationOpaque};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// 3 bytes per pixel in R, G, B order.
+    Rgb8,
+    /// 4 bytes per pixel in R, G, B, A order. Alpha is 255 unless the input
+    /// format has an alpha channel.
+    Rgba8,
+    /// 1 byte per pixel containing the luma.
+    Gray8,
+}
+
+impl OutputFormat {
+    pub fn bytes_per_pixel(&self) -> usize {
+        match self {
+            OutputFormat::Rgb8 => 3,
+            OutputFormat::Rgba8 => 4,
+            OutputFormat::Gray8 => 1,
+        }
+    }
+}
+
+/// Matrix used to convert YUV samples to RGB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum YuvMatrix {
+    Rec601,
+    Rec709,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum YuvRange {
+    /// Luma in [16, 235] and chroma in [16, 240].
+    Limited,
+    /// All samples use [0, 255].
+    Full,
+}
+
+/// Describes how a frame is stored in memory.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameLayout {
+    pub pixel_format: PixelFormat,
+
+    pub size: Size,
+
+    /// Number of bytes in each line of the first plane. If zero, lines are
+    /// assumed to be tightly packed.
+    pub stride: u32,
+
+    /// Only used for YUV formats.
+    pub yuv_matrix: YuvMatrix,
+    pub yuv_range: YuvRange,
+}
+
+impl FrameLayout {
+    /// Gets the layout of frames produced by a configured stream.
+    ///
+    /// NOTE: The colour space isn't currently exposed by the stream
+    /// configuration so YUV data is assumed to be limited range Rec601.
+    pub fn from_stream_config(config: &StreamConfigurationOpaque) -> Self {
+        Self {
+            pixel_format: config.pixel_format(),
+            size: config.size(),
+            stride: config.stride(),
+            yuv_matrix: YuvMatrix::Rec601,
+            yuv_range: YuvRange::Limited,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConvertError {
+    /// The pixel format can't be converted by this module.
+    UnsupportedFormat(PixelFormat),
+
+    /// A plane is smaller than needed to store a frame with the given layout.
+    BufferTooSmall {
+        plane: usize,
+        expected: usize,
+        actual: usize,
+    },
+
+    /// The frame buffer's memory hasn't been mapped with
+    /// FrameBuffer::map_memory().
+    NotMapped,
+
+    /// The frame has a zero width or height.
+    EmptyFrame(Size),
+}
+
+impl fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConvertError::UnsupportedFormat(format) => {
+                write!(f, "Unsupported pixel format: {:?}", format)
+            }
+            ConvertError::BufferTooSmall {
+                plane,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Plane {} is too small: expected at least {} bytes but got {}",
+                plane, expected, actual
+            ),
+            ConvertError::NotMapped => write!(f, "Frame buffer memory is not mapped"),
+            ConvertError::EmptyFrame(size) => write!(f, "Frame size {} is empty", size),
+        }
+    }
+}
+
+impl std::error::Error for ConvertError {}
+
+/// Converts a single frame into a tightly packed image in the given output
+/// format.
+///
+/// 'planes' should contain the memory of each plane of the frame (see
+/// FrameBuffer::plane_memory()). If a multi-planar frame is provided as a
+/// single slice, the planes are assumed to be stored contiguously.
+pub fn convert(
+    planes: &[&[u8]],
+    layout: &FrameLayout,
+    output: OutputFormat,
+) -> Result<Vec<u8>, ConvertError> {
+    if layout.size.width == 0 || layout.size.height == 0 {
+        return Err(ConvertError::EmptyFrame(layout.size));
+    }
+
+    let source = Source::new(layout.pixel_format)?;
+    let info = PixelFormatInfo::lookup(layout.pixel_format)
+        .ok_or(ConvertError::UnsupportedFormat(layout.pixel_format))?;
+
+    let strides = (0..info.num_planes())
+        .map(|i| {
+            if layout.stride != 0 {
+                info.plane_stride(layout.stride, i) as usize
+            } else {
+                info.stride(layout.size.width, i, 1) as usize
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let planes = split_planes(planes, info, layout, &strides)?;
+
+    let width = layout.size.width as usize;
+    let height = layout.size.height as usize;
+    let bytes_per_pixel = output.bytes_per_pixel();
+    let coefficients = YuvCoefficients::new(layout.yuv_matrix, layout.yuv_range);
+
+    let mut out = vec![0u8; width * height * bytes_per_pixel];
+
+    for (y, out_row) in out.chunks_exact_mut(width * bytes_per_pixel).enumerate() {
+        for (x, out_pixel) in out_row.chunks_exact_mut(bytes_per_pixel).enumerate() {
+            let sample = source.sample(&planes, &strides, info, x, y);
+            sample.write(out_pixel, output, &coefficients);
+        }
+    }
+
+    Ok(out)
+}
+
+/// Converts tightly packed Y, Cb, Cr samples (e.g. a decoded JPEG) to RGB.
+#[cfg(feature = "mjpeg")]
+pub(crate) fn packed_yuv444_to_rgb(data: &[u8], matrix: YuvMatrix, range: YuvRange) -> Vec<u8> {
+    let coefficients = YuvCoefficients::new(matrix, range);
+    data.chunks_exact(3)
+        .flat_map(|yuv| {
+            let [r, g, b, _] = coefficients.to_rgba([yuv[0], yuv[1], yuv[2]]);
+            [r, g, b]
+        })
+        .collect()
+}
+
+/// Gets the memory of each plane and checks that they are large enough.
+fn split_planes<'a>(
+    planes: &[&'a [u8]],
+    info: &PixelFormatInfo,
+    layout: &FrameLayout,
+    strides: &[usize],
+) -> Result<Vec<&'a [u8]>, ConvertError> {
+    let height = layout.size.height;
+
+    let mut out = vec![];
+    let mut remaining: &[u8] = &[];
+
+    for (i, stride) in strides.iter().cloned().enumerate() {
+        // Bytes needed if every line is padded out to the stride.
+        let full_size = info.plane_size_with_stride(height, i, stride as u32) as usize;
+
+        // The padding after the last line may be omitted.
+        let min_size = full_size - stride + info.stride(layout.size.width, i, 1) as usize;
+
+        let data = if let Some(data) = planes.get(i) {
+            *data
+        } else if i > 0 && planes.len() == 1 {
+            let data = remaining;
+            remaining = &remaining[full_size.min(remaining.len())..];
+            data
+        } else {
+            &[]
+        };
+
+        if data.len() < min_size {
+            return Err(ConvertError::BufferTooSmall {
+                plane: i,
+                expected: min_size,
+                actual: data.len(),
+            });
+        }
+
+        if i == 0 && planes.len() == 1 {
+            remaining = &data[full_size.min(data.len())..];
+        }
+
+        out.push(data);
+    }
+
+    Ok(out)
+}
+
+/// Describes where each component of a pixel is stored for a supported format.
+enum Source {
+    /// 4:2:2 packed YUV where each 4 byte group stores two pixels. Values are
+    /// the offsets of the Y0, U, Y1 and V samples in each group.
+    PackedYuv422 {
+        y0: usize,
+        u: usize,
+        y1: usize,
+        v: usize,
+    },
+
+    /// Luma plane followed by a plane of interleaved chroma samples.
+    SemiPlanarYuv { u_first: bool },
+
+    /// Luma plane followed by separate chroma planes.
+    PlanarYuv { u_plane: usize, v_plane: usize },
+
+    /// Interleaved RGB samples. Values are byte offsets of each component.
+    Rgb {
+        bytes_per_pixel: usize,
+        r: usize,
+        g: usize,
+        b: usize,
+        a: Option<usize>,
+    },
+
+    /// Single 8-bit luma plane.
+    Grey,
+}
+
+impl Source {
+    fn new(format: PixelFormat) -> Result<Self, ConvertError> {
+        // NOTE: DRM formats are named by the order of components in a little
+        // endian word, so the order in memory is reversed (e.g. RGB888 is
+        // stored as B, G, R).
+        Ok(match format {
+            formats::YUYV => Source::PackedYuv422 {
+                y0: 0,
+                u: 1,
+                y1: 2,
+                v: 3,
+            },
+            formats::YVYU => Source::PackedYuv422 {
+                y0: 0,
+                v: 1,
+                y1: 2,
+                u: 3,
+            },
+            formats::UYVY => Source::PackedYuv422 {
+                u: 0,
+                y0: 1,
+                v: 2,
+                y1: 3,
+            },
+            formats::VYUY => Source::PackedYuv422 {
+                v: 0,
+                y0: 1,
+                u: 2,
+                y1: 3,
+            },
+            formats::NV12 | formats::NV16 | formats::NV24 => {
+                Source::SemiPlanarYuv { u_first: true }
+            }
+            formats::NV21 | formats::NV61 | formats::NV42 => {
+                Source::SemiPlanarYuv { u_first: false }
+            }
+            formats::YUV420 | formats::YUV422 | formats::YUV444 => Source::PlanarYuv {
+                u_plane: 1,
+                v_plane: 2,
+            },
+            formats::YVU420 | formats::YVU422 | formats::YVU444 => Source::PlanarYuv {
+                u_plane: 2,
+                v_plane: 1,
+            },
+            formats::RGB888 => Source::Rgb {
+                bytes_per_pixel: 3,
+                r: 2,
+                g: 1,
+                b: 0,
+                a: None,
+            },
+            formats::BGR888 => Source::Rgb {
+                bytes_per_pixel: 3,
+                r: 0,
+                g: 1,
+                b: 2,
+                a: None,
+            },
+            formats::XRGB8888 | formats::ARGB8888 => Source::Rgb {
+                bytes_per_pixel: 4,
+                r: 2,
+                g: 1,
+                b: 0,
+                a: if format == formats::ARGB8888 {
+                    Some(3)
+                } else {
+                    None
+                },
+            },
+            formats::XBGR8888 | formats::ABGR8888 => Source::Rgb {
+                bytes_per_pixel: 4,
+                r: 0,
+                g: 1,
+                b: 2,
+                a: if format == formats::ABGR8888 {
+                    Some(3)
+                } else {
+                    None
+                },
+            },
+            formats::R8 => Source::Grey,
+            _ => return Err(ConvertError::UnsupportedFormat(format)),
+        })
+    }
+
+    fn sample(
+        &self,
+        planes: &[&[u8]],
+        strides: &[usize],
+        info: &PixelFormatInfo,
+        x: usize,
+        y: usize,
+    ) -> Sample {
+        let h_sub = info.horizontal_sub_sampling as usize;
+        let v_sub = info.vertical_sub_sampling as usize;
+
+        match self {
+            Source::PackedYuv422 { y0, u, y1, v } => {
+                let group = &planes[0][y * strides[0] + (x / 2) * 4..];
+                let luma = if x % 2 == 0 { group[*y0] } else { group[*y1] };
+                Sample::Yuv([luma, group[*u], group[*v]])
+            }
+            Source::SemiPlanarYuv { u_first } => {
+                let luma = planes[0][y * strides[0] + x];
+                let chroma = &planes[1][(y / v_sub) * strides[1] + (x / h_sub) * 2..];
+                if *u_first {
+                    Sample::Yuv([luma, chroma[0], chroma[1]])
+                } else {
+                    Sample::Yuv([luma, chroma[1], chroma[0]])
+                }
+            }
+            Source::PlanarYuv { u_plane, v_plane } => {
+                let luma = planes[0][y * strides[0] + x];
+                let u = planes[*u_plane][(y / v_sub) * strides[*u_plane] + x / h_sub];
+                let v = planes[*v_plane][(y / v_sub) * strides[*v_plane] + x / h_sub];
+                Sample::Yuv([luma, u, v])
+            }
+            Source::Rgb {
+                bytes_per_pixel,
+                r,
+                g,
+                b,
+                a,
+            } => {
+                let pixel = &planes[0][y * strides[0] + x * bytes_per_pixel..];
+                let alpha = a.map(|a| pixel[a]).unwrap_or(255);
+                Sample::Rgba([pixel[*r], pixel[*g], pixel[*b], alpha])
+            }
+            Source::Grey => Sample::Grey(planes[0][y * strides[0] + x]),
+        }
+    }
+}
+
+enum Sample {
+    Yuv([u8; 3]),
+    Rgba([u8; 4]),
+    Grey(u8),
+}
+
+impl Sample {
+    fn write(&self, out: &mut [u8], output: OutputFormat, coefficients: &YuvCoefficients) {
+        let rgba = match self {
+            Sample::Yuv(yuv) => {
+                if output == OutputFormat::Gray8 {
+                    out[0] = coefficients.luma(yuv[0]);
+                    return;
+                }
+
+                coefficients.to_rgba(*yuv)
+            }
+            Sample::Rgba(rgba) => *rgba,
+            Sample::Grey(v) => [*v, *v, *v, 255],
+        };
+
+        match output {
+            OutputFormat::Rgb8 => out.copy_from_slice(&rgba[0..3]),
+            OutputFormat::Rgba8 => out.copy_from_slice(&rgba),
+            OutputFormat::Gray8 => {
+                // Rec601 luma weights in 8.8 fixed point.
+                let luma = 77 * (rgba[0] as u32) + 150 * (rgba[1] as u32) + 29 * (rgba[2] as u32);
+                out[0] = (luma >> 8) as u8;
+            }
+        }
+    }
+}
+
+/// YUV to RGB conversion coefficients in 16.16 fixed point.
+struct YuvCoefficients {
+    y_offset: i32,
+    y: i32,
+    r_v: i32,
+    g_u: i32,
+    g_v: i32,
+    b_u: i32,
+}
+
+impl YuvCoefficients {
+    fn new(matrix: YuvMatrix, range: YuvRange) -> Self {
+        let (kr, kb) = match matrix {
+            YuvMatrix::Rec601 => (0.299, 0.114),
+            YuvMatrix::Rec709 => (0.2126, 0.0722),
+        };
+        let kg = 1.0 - kr - kb;
+
+        let (y_offset, y_scale, c_scale) = match range {
+            YuvRange::Limited => (16, 255.0 / 219.0, 255.0 / 224.0),
+            YuvRange::Full => (0, 1.0, 1.0),
+        };
+
+        let fixed = |v: f64| (v * 65536.0).round() as i32;
+
+        Self {
+            y_offset,
+            y: fixed(y_scale),
+            r_v: fixed(2.0 * (1.0 - kr) * c_scale),
+            g_u: fixed(2.0 * (1.0 - kb) * kb / kg * c_scale),
+            g_v: fixed(2.0 * (1.0 - kr) * kr / kg * c_scale),
+            b_u: fixed(2.0 * (1.0 - kb) * c_scale),
+        }
+    }
+
+    fn luma(&self, y: u8) -> u8 {
+        clamp_fixed((y as i32 - self.y_offset) * self.y)
+    }
+
+    fn to_rgba(&self, [y, u, v]: [u8; 3]) -> [u8; 4] {
+        let y = (y as i32 - self.y_offset) * self.y;
+        let u = u as i32 - 128;
+        let v = v as i32 - 128;
+
+        [
+            clamp_fixed(y + self.r_v * v),
+            clamp_fixed(y - self.g_u * u - self.g_v * v),
+            clamp_fixed(y + self.b_u * u),
+            255,
+        ]
+    }
+}
+
+fn clamp_fixed(v: i32) -> u8 {
+    ((v + (1 << 15)) >> 16).clamp(0, 255) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(pixel_format: PixelFormat, width: u32, height: u32, stride: u32) -> FrameLayout {
+        FrameLayout {
+            pixel_format,
+            size: Size { width, height },
+            stride,
+            yuv_matrix: YuvMatrix::Rec601,
+            yuv_range: YuvRange::Full,
+        }
+    }
+
+    /// 4x2 frame used for the YUV 4:2:0 tests. The left 2x2 block has neutral
+    /// chroma and the right one has U = 64 and V = 192.
+    const LUMA: [[u8; 4]; 2] = [[10, 20, 30, 40], [50, 60, 70, 80]];
+
+    /// Expected Rec601 full range RGB output for the frame above.
+    const EXPECTED_RGB: [[u8; 3]; 8] = [
+        [10, 10, 10],
+        [20, 20, 20],
+        [120, 6, 0],
+        [130, 16, 0],
+        [50, 50, 50],
+        [60, 60, 60],
+        [160, 46, 0],
+        [170, 56, 0],
+    ];
+
+    fn expected_rgb() -> Vec<u8> {
+        EXPECTED_RGB.concat()
+    }
+
+    /// Builds a contiguous semi-planar 4:2:0 frame with padding at the end of
+    /// each line.
+    fn semi_planar(u_first: bool, stride: usize) -> Vec<u8> {
+        let mut data = vec![0xaa; stride * 3];
+        for (y, row) in LUMA.iter().enumerate() {
+            data[y * stride..y * stride + 4].copy_from_slice(row);
+        }
+
+        let chroma = if u_first {
+            [128, 128, 64, 192]
+        } else {
+            [128, 128, 192, 64]
+        };
+        data[2 * stride..2 * stride + 4].copy_from_slice(&chroma);
+        data
+    }
+
+    #[test]
+    fn nv12_to_rgb8() {
+        let data = semi_planar(true, 6);
+        let out = convert(
+            &[&data],
+            &layout(formats::NV12, 4, 2, 6),
+            OutputFormat::Rgb8,
+        )
+        .unwrap();
+        assert_eq!(out, expected_rgb());
+    }
+
+    #[test]
+    fn nv21_to_rgb8() {
+        let data = semi_planar(false, 6);
+        let out = convert(
+            &[&data],
+            &layout(formats::NV21, 4, 2, 6),
+            OutputFormat::Rgb8,
+        )
+        .unwrap();
+        assert_eq!(out, expected_rgb());
+    }
+
+    #[test]
+    fn nv12_to_rgba8_and_grey() {
+        let data = semi_planar(true, 4);
+        let format = layout(formats::NV12, 4, 2, 0);
+
+        let out = convert(&[&data], &format, OutputFormat::Rgba8).unwrap();
+        let expected = EXPECTED_RGB
+            .iter()
+            .flat_map(|[r, g, b]| [*r, *g, *b, 255])
+            .collect::<Vec<_>>();
+        assert_eq!(out, expected);
+
+        let out = convert(&[&data], &format, OutputFormat::Gray8).unwrap();
+        assert_eq!(out, LUMA.concat());
+    }
+
+    #[test]
+    fn yuv420_separate_planes() {
+        let luma = LUMA.concat();
+        let u = [128, 64];
+        let v = [128, 192];
+
+        let out = convert(
+            &[&luma, &u, &v],
+            &layout(formats::YUV420, 4, 2, 0),
+            OutputFormat::Rgb8,
+        )
+        .unwrap();
+        assert_eq!(out, expected_rgb());
+    }
+
+    #[test]
+    fn yuv420_contiguous() {
+        let mut data = LUMA.concat();
+        data.extend_from_slice(&[128, 64, 128, 192]);
+
+        let out = convert(
+            &[&data],
+            &layout(formats::YUV420, 4, 2, 0),
+            OutputFormat::Rgb8,
+        )
+        .unwrap();
+        assert_eq!(out, expected_rgb());
+    }
+
+    #[test]
+    fn packed_yuv422() {
+        // Two pixels with Y = 100 and 200, U = 192 and V = 64.
+        let expected = vec![10, 124, 213, 110, 224, 255];
+
+        let yuyv = [100, 192, 200, 64];
+        let out = convert(
+            &[&yuyv],
+            &layout(formats::YUYV, 2, 1, 0),
+            OutputFormat::Rgb8,
+        )
+        .unwrap();
+        assert_eq!(out, expected);
+
+        let uyvy = [192, 100, 64, 200];
+        let out = convert(
+            &[&uyvy],
+            &layout(formats::UYVY, 2, 1, 0),
+            OutputFormat::Rgb8,
+        )
+        .unwrap();
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn limited_range_grey() {
+        let yuyv = [16, 128, 235, 128];
+        let mut format = layout(formats::YUYV, 2, 1, 0);
+        format.yuv_range = YuvRange::Limited;
+
+        let out = convert(&[&yuyv], &format, OutputFormat::Gray8).unwrap();
+        assert_eq!(out, vec![0, 255]);
+    }
+
+    #[test]
+    fn rgb_formats() {
+        // RGB888 is stored as B, G, R in memory and BGR888 as R, G, B.
+        let data = [30, 20, 10, 60, 50, 40];
+
+        let out = convert(
+            &[&data],
+            &layout(formats::RGB888, 2, 1, 0),
+            OutputFormat::Rgba8,
+        )
+        .unwrap();
+        assert_eq!(out, vec![10, 20, 30, 255, 40, 50, 60, 255]);
+
+        let out = convert(
+            &[&data],
+            &layout(formats::BGR888, 2, 1, 0),
+            OutputFormat::Rgb8,
+        )
+        .unwrap();
+        assert_eq!(out, vec![30, 20, 10, 60, 50, 40]);
+
+        let out = convert(
+            &[&data],
+            &layout(formats::RGB888, 2, 1, 0),
+            OutputFormat::Gray8,
+        )
+        .unwrap();
+        assert_eq!(out, vec![18, 48]);
+    }
+
+    #[test]
+    fn xrgb8888_ignores_padding_byte() {
+        // Stored as B, G, R, X with the second line padded to 12 bytes.
+        let mut data = vec![0; 12 + 8];
+        data[0..8].copy_from_slice(&[3, 2, 1, 99, 6, 5, 4, 99]);
+        data[12..20].copy_from_slice(&[9, 8, 7, 99, 255, 255, 255, 99]);
+        let format = layout(formats::XRGB8888, 2, 2, 12);
+
+        let out = convert(&[&data], &format, OutputFormat::Rgba8).unwrap();
+        assert_eq!(
+            out,
+            vec![1, 2, 3, 255, 4, 5, 6, 255, 7, 8, 9, 255, 255, 255, 255, 255]
+        );
+
+        let out = convert(&[&data], &format, OutputFormat::Rgb8).unwrap();
+        assert_eq!(out, vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 255, 255, 255]);
+    }
+
+    #[test]
+    fn errors() {
+        let data = [0u8; 16];
+
+        assert_eq!(
+            convert(
+                &[&data],
+                &layout(formats::NV12, 0, 2, 0),
+                OutputFormat::Rgb8
+            ),
+            Err(ConvertError::EmptyFrame(Size {
+                width: 0,
+                height: 2
+            }))
+        );
+        assert!(matches!(
+            convert(
+                &[&data],
+                &layout(formats::NV12, 4, 4, 0),
+                OutputFormat::Rgb8
+            ),
+            Err(ConvertError::BufferTooSmall { plane: 1, .. })
+        ));
+        assert!(matches!(
+            convert(
+                &[&data],
+                &layout(formats::MJPEG, 4, 2, 0),
+                OutputFormat::Rgb8
+            ),
+            Err(ConvertError::UnsupportedFormat(_))
+        ));
+    }
+}
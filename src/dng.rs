@@ -0,0 +1,656 @@
+//! Writing of raw frames to DNG files.
+//!
+//! The raw samples are stored uncompressed at 16 bits per sample along with
+//! the metadata needed by raw processors to develop the image (CFA layout,
+//! black/white levels and colour matrices).
+
+use std::fmt;
+use std::io::{self, Write};
+
+use crate::bayer::RawImage;
+use crate::control_list::ControlList;
+use crate::controls;
+use crate::convert::ConvertError;
+use crate::frame_buffer::FrameBuffer;
+use crate::pixel_format_info::BayerOrder;
+use crate::properties;
+use crate::stream_configuration::{Size, StreamConfigurationOpaque};
+
+/// Information about the capture which is stored alongside the raw image.
+///
+/// All fields are optional and are omitted from the DNG (or replaced with
+/// neutral defaults) if not known.
+#[derive(Debug, Clone, Default)]
+pub struct DngMetadata {
+    /// Sensor model name.
+    pub model: Option<String>,
+
+    /// Physical size of each pixel in nanometers.
+    pub unit_cell_size: Option<Size>,
+
+    /// Exposure time in microseconds.
+    pub exposure_time: Option<i32>,
+
+    pub analogue_gain: Option<f32>,
+
+    /// Red and blue gains applied by the ISP's white balance.
+    pub colour_gains: Option<[f32; 2]>,
+
+    /// Row major 3x3 matrix converting white balanced camera RGB to sRGB.
+    pub colour_correction_matrix: Option<[f32; 9]>,
+
+    /// Black levels in the order R, Gr, Gb, B scaled to a 16-bit range.
+    pub black_levels: Option<[i32; 4]>,
+}
+
+impl DngMetadata {
+    /// Collects the metadata from a completed request's metadata and the
+    /// properties of the camera which captured it.
+    pub fn from_controls(metadata: &ControlList, properties: &ControlList) -> Self {
+        Self {
+            model: properties.get(properties::Model),
+            unit_cell_size: properties.get(properties::UnitCellSize),
+            exposure_time: metadata.get(controls::ExposureTime),
+            analogue_gain: metadata.get(controls::AnalogueGain),
+            colour_gains: metadata.get(controls::ColourGains),
+            colour_correction_matrix: metadata.get(controls::ColourCorrectionMatrix),
+            black_levels: metadata.get(controls::SensorBlackLevels),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum DngError {
+    /// The frame couldn't be unpacked (e.g. it isn't in a raw format).
+    Convert(ConvertError),
+    Io(io::Error),
+}
+
+impl fmt::Display for DngError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DngError::Convert(e) => write!(f, "Failed to unpack raw frame: {}", e),
+            DngError::Io(e) => write!(f, "Failed to write DNG: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DngError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DngError::Convert(e) => Some(e),
+            DngError::Io(e) => Some(e),
+        }
+    }
+}
+
+impl From<ConvertError> for DngError {
+    fn from(e: ConvertError) -> Self {
+        DngError::Convert(e)
+    }
+}
+
+impl From<io::Error> for DngError {
+    fn from(e: io::Error) -> Self {
+        DngError::Io(e)
+    }
+}
+
+/// Writes the raw frame most recently captured into a buffer as a DNG.
+///
+/// The buffer's memory must be mapped using FrameBuffer::map_memory() and
+/// 'config' must be the configuration of the raw stream which produced it.
+pub fn write_dng<W: Write>(
+    writer: W,
+    buffer: &FrameBuffer,
+    config: &StreamConfigurationOpaque,
+    metadata: &ControlList,
+    properties: &ControlList,
+) -> Result<(), DngError> {
+    let planes = buffer.plane_memory().ok_or(ConvertError::NotMapped)?;
+    let data = planes.first().ok_or(ConvertError::BufferTooSmall {
+        plane: 0,
+        expected: 1,
+        actual: 0,
+    })?;
+
+    let image = RawImage::unpack(data, config.pixel_format(), config.size(), config.stride())?;
+
+    write_raw_image(
+        writer,
+        &image,
+        &DngMetadata::from_controls(metadata, properties),
+    )?;
+    Ok(())
+}
+
+/// Writes an unpacked raw image as a DNG.
+///
+/// The samples should not have had any black level or white balance
+/// processing applied.
+pub fn write_raw_image<W: Write>(
+    mut writer: W,
+    image: &RawImage,
+    metadata: &DngMetadata,
+) -> io::Result<()> {
+    let width = image.size.width;
+    let height = image.size.height;
+    let image_size = (width as usize) * (height as usize) * 2;
+
+    let model = metadata.model.as_deref().unwrap_or("Unknown");
+
+    let mut ifd = Ifd::default();
+    ifd.long(NEW_SUBFILE_TYPE, &[0]);
+    ifd.long(IMAGE_WIDTH, &[width]);
+    ifd.long(IMAGE_LENGTH, &[height]);
+    ifd.short(BITS_PER_SAMPLE, &[16]);
+    ifd.short(COMPRESSION, &[1]);
+    ifd.ascii(MAKE, "libcamera");
+    ifd.ascii(MODEL, model);
+    // Actual value is filled in once the layout is known.
+    ifd.long(STRIP_OFFSETS, &[0]);
+    ifd.short(ORIENTATION, &[1]);
+    ifd.short(SAMPLES_PER_PIXEL, &[1]);
+    ifd.long(ROWS_PER_STRIP, &[height]);
+    ifd.long(STRIP_BYTE_COUNTS, &[image_size as u32]);
+    ifd.short(PLANAR_CONFIGURATION, &[1]);
+    ifd.ascii(
+        SOFTWARE,
+        concat!("libcamera-rs ", env!("CARGO_PKG_VERSION")),
+    );
+    ifd.byte(DNG_VERSION, &[1, 4, 0, 0]);
+    ifd.byte(DNG_BACKWARD_VERSION, &[1, 1, 0, 0]);
+    ifd.ascii(UNIQUE_CAMERA_MODEL, model);
+
+    let black_levels = metadata.black_levels.unwrap_or([0; 4]);
+    let shift = 16 - image.bit_depth;
+    let black_level =
+        |x: usize, y: usize| (black_levels[image.channel_at(x, y) as usize].max(0) as u32) >> shift;
+
+    if let Some(order) = image.bayer_order {
+        ifd.short(PHOTOMETRIC_INTERPRETATION, &[PHOTOMETRIC_CFA]);
+        ifd.short(CFA_REPEAT_PATTERN_DIM, &[2, 2]);
+        ifd.byte(CFA_PATTERN, &cfa_pattern(order));
+        ifd.short(BLACK_LEVEL_REPEAT_DIM, &[2, 2]);
+        ifd.long(
+            BLACK_LEVEL,
+            &[
+                black_level(0, 0),
+                black_level(1, 0),
+                black_level(0, 1),
+                black_level(1, 1),
+            ],
+        );
+    } else {
+        ifd.short(PHOTOMETRIC_INTERPRETATION, &[PHOTOMETRIC_LINEAR_RAW]);
+        ifd.short(BLACK_LEVEL_REPEAT_DIM, &[1, 1]);
+        ifd.long(BLACK_LEVEL, &[black_level(0, 0)]);
+    }
+
+    ifd.long(WHITE_LEVEL, &[image.white_level() as u32]);
+
+    if image.bayer_order.is_some() {
+        let [red_gain, blue_gain] = metadata.colour_gains.unwrap_or([1.0, 1.0]);
+        let ccm = metadata
+            .colour_correction_matrix
+            .map(|m| m.map(|v| v as f64))
+            .unwrap_or(IDENTITY);
+
+        // Camera RGB is converted to XYZ by applying the white balance gains,
+        // the colour correction matrix to get sRGB and then the standard sRGB
+        // to XYZ matrix. ColorMatrix1 is the inverse of this.
+        #[rustfmt::skip]
+        let white_balance = [
+            red_gain as f64, 0.0, 0.0,
+            0.0, 1.0, 0.0,
+            0.0, 0.0, blue_gain as f64,
+        ];
+        let camera_to_xyz = multiply(&multiply(&SRGB_TO_XYZ, &ccm), &white_balance);
+        let colour_matrix = invert(&camera_to_xyz).unwrap_or(IDENTITY);
+
+        ifd.srational(COLOR_MATRIX_1, &colour_matrix.map(to_srational));
+        ifd.short(CALIBRATION_ILLUMINANT_1, &[ILLUMINANT_D65]);
+        ifd.rational(
+            AS_SHOT_NEUTRAL,
+            &[
+                to_rational(1.0 / red_gain as f64),
+                to_rational(1.0),
+                to_rational(1.0 / blue_gain as f64),
+            ],
+        );
+    }
+
+    if let Some(exposure_time) = metadata.exposure_time {
+        ifd.rational(EXPOSURE_TIME, &[(exposure_time.max(0) as u32, 1_000_000)]);
+    }
+
+    if let Some(gain) = metadata.analogue_gain {
+        ifd.short(
+            ISO_SPEED_RATINGS,
+            &[(gain * 100.0).round().clamp(0.0, 65535.0) as u16],
+        );
+    }
+
+    if let Some(cell_size) = metadata.unit_cell_size {
+        if cell_size.width > 0 && cell_size.height > 0 {
+            // Pixels per centimeter.
+            ifd.rational(FOCAL_PLANE_X_RESOLUTION, &[(10_000_000, cell_size.width)]);
+            ifd.rational(FOCAL_PLANE_Y_RESOLUTION, &[(10_000_000, cell_size.height)]);
+            ifd.short(FOCAL_PLANE_RESOLUTION_UNIT, &[3]);
+        }
+    }
+
+    let (header, image_offset) = ifd.serialize();
+    assert_eq!(header.len(), image_offset as usize);
+
+    writer.write_all(&header)?;
+
+    let mut line = Vec::with_capacity((width as usize) * 2);
+    if width > 0 {
+        for samples in image.data.chunks_exact(width as usize) {
+            line.clear();
+            for sample in samples {
+                line.extend_from_slice(&sample.to_le_bytes());
+            }
+            writer.write_all(&line)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn cfa_pattern(order: BayerOrder) -> [u8; 4] {
+    const R: u8 = 0;
+    const G: u8 = 1;
+    const B: u8 = 2;
+
+    match order {
+        BayerOrder::Rggb => [R, G, G, B],
+        BayerOrder::Grbg => [G, R, B, G],
+        BayerOrder::Gbrg => [G, B, R, G],
+        BayerOrder::Bggr => [B, G, G, R],
+    }
+}
+
+type Matrix = [f64; 9];
+
+const IDENTITY: Matrix = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0];
+
+/// Linear sRGB (D65) to CIE XYZ.
+#[rustfmt::skip]
+const SRGB_TO_XYZ: Matrix = [
+    0.4124564, 0.3575761, 0.1804375,
+    0.2126729, 0.7151522, 0.0721750,
+    0.0193339, 0.1191920, 0.9503041,
+];
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut out = [0.0; 9];
+    for i in 0..3 {
+        for j in 0..3 {
+            out[i * 3 + j] = (0..3).map(|k| a[i * 3 + k] * b[k * 3 + j]).sum();
+        }
+    }
+    out
+}
+
+fn invert(m: &Matrix) -> Option<Matrix> {
+    let cofactor = |r0: usize, r1: usize, c0: usize, c1: usize| {
+        m[r0 * 3 + c0] * m[r1 * 3 + c1] - m[r0 * 3 + c1] * m[r1 * 3 + c0]
+    };
+
+    let det =
+        m[0] * cofactor(1, 2, 1, 2) - m[1] * cofactor(1, 2, 0, 2) + m[2] * cofactor(1, 2, 0, 1);
+    if det.abs() < 1e-12 {
+        return None;
+    }
+
+    #[rustfmt::skip]
+    let adjugate = [
+        cofactor(1, 2, 1, 2), -cofactor(0, 2, 1, 2), cofactor(0, 1, 1, 2),
+        -cofactor(1, 2, 0, 2), cofactor(0, 2, 0, 2), -cofactor(0, 1, 0, 2),
+        cofactor(1, 2, 0, 1), -cofactor(0, 2, 0, 1), cofactor(0, 1, 0, 1),
+    ];
+
+    Some(adjugate.map(|v| v / det))
+}
+
+fn to_rational(v: f64) -> (u32, u32) {
+    (
+        (v * 1_000_000.0).round().clamp(0.0, u32::MAX as f64) as u32,
+        1_000_000,
+    )
+}
+
+fn to_srational(v: f64) -> (i32, i32) {
+    ((v * 10_000.0).round() as i32, 10_000)
+}
+
+// TIFF/EP, EXIF and DNG tags used above.
+const NEW_SUBFILE_TYPE: u16 = 254;
+const IMAGE_WIDTH: u16 = 256;
+const IMAGE_LENGTH: u16 = 257;
+const BITS_PER_SAMPLE: u16 = 258;
+const COMPRESSION: u16 = 259;
+const PHOTOMETRIC_INTERPRETATION: u16 = 262;
+const MAKE: u16 = 271;
+const MODEL: u16 = 272;
+const STRIP_OFFSETS: u16 = 273;
+const ORIENTATION: u16 = 274;
+const SAMPLES_PER_PIXEL: u16 = 277;
+const ROWS_PER_STRIP: u16 = 278;
+const STRIP_BYTE_COUNTS: u16 = 279;
+const PLANAR_CONFIGURATION: u16 = 284;
+const SOFTWARE: u16 = 305;
+const CFA_REPEAT_PATTERN_DIM: u16 = 33421;
+const CFA_PATTERN: u16 = 33422;
+const EXPOSURE_TIME: u16 = 33434;
+const ISO_SPEED_RATINGS: u16 = 34855;
+const FOCAL_PLANE_X_RESOLUTION: u16 = 41486;
+const FOCAL_PLANE_Y_RESOLUTION: u16 = 41487;
+const FOCAL_PLANE_RESOLUTION_UNIT: u16 = 41488;
+const DNG_VERSION: u16 = 50706;
+const DNG_BACKWARD_VERSION: u16 = 50707;
+const UNIQUE_CAMERA_MODEL: u16 = 50708;
+const BLACK_LEVEL_REPEAT_DIM: u16 = 50713;
+const BLACK_LEVEL: u16 = 50714;
+const WHITE_LEVEL: u16 = 50717;
+const COLOR_MATRIX_1: u16 = 50721;
+const AS_SHOT_NEUTRAL: u16 = 50728;
+const CALIBRATION_ILLUMINANT_1: u16 = 50778;
+
+const PHOTOMETRIC_CFA: u16 = 32803;
+const PHOTOMETRIC_LINEAR_RAW: u16 = 34892;
+const ILLUMINANT_D65: u16 = 21;
+
+// TIFF field types.
+const TYPE_BYTE: u16 = 1;
+const TYPE_ASCII: u16 = 2;
+const TYPE_SHORT: u16 = 3;
+const TYPE_LONG: u16 = 4;
+const TYPE_RATIONAL: u16 = 5;
+const TYPE_SRATIONAL: u16 = 10;
+
+struct IfdEntry {
+    tag: u16,
+    typ: u16,
+    count: u32,
+    /// Little endian encoded values.
+    data: Vec<u8>,
+}
+
+/// Builder for a little endian TIFF file containing a single IFD followed by
+/// a single strip of image data.
+#[derive(Default)]
+struct Ifd {
+    entries: Vec<IfdEntry>,
+}
+
+impl Ifd {
+    fn add(&mut self, tag: u16, typ: u16, count: usize, data: Vec<u8>) {
+        self.entries.push(IfdEntry {
+            tag,
+            typ,
+            count: count as u32,
+            data,
+        });
+    }
+
+    fn byte(&mut self, tag: u16, values: &[u8]) {
+        self.add(tag, TYPE_BYTE, values.len(), values.to_vec());
+    }
+
+    fn ascii(&mut self, tag: u16, value: &str) {
+        let mut data = value.as_bytes().to_vec();
+        data.push(0);
+        self.add(tag, TYPE_ASCII, data.len(), data);
+    }
+
+    fn short(&mut self, tag: u16, values: &[u16]) {
+        let data = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        self.add(tag, TYPE_SHORT, values.len(), data);
+    }
+
+    fn long(&mut self, tag: u16, values: &[u32]) {
+        let data = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        self.add(tag, TYPE_LONG, values.len(), data);
+    }
+
+    fn rational(&mut self, tag: u16, values: &[(u32, u32)]) {
+        let data = values
+            .iter()
+            .flat_map(|(n, d)| n.to_le_bytes().into_iter().chain(d.to_le_bytes()))
+            .collect();
+        self.add(tag, TYPE_RATIONAL, values.len(), data);
+    }
+
+    fn srational(&mut self, tag: u16, values: &[(i32, i32)]) {
+        let data = values
+            .iter()
+            .flat_map(|(n, d)| n.to_le_bytes().into_iter().chain(d.to_le_bytes()))
+            .collect();
+        self.add(tag, TYPE_SRATIONAL, values.len(), data);
+    }
+
+    /// Serializes the TIFF header, IFD and any out of line values.
+    ///
+    /// Returns the serialized data and the offset at which the image data
+    /// should immediately follow it.
+    fn serialize(mut self) -> (Vec<u8>, u32) {
+        self.entries.sort_by_key(|e| e.tag);
+
+        const HEADER_SIZE: usize = 8;
+        let ifd_size = 2 + self.entries.len() * 12 + 4;
+
+        // Values larger than 4 bytes are stored after the IFD.
+        let mut extra_size = 0;
+        for entry in &self.entries {
+            if entry.data.len() > 4 {
+                extra_size += (entry.data.len() + 1) & !1;
+            }
+        }
+
+        let image_offset = (HEADER_SIZE + ifd_size + extra_size) as u32;
+
+        for entry in &mut self.entries {
+            if entry.tag == STRIP_OFFSETS {
+                entry.data = image_offset.to_le_bytes().to_vec();
+            }
+        }
+
+        let mut out = Vec::with_capacity(image_offset as usize);
+        out.extend_from_slice(b"II");
+        out.extend_from_slice(&42u16.to_le_bytes());
+        out.extend_from_slice(&(HEADER_SIZE as u32).to_le_bytes());
+
+        out.extend_from_slice(&(self.entries.len() as u16).to_le_bytes());
+
+        let mut extra = vec![];
+        let extra_offset = HEADER_SIZE + ifd_size;
+
+        for entry in &self.entries {
+            out.extend_from_slice(&entry.tag.to_le_bytes());
+            out.extend_from_slice(&entry.typ.to_le_bytes());
+            out.extend_from_slice(&entry.count.to_le_bytes());
+
+            if entry.data.len() <= 4 {
+                let mut value = [0u8; 4];
+                value[..entry.data.len()].copy_from_slice(&entry.data);
+                out.extend_from_slice(&value);
+            } else {
+                out.extend_from_slice(&((extra_offset + extra.len()) as u32).to_le_bytes());
+                extra.extend_from_slice(&entry.data);
+                if extra.len() % 2 != 0 {
+                    extra.push(0);
+                }
+            }
+        }
+
+        // No more IFDs.
+        out.extend_from_slice(&0u32.to_le_bytes());
+
+        out.extend_from_slice(&extra);
+
+        (out, image_offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    /// Type, count and value bytes of each entry in the first IFD.
+    struct ParsedTiff {
+        data: Vec<u8>,
+        entries: HashMap<u16, (u16, u32, Vec<u8>)>,
+    }
+
+    fn read_u16(data: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes([data[offset], data[offset + 1]])
+    }
+
+    fn read_u32(data: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn parse(data: Vec<u8>) -> ParsedTiff {
+        assert_eq!(&data[0..4], b"II\x2a\x00");
+        let ifd = read_u32(&data, 4) as usize;
+
+        let mut entries = HashMap::new();
+        let count = read_u16(&data, ifd) as usize;
+        for i in 0..count {
+            let entry = ifd + 2 + i * 12;
+            let tag = read_u16(&data, entry);
+            let typ = read_u16(&data, entry + 2);
+            let count = read_u32(&data, entry + 4);
+
+            let size = match typ {
+                TYPE_BYTE | TYPE_ASCII => 1,
+                TYPE_SHORT => 2,
+                TYPE_LONG => 4,
+                TYPE_RATIONAL | TYPE_SRATIONAL => 8,
+                _ => panic!("unexpected type {}", typ),
+            } * count as usize;
+
+            let offset = if size <= 4 {
+                entry + 8
+            } else {
+                read_u32(&data, entry + 8) as usize
+            };
+            entries.insert(tag, (typ, count, data[offset..offset + size].to_vec()));
+        }
+
+        // Only a single IFD is written.
+        assert_eq!(read_u32(&data, ifd + 2 + count * 12), 0);
+
+        ParsedTiff { data, entries }
+    }
+
+    impl ParsedTiff {
+        fn get(&self, tag: u16, typ: u16) -> &[u8] {
+            let (entry_typ, _, value) = &self.entries[&tag];
+            assert_eq!(*entry_typ, typ, "type of tag {}", tag);
+            value
+        }
+
+        fn longs(&self, tag: u16) -> Vec<u32> {
+            self.get(tag, TYPE_LONG)
+                .chunks_exact(4)
+                .map(|v| read_u32(v, 0))
+                .collect()
+        }
+
+        fn srationals(&self, tag: u16) -> Vec<f64> {
+            self.get(tag, TYPE_SRATIONAL)
+                .chunks_exact(8)
+                .map(|v| read_u32(v, 0) as i32 as f64 / read_u32(v, 4) as i32 as f64)
+                .collect()
+        }
+    }
+
+    fn write(image: &RawImage, metadata: &DngMetadata) -> ParsedTiff {
+        let mut out = vec![];
+        write_raw_image(&mut out, image, metadata).unwrap();
+        parse(out)
+    }
+
+    fn test_image() -> RawImage {
+        RawImage {
+            size: Size {
+                width: 4,
+                height: 2,
+            },
+            bayer_order: Some(BayerOrder::Grbg),
+            bit_depth: 10,
+            data: (0..8).map(|v| v * 100).collect(),
+        }
+    }
+
+    #[test]
+    fn layout_and_image_data() {
+        let image = test_image();
+        let tiff = write(&image, &DngMetadata::default());
+
+        assert_eq!(tiff.longs(IMAGE_WIDTH), vec![4]);
+        assert_eq!(tiff.longs(IMAGE_LENGTH), vec![2]);
+        assert_eq!(tiff.longs(ROWS_PER_STRIP), vec![2]);
+        assert_eq!(tiff.longs(WHITE_LEVEL), vec![1023]);
+
+        let offset = tiff.longs(STRIP_OFFSETS)[0] as usize;
+        let size = tiff.longs(STRIP_BYTE_COUNTS)[0] as usize;
+        assert_eq!(size, 4 * 2 * 2);
+        assert_eq!(tiff.data.len(), offset + size);
+
+        let samples = tiff.data[offset..]
+            .chunks_exact(2)
+            .map(|v| read_u16(v, 0))
+            .collect::<Vec<_>>();
+        assert_eq!(samples, image.data);
+    }
+
+    #[test]
+    fn cfa_and_black_level() {
+        let metadata = DngMetadata {
+            // R, Gr, Gb, B of 64, 65, 66 and 67 in 10 bits.
+            black_levels: Some([64 << 6, 65 << 6, 66 << 6, 67 << 6]),
+            ..Default::default()
+        };
+        let tiff = write(&test_image(), &metadata);
+
+        assert_eq!(
+            tiff.get(PHOTOMETRIC_INTERPRETATION, TYPE_SHORT),
+            &PHOTOMETRIC_CFA.to_le_bytes()
+        );
+        assert_eq!(tiff.get(CFA_PATTERN, TYPE_BYTE), &[1, 0, 2, 1]);
+        assert_eq!(tiff.longs(BLACK_LEVEL), vec![65, 64, 67, 66]);
+    }
+
+    #[test]
+    fn colour_matrix() {
+        let metadata = DngMetadata {
+            colour_gains: Some([2.0, 1.5]),
+            ..Default::default()
+        };
+        let tiff = write(&test_image(), &metadata);
+
+        // With no colour correction, ColorMatrix1 is the XYZ to sRGB matrix
+        // followed by the inverse of the white balance gains.
+        #[rustfmt::skip]
+        let xyz_to_srgb = [
+            3.2404542, -1.5371385, -0.4985314,
+            -0.9692660, 1.8760108, 0.0415560,
+            0.0556434, -0.2040259, 1.0572252,
+        ];
+        let gains = [2.0, 1.0, 1.5];
+
+        let matrix = tiff.srationals(COLOR_MATRIX_1);
+        assert_eq!(matrix.len(), 9);
+        for (i, value) in matrix.iter().enumerate() {
+            let expected = xyz_to_srgb[i] / gains[i / 3];
+            assert!((value - expected).abs() < 1e-3, "{}: {}", i, value);
+        }
+    }
+}
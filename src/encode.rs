@@ -0,0 +1,303 @@
+//! Conversion of captured frames to images which can be encoded as JPEG/PNG.
+//!
+//! Only available with the "image" feature.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::{DynamicImage, GrayImage, ImageFormat, RgbImage, RgbaImage};
+
+use crate::convert::{self, ConvertError, FrameLayout, OutputFormat};
+#[cfg(feature = "mjpeg")]
+use crate::convert::{YuvMatrix, YuvRange};
+use crate::formats;
+use crate::frame_buffer::FrameBuffer;
+#[cfg(feature = "mjpeg")]
+use crate::mjpeg::{self, DecodedFormat, DecodedFrame, MjpegError, MjpegOutput};
+use crate::stream_configuration::StreamConfigurationOpaque;
+
+#[derive(Debug)]
+pub enum EncodeError {
+    /// The frame couldn't be converted from the stream's pixel format.
+    Convert(ConvertError),
+
+    /// The buffer's memory is mapped but the camera didn't write any data
+    /// into it.
+    EmptyFrame,
+
+    /// The buffer has more than one plane, so the compressed data can't be
+    /// read.
+    NotContiguous,
+
+    /// The MJPEG frame is incomplete or couldn't be decoded.
+    #[cfg(feature = "mjpeg")]
+    Mjpeg(MjpegError),
+
+    /// Decoding (for MJPEG streams), encoding or writing the image failed.
+    Image(image::ImageError),
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncodeError::Convert(e) => write!(f, "Failed to convert frame: {}", e),
+            EncodeError::EmptyFrame => write!(f, "Frame buffer contains no data"),
+            EncodeError::NotContiguous => write!(f, "Frame buffer has more than one plane"),
+            #[cfg(feature = "mjpeg")]
+            EncodeError::Mjpeg(e) => write!(f, "Failed to decode MJPEG frame: {}", e),
+            EncodeError::Image(e) => write!(f, "Failed to encode image: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for EncodeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EncodeError::Convert(e) => Some(e),
+            EncodeError::EmptyFrame | EncodeError::NotContiguous => None,
+            #[cfg(feature = "mjpeg")]
+            EncodeError::Mjpeg(e) => Some(e),
+            EncodeError::Image(e) => Some(e),
+        }
+    }
+}
+
+#[cfg(feature = "mjpeg")]
+impl From<MjpegError> for EncodeError {
+    fn from(e: MjpegError) -> Self {
+        EncodeError::Mjpeg(e)
+    }
+}
+
+impl From<ConvertError> for EncodeError {
+    fn from(e: ConvertError) -> Self {
+        EncodeError::Convert(e)
+    }
+}
+
+impl From<image::ImageError> for EncodeError {
+    fn from(e: image::ImageError) -> Self {
+        EncodeError::Image(e)
+    }
+}
+
+impl From<std::io::Error> for EncodeError {
+    fn from(e: std::io::Error) -> Self {
+        EncodeError::Image(image::ImageError::IoError(e))
+    }
+}
+
+impl FrameBuffer {
+    /// Converts the frame most recently captured into this buffer into an
+    /// image.
+    ///
+    /// MJPEG frames are decoded (using crate::mjpeg if the "mjpeg" feature is
+    /// enabled, which also handles frames without Huffman tables) and all
+    /// other formats go through the converter in crate::convert. The memory
+    /// must first be mapped using map_memory().
+    pub fn to_image(
+        &self,
+        config: &StreamConfigurationOpaque,
+    ) -> Result<DynamicImage, EncodeError> {
+        if config.pixel_format() == formats::MJPEG {
+            return decode_mjpeg_image(self.mjpeg_data()?);
+        }
+
+        let planes = self.plane_memory().ok_or(ConvertError::NotMapped)?;
+        convert_image(&planes, &FrameLayout::from_stream_config(config))
+    }
+
+    /// Saves the frame most recently captured into this buffer as a JPEG with
+    /// the given quality (1-100).
+    ///
+    /// Frames from MJPEG streams are written without re-encoding (with the
+    /// "mjpeg" feature, missing Huffman tables are inserted and any padding
+    /// after the image is removed).
+    pub fn save_jpeg<P: AsRef<Path>>(
+        &self,
+        config: &StreamConfigurationOpaque,
+        path: P,
+        quality: u8,
+    ) -> Result<(), EncodeError> {
+        if config.pixel_format() == formats::MJPEG {
+            let data = self.mjpeg_data()?;
+            #[cfg(feature = "mjpeg")]
+            let data = mjpeg::repair(data)?;
+
+            let mut file = File::create(path)?;
+            file.write_all(&data)?;
+            return Ok(());
+        }
+
+        // JPEG can't store an alpha channel.
+        let image = DynamicImage::ImageRgb8(self.to_image(config)?.into_rgb8());
+
+        let mut writer = BufWriter::new(File::create(path)?);
+        JpegEncoder::new_with_quality(&mut writer, quality).encode_image(&image)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Saves the frame most recently captured into this buffer as a PNG.
+    pub fn save_png<P: AsRef<Path>>(
+        &self,
+        config: &StreamConfigurationOpaque,
+        path: P,
+    ) -> Result<(), EncodeError> {
+        self.to_image(config)?
+            .save_with_format(path, ImageFormat::Png)?;
+        Ok(())
+    }
+
+    fn mjpeg_data(&self) -> Result<&[u8], EncodeError> {
+        if self.memory().is_none() {
+            return Err(ConvertError::NotMapped.into());
+        }
+
+        // NOTE: The payload is normally much smaller than the buffer so only
+        // the used bytes are passed on.
+        match self.single_plane_memory() {
+            Some([]) => Err(EncodeError::EmptyFrame),
+            Some(data) => Ok(data),
+            None => Err(EncodeError::NotContiguous),
+        }
+    }
+}
+
+/// Converts an uncompressed frame into an image with the same number of
+/// channels (RGB, RGBA or greyscale).
+fn convert_image(planes: &[&[u8]], layout: &FrameLayout) -> Result<DynamicImage, EncodeError> {
+    let output = match layout.pixel_format {
+        formats::R8 => OutputFormat::Gray8,
+        formats::ARGB8888 | formats::ABGR8888 => OutputFormat::Rgba8,
+        _ => OutputFormat::Rgb8,
+    };
+
+    let data = convert::convert(planes, layout, output)?;
+    let size = layout.size;
+
+    // The converter always returns exactly width * height pixels, so
+    // constructing the image buffer can't fail.
+    Ok(match output {
+        OutputFormat::Rgb8 => {
+            DynamicImage::ImageRgb8(RgbImage::from_raw(size.width, size.height, data).unwrap())
+        }
+        OutputFormat::Rgba8 => {
+            DynamicImage::ImageRgba8(RgbaImage::from_raw(size.width, size.height, data).unwrap())
+        }
+        OutputFormat::Gray8 => {
+            DynamicImage::ImageLuma8(GrayImage::from_raw(size.width, size.height, data).unwrap())
+        }
+    })
+}
+
+#[cfg(feature = "mjpeg")]
+fn decode_mjpeg_image(data: &[u8]) -> Result<DynamicImage, EncodeError> {
+    Ok(decoded_image(mjpeg::decode(data, MjpegOutput::Rgb8)?))
+}
+
+#[cfg(feature = "mjpeg")]
+fn decoded_image(frame: DecodedFrame) -> DynamicImage {
+    // The decoder always returns exactly width * height pixels.
+    let (width, height) = (frame.width, frame.height);
+    match frame.format {
+        DecodedFormat::Gray8 => {
+            DynamicImage::ImageLuma8(GrayImage::from_raw(width, height, frame.data).unwrap())
+        }
+        DecodedFormat::Rgb8 => {
+            DynamicImage::ImageRgb8(RgbImage::from_raw(width, height, frame.data).unwrap())
+        }
+        // JPEG uses full range Rec601 (as defined by JFIF).
+        DecodedFormat::Yuv444 => {
+            let data =
+                convert::packed_yuv444_to_rgb(&frame.data, YuvMatrix::Rec601, YuvRange::Full);
+            DynamicImage::ImageRgb8(RgbImage::from_raw(width, height, data).unwrap())
+        }
+    }
+}
+
+#[cfg(not(feature = "mjpeg"))]
+fn decode_mjpeg_image(data: &[u8]) -> Result<DynamicImage, EncodeError> {
+    Ok(image::load_from_memory_with_format(
+        data,
+        ImageFormat::Jpeg,
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convert::{YuvMatrix, YuvRange};
+    use crate::pixel_format::PixelFormat;
+    use crate::stream_configuration::Size;
+
+    fn layout(pixel_format: PixelFormat, width: u32, height: u32) -> FrameLayout {
+        FrameLayout {
+            pixel_format,
+            size: Size { width, height },
+            stride: 0,
+            yuv_matrix: YuvMatrix::Rec601,
+            yuv_range: YuvRange::Full,
+        }
+    }
+
+    #[test]
+    fn converts_nv12_to_rgb_image() {
+        // 2x2 frame with neutral chroma followed by U = 64 and V = 192.
+        let data = [10, 20, 30, 40, 64, 192];
+        let image = convert_image(&[&data], &layout(formats::NV12, 2, 2)).unwrap();
+
+        let image = image.as_rgb8().unwrap();
+        assert_eq!(image.dimensions(), (2, 2));
+        assert_eq!(image.get_pixel(0, 0).0, [100, 0, 0]);
+        assert_eq!(image.get_pixel(1, 1).0, [130, 16, 0]);
+    }
+
+    #[test]
+    fn converts_rgb888_to_rgb_image() {
+        // RGB888 is stored as B, G, R in memory.
+        let data = [3, 2, 1, 30, 20, 10];
+        let image = convert_image(&[&data], &layout(formats::RGB888, 2, 1)).unwrap();
+
+        assert_eq!(image.as_rgb8().unwrap().as_raw(), &[1, 2, 3, 10, 20, 30]);
+    }
+
+    #[test]
+    fn keeps_alpha_and_grey_channels() {
+        let data = [10, 20, 30, 40];
+        let image = convert_image(&[&data], &layout(formats::ARGB8888, 1, 1)).unwrap();
+        assert_eq!(image.as_rgba8().unwrap().as_raw(), &[30, 20, 10, 40]);
+
+        let image = convert_image(&[&data], &layout(formats::R8, 2, 2)).unwrap();
+        assert_eq!(image.as_luma8().unwrap().as_raw(), &data);
+    }
+
+    #[test]
+    fn reports_conversion_errors() {
+        let data = [0; 2];
+        assert!(matches!(
+            convert_image(&[&data], &layout(formats::NV12, 2, 2)),
+            Err(EncodeError::Convert(_))
+        ));
+    }
+
+    #[cfg(feature = "mjpeg")]
+    #[test]
+    fn converts_decoded_yuv_to_rgb() {
+        let frame = DecodedFrame {
+            width: 2,
+            height: 1,
+            format: DecodedFormat::Yuv444,
+            data: vec![128, 128, 128, 30, 64, 192],
+        };
+
+        let image = decoded_image(frame);
+        assert_eq!(
+            image.as_rgb8().unwrap().as_raw(),
+            &[128, 128, 128, 120, 6, 0]
+        );
+    }
+}
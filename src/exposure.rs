@@ -0,0 +1,579 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::camera::Camera;
+use crate::completion::{Completion, CompletionFuture};
+use crate::control_latency::close;
+use crate::controls;
+use crate::request::{Request, RequestStatus};
+
+/// Exposure settings applied by the camera for a frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExposureSettings {
+    pub exposure_time: Duration,
+    pub analogue_gain: f32,
+
+    /// Red and blue white balance gains (if known).
+    pub colour_gains: Option<[f32; 2]>,
+}
+
+/// Drives manual exposure and AE/AWB locking on a running camera.
+///
+/// Like Autofocus, the application must call apply() on every request before
+/// it is enqueued and observe() on every request once it completes.
+#[derive(Clone)]
+pub struct Exposure {
+    state: Arc<Mutex<ExposureState>>,
+    completion: Arc<Mutex<Completion<Option<ExposureSettings>>>>,
+
+    /// Valid ExposureTime range in microseconds.
+    exposure_limits: Option<(i32, i32)>,
+    gain_limits: Option<(f32, f32)>,
+}
+
+#[derive(Default)]
+struct ExposureState {
+    ae_enable: Option<bool>,
+    awb_enable: Option<bool>,
+    settings: Option<ExposureSettings>,
+
+    phase: Phase,
+
+    /// Largest of the camera's max_latency() and the MaxLatency and
+    /// PipelineDepth reported in metadata.
+    latency: u32,
+    last: Option<ExposureSettings>,
+}
+
+/// Controls taken from the state by apply() to be sent with a request.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+struct ExposureControls {
+    ae_enable: Option<bool>,
+    awb_enable: Option<bool>,
+    settings: Option<ExposureSettings>,
+}
+
+/// Values read from the metadata of a completed request.
+#[derive(Default)]
+struct ExposureReport {
+    settings: Option<ExposureSettings>,
+    ae_locked: bool,
+
+    /// Largest of the reported MaxLatency and PipelineDepth.
+    depth: Option<u32>,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+enum Phase {
+    #[default]
+    Idle,
+
+    /// AE/AWB are waiting to be enabled by apply().
+    LockQueued,
+
+    /// AE/AWB were enabled in the request with the given cookie.
+    LockSent {
+        cookie: u64,
+        controls: ExposureControls,
+    },
+
+    /// Waiting for AeLocked to be reported.
+    Converging,
+
+    /// Manual settings are waiting to be sent by apply().
+    Queued { target: ExposureSettings },
+
+    Sent {
+        cookie: u64,
+        target: ExposureSettings,
+        controls: ExposureControls,
+    },
+
+    /// The request with the new settings has completed, but the metadata
+    /// doesn't yet report them.
+    Settling {
+        target: ExposureSettings,
+        frames_left: u32,
+    },
+}
+
+impl Exposure {
+    pub fn new(camera: &Camera) -> Self {
+        let info_map = camera.controls();
+
+        let exposure_limits = info_map
+            .get(controls::ExposureTime)
+            .and_then(|info| info.limits());
+        let gain_limits = info_map
+            .get(controls::AnalogueGain)
+            .and_then(|info| info.limits());
+
+        Self {
+            state: Arc::new(Mutex::new(ExposureState {
+                latency: camera.max_latency(),
+                ..Default::default()
+            })),
+            completion: Completion::new(None),
+            exposure_limits,
+            gain_limits,
+        }
+    }
+
+    /// Disables AE and fixes the exposure time and analogue gain.
+    ///
+    /// The values are clamped to the limits advertised by the camera. The
+    /// future completes with the settings reported in the metadata once they
+    /// match the requested ones, or once the camera's latency has passed
+    /// (as the sensor may not be able to apply exactly the requested values).
+    /// It completes with None if superseded by another call. If the request
+    /// carrying the settings is cancelled, they are sent again with the next
+    /// request passed to apply().
+    pub fn set_manual(&self, exposure_time: Duration, analogue_gain: f32) -> ExposureFuture {
+        let mut exposure_us = exposure_time.as_micros().min(i32::MAX as u128) as i32;
+        if let Some((min, max)) = self.exposure_limits {
+            exposure_us = exposure_us.clamp(min, max);
+        }
+
+        let analogue_gain = match self.gain_limits {
+            Some((min, max)) => analogue_gain.clamp(min, max),
+            None => analogue_gain,
+        };
+
+        let mut state = self.state.lock().unwrap();
+        self.supersede(&mut state);
+        state.queue_manual(ExposureSettings {
+            exposure_time: Duration::from_micros(exposure_us as u64),
+            analogue_gain,
+            colour_gains: None,
+        });
+
+        Completion::future(&self.completion)
+    }
+
+    /// Runs AE/AWB until AE reports that it has converged and then freezes
+    /// the exposure and white balance at the values it converged on.
+    ///
+    /// The future completes with the frozen settings once they are reported
+    /// by the camera, or with None if superseded by another call.
+    pub fn lock_after_convergence(&self) -> ExposureFuture {
+        let mut state = self.state.lock().unwrap();
+        self.supersede(&mut state);
+        state.ae_enable = Some(true);
+        state.awb_enable = Some(true);
+        state.settings = None;
+        state.phase = Phase::LockQueued;
+
+        Completion::future(&self.completion)
+    }
+
+    /// Returns exposure and white balance to the control of the AE/AWB
+    /// algorithms.
+    pub fn unlock(&self) {
+        let mut state = self.state.lock().unwrap();
+        self.supersede(&mut state);
+        state.ae_enable = Some(true);
+        state.awb_enable = Some(true);
+        state.settings = None;
+    }
+
+    /// Gets the settings reported by the last request passed to observe().
+    pub fn settings(&self) -> Option<ExposureSettings> {
+        self.state.lock().unwrap().last
+    }
+
+    /// Adds any pending exposure controls to a request which is about to be
+    /// enqueued.
+    pub fn apply(&self, request: &mut Request) {
+        let cookie = request.cookie();
+        let sent = self.state.lock().unwrap().take_controls(cookie);
+        let list = request.controls_mut();
+
+        if let Some(enable) = sent.ae_enable {
+            list.set(controls::AeEnable, enable);
+        }
+
+        if let Some(enable) = sent.awb_enable {
+            list.set(controls::AwbEnable, enable);
+        }
+
+        if let Some(settings) = sent.settings {
+            list.set(
+                controls::ExposureTime,
+                settings.exposure_time.as_micros() as i32,
+            );
+            list.set(controls::AnalogueGain, settings.analogue_gain);
+            if let Some(gains) = settings.colour_gains {
+                list.set(controls::ColourGains, gains);
+            }
+        }
+    }
+
+    /// Updates the exposure state from the metadata of a completed request.
+    pub fn observe(&self, request: &Request) {
+        let metadata = request.metadata();
+
+        let settings = match (
+            metadata.get(controls::ExposureTime),
+            metadata.get(controls::AnalogueGain),
+        ) {
+            (Some(exposure_time), Some(analogue_gain)) => Some(ExposureSettings {
+                exposure_time: Duration::from_micros(exposure_time.max(0) as u64),
+                analogue_gain,
+                colour_gains: metadata.get(controls::ColourGains),
+            }),
+            _ => None,
+        };
+
+        let depth = [
+            metadata.get(controls::MaxLatency),
+            metadata.get(controls::PipelineDepth),
+        ]
+        .into_iter()
+        .flatten()
+        .filter(|v| *v >= 0)
+        .map(|v| v as u32)
+        .max();
+
+        let report = ExposureReport {
+            settings,
+            ae_locked: metadata.get(controls::AeLocked).unwrap_or(false),
+            depth,
+        };
+
+        self.observe_values(request.cookie(), request.status(), report);
+    }
+
+    fn observe_values(&self, cookie: u64, status: RequestStatus, report: ExposureReport) {
+        let mut state = self.state.lock().unwrap();
+
+        // Controls in a cancelled request are never applied, so they are
+        // sent again with the next request passed to apply().
+        if status != RequestStatus::RequestComplete {
+            match state.phase {
+                Phase::LockSent {
+                    cookie: c,
+                    controls,
+                } if c == cookie => {
+                    state.requeue(controls);
+                    state.phase = Phase::LockQueued;
+                }
+                Phase::Sent {
+                    cookie: c,
+                    target,
+                    controls,
+                } if c == cookie => {
+                    state.requeue(controls);
+                    state.phase = Phase::Queued { target };
+                }
+                _ => {}
+            }
+            return;
+        }
+
+        if report.settings.is_some() {
+            state.last = report.settings;
+        }
+        if let Some(depth) = report.depth {
+            state.latency = state.latency.max(depth);
+        }
+
+        match state.phase {
+            Phase::LockSent { cookie: c, .. } if c == cookie => {
+                state.phase = Phase::Converging;
+            }
+            Phase::Sent {
+                cookie: c, target, ..
+            } if c == cookie => {
+                state.phase = Phase::Settling {
+                    target,
+                    frames_left: state.latency,
+                };
+            }
+            _ => {}
+        }
+
+        match state.phase {
+            Phase::Converging => {
+                if let (true, Some(reported)) = (report.ae_locked, report.settings) {
+                    state.ae_enable = Some(false);
+                    if reported.colour_gains.is_some() {
+                        state.awb_enable = Some(false);
+                    }
+                    state.queue_manual(reported);
+                }
+            }
+            Phase::Settling {
+                target,
+                frames_left,
+            } => match report.settings {
+                Some(reported) if frames_left == 0 || settled(&target, &reported) => {
+                    self.finish(&mut state, Some(reported));
+                }
+                None if frames_left == 0 => self.finish(&mut state, Some(target)),
+                _ => {
+                    state.phase = Phase::Settling {
+                        target,
+                        frames_left: frames_left - 1,
+                    };
+                }
+            },
+            _ => {}
+        }
+    }
+
+    fn finish(&self, state: &mut ExposureState, result: Option<ExposureSettings>) {
+        state.phase = Phase::Idle;
+        self.completion.lock().unwrap().finish(result);
+    }
+
+    /// Abandons the current operation (if any) so that a new one can start.
+    fn supersede(&self, state: &mut ExposureState) {
+        state.phase = Phase::Idle;
+        self.completion.lock().unwrap().supersede();
+    }
+}
+
+impl ExposureState {
+    /// Takes the controls to send with the request with the given cookie.
+    fn take_controls(&mut self, cookie: u64) -> ExposureControls {
+        let controls = ExposureControls {
+            ae_enable: self.ae_enable.take(),
+            awb_enable: self.awb_enable.take(),
+            settings: self.settings.take(),
+        };
+
+        if controls == ExposureControls::default() {
+            return controls;
+        }
+
+        self.phase = match self.phase {
+            Phase::LockQueued => Phase::LockSent { cookie, controls },
+            Phase::Queued { target } => Phase::Sent {
+                cookie,
+                target,
+                controls,
+            },
+            phase => phase,
+        };
+
+        controls
+    }
+
+    /// Puts back controls which were taken for a request that was cancelled
+    /// (unless they have since been replaced).
+    fn requeue(&mut self, controls: ExposureControls) {
+        self.ae_enable = self.ae_enable.or(controls.ae_enable);
+        self.awb_enable = self.awb_enable.or(controls.awb_enable);
+        self.settings = self.settings.or(controls.settings);
+    }
+
+    fn queue_manual(&mut self, target: ExposureSettings) {
+        self.ae_enable = Some(false);
+        self.settings = Some(target);
+        self.phase = Phase::Queued { target };
+    }
+}
+
+pub(crate) fn settled(target: &ExposureSettings, reported: &ExposureSettings) -> bool {
+    close(
+        target.exposure_time.as_secs_f64(),
+        reported.exposure_time.as_secs_f64(),
+    ) && close(target.analogue_gain as f64, reported.analogue_gain as f64)
+}
+
+/// Future returned by the Exposure helper methods.
+pub type ExposureFuture = CompletionFuture<Option<ExposureSettings>>;
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+
+    use super::*;
+
+    const COMPLETE: RequestStatus = RequestStatus::RequestComplete;
+    const CANCELLED: RequestStatus = RequestStatus::RequestCancelled;
+
+    fn exposure(latency: u32) -> Exposure {
+        Exposure {
+            state: Arc::new(Mutex::new(ExposureState {
+                latency,
+                ..Default::default()
+            })),
+            completion: Completion::new(None),
+            exposure_limits: Some((100, 100_000)),
+            gain_limits: Some((1.0, 16.0)),
+        }
+    }
+
+    fn settings(exposure_us: u64, analogue_gain: f32) -> ExposureSettings {
+        ExposureSettings {
+            exposure_time: Duration::from_micros(exposure_us),
+            analogue_gain,
+            colour_gains: None,
+        }
+    }
+
+    fn report(settings: ExposureSettings, ae_locked: bool) -> ExposureReport {
+        ExposureReport {
+            settings: Some(settings),
+            ae_locked,
+            depth: None,
+        }
+    }
+
+    fn take(exposure: &Exposure, cookie: u64) -> ExposureControls {
+        exposure.state.lock().unwrap().take_controls(cookie)
+    }
+
+    fn poll(future: &mut ExposureFuture) -> Poll<Option<ExposureSettings>> {
+        pin!(future).poll(&mut Context::from_waker(Waker::noop()))
+    }
+
+    #[test]
+    fn manual_settings_are_clamped_and_sent_once() {
+        let exposure = exposure(2);
+        let _future = exposure.set_manual(Duration::from_micros(10), 100.0);
+
+        let sent = take(&exposure, 1);
+        assert_eq!(sent.ae_enable, Some(false));
+        assert_eq!(sent.settings, Some(settings(100, 16.0)));
+
+        assert_eq!(take(&exposure, 2), ExposureControls::default());
+    }
+
+    #[test]
+    fn manual_completes_once_reported() {
+        let exposure = exposure(4);
+        let mut future = exposure.set_manual(Duration::from_millis(10), 2.0);
+        take(&exposure, 1);
+
+        exposure.observe_values(1, COMPLETE, report(settings(5_000, 1.0), false));
+        assert_eq!(poll(&mut future), Poll::Pending);
+
+        let reported = settings(10_010, 2.0);
+        exposure.observe_values(2, COMPLETE, report(reported, false));
+        assert_eq!(poll(&mut future), Poll::Ready(Some(reported)));
+        assert_eq!(exposure.settings(), Some(reported));
+    }
+
+    #[test]
+    fn manual_completes_after_latency_with_reported_settings() {
+        let exposure = exposure(2);
+        let mut future = exposure.set_manual(Duration::from_millis(10), 2.0);
+        take(&exposure, 1);
+
+        // The sensor can't reach the requested exposure time.
+        let reported = settings(8_000, 2.0);
+        for cookie in 1..=2 {
+            exposure.observe_values(cookie, COMPLETE, report(reported, false));
+            assert_eq!(poll(&mut future), Poll::Pending);
+        }
+
+        exposure.observe_values(3, COMPLETE, report(reported, false));
+        assert_eq!(poll(&mut future), Poll::Ready(Some(reported)));
+    }
+
+    #[test]
+    fn metadata_depth_extends_latency() {
+        let exposure = exposure(0);
+        let mut future = exposure.set_manual(Duration::from_millis(10), 2.0);
+        take(&exposure, 1);
+
+        let reported = settings(8_000, 2.0);
+        let mut first = report(reported, false);
+        first.depth = Some(1);
+        exposure.observe_values(1, COMPLETE, first);
+        assert_eq!(poll(&mut future), Poll::Pending);
+
+        exposure.observe_values(2, COMPLETE, report(reported, false));
+        assert_eq!(poll(&mut future), Poll::Ready(Some(reported)));
+    }
+
+    #[test]
+    fn cancelled_manual_request_is_resent() {
+        let exposure = exposure(2);
+        let mut future = exposure.set_manual(Duration::from_millis(10), 2.0);
+        let sent = take(&exposure, 1);
+
+        exposure.observe_values(1, CANCELLED, ExposureReport::default());
+        assert_eq!(poll(&mut future), Poll::Pending);
+
+        assert_eq!(take(&exposure, 2), sent);
+
+        let reported = settings(10_000, 2.0);
+        exposure.observe_values(2, COMPLETE, report(reported, false));
+        assert_eq!(poll(&mut future), Poll::Ready(Some(reported)));
+    }
+
+    #[test]
+    fn unrelated_cancelled_request_is_ignored() {
+        let exposure = exposure(2);
+        let _future = exposure.set_manual(Duration::from_millis(10), 2.0);
+        take(&exposure, 2);
+
+        exposure.observe_values(1, CANCELLED, ExposureReport::default());
+        assert_eq!(take(&exposure, 3), ExposureControls::default());
+    }
+
+    #[test]
+    fn lock_freezes_converged_settings() {
+        let exposure = exposure(2);
+        let mut future = exposure.lock_after_convergence();
+
+        let sent = take(&exposure, 1);
+        assert_eq!(sent.ae_enable, Some(true));
+        assert_eq!(sent.awb_enable, Some(true));
+
+        let converged = ExposureSettings {
+            colour_gains: Some([1.5, 2.0]),
+            ..settings(20_000, 4.0)
+        };
+        exposure.observe_values(1, COMPLETE, report(settings(5_000, 1.0), false));
+        exposure.observe_values(2, COMPLETE, report(converged, true));
+        assert_eq!(poll(&mut future), Poll::Pending);
+
+        let sent = take(&exposure, 3);
+        assert_eq!(sent.ae_enable, Some(false));
+        assert_eq!(sent.awb_enable, Some(false));
+        assert_eq!(sent.settings, Some(converged));
+
+        exposure.observe_values(3, COMPLETE, report(converged, true));
+        assert_eq!(poll(&mut future), Poll::Ready(Some(converged)));
+    }
+
+    #[test]
+    fn cancelled_lock_request_is_resent() {
+        let exposure = exposure(2);
+        let mut future = exposure.lock_after_convergence();
+        let sent = take(&exposure, 1);
+
+        // A report of convergence from a request which never enabled AE
+        // mustn't lock the exposure.
+        let converged = settings(20_000, 4.0);
+        exposure.observe_values(1, CANCELLED, report(converged, true));
+        assert_eq!(take(&exposure, 2), sent);
+
+        exposure.observe_values(2, COMPLETE, report(converged, true));
+        assert_eq!(take(&exposure, 3).settings, Some(converged));
+        exposure.observe_values(3, COMPLETE, report(converged, true));
+        assert_eq!(poll(&mut future), Poll::Ready(Some(converged)));
+    }
+
+    #[test]
+    fn superseded_operation_completes_with_none() {
+        let exposure = exposure(2);
+        let mut future = exposure.set_manual(Duration::from_millis(10), 2.0);
+        take(&exposure, 1);
+
+        exposure.unlock();
+        assert_eq!(poll(&mut future), Poll::Ready(None));
+
+        // The settings reported for the old request don't complete anything.
+        let sent = take(&exposure, 2);
+        assert_eq!(sent.ae_enable, Some(true));
+        exposure.observe_values(1, COMPLETE, report(settings(10_000, 2.0), false));
+        assert_eq!(exposure.state.lock().unwrap().phase, Phase::Idle);
+    }
+}
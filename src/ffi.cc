@@ -1,5 +1,7 @@
 #include "ffi.h"
 
+#include <errno.h>
+
 namespace libcamera {
 
 std::unique_ptr<CameraManager> new_camera_manager() {
@@ -30,6 +32,78 @@ std::unique_ptr<CameraConfiguration> generate_camera_configuration(
   return config;
 }
 
+void camera_configuration_add(CameraConfiguration &config) {
+  config.addConfiguration(StreamConfiguration());
+}
+
+std::unique_ptr<CameraConfiguration> camera_configuration_without(
+    Camera &camera, const CameraConfiguration &config, uint32_t index) {
+  // libcamera has no public API for removing stream configurations, so a new
+  // configuration is generated with no roles and the others copied into it.
+  auto out = camera.generateConfiguration({});
+  if (!out) {
+    return nullptr;
+  }
+
+  for (uint32_t i = 0; i < config.size(); i++) {
+    if (i != index) {
+      out->addConfiguration(config.at(i));
+    }
+  }
+
+  out->sensorConfig = config.sensorConfig;
+  out->orientation = config.orientation;
+
+  return out;
+}
+
+bool camera_configuration_has_sensor_config(
+    const CameraConfiguration &config) {
+  return config.sensorConfig.has_value();
+}
+
+::SensorConfiguration camera_configuration_sensor_config(
+    const CameraConfiguration &config) {
+  const SensorConfiguration &value = *config.sensorConfig;
+  return ::SensorConfiguration{.bit_depth = value.bitDepth,
+                               .analog_crop = value.analogCrop,
+                               .bin_x = value.binning.binX,
+                               .bin_y = value.binning.binY,
+                               .x_odd_inc = value.skipping.xOddInc,
+                               .x_even_inc = value.skipping.xEvenInc,
+                               .y_odd_inc = value.skipping.yOddInc,
+                               .y_even_inc = value.skipping.yEvenInc,
+                               .output_size = value.outputSize};
+}
+
+void camera_configuration_set_sensor_config(
+    CameraConfiguration &config, const ::SensorConfiguration &value) {
+  SensorConfiguration out;
+  out.bitDepth = value.bit_depth;
+  out.analogCrop = value.analog_crop;
+  out.binning.binX = value.bin_x;
+  out.binning.binY = value.bin_y;
+  out.skipping.xOddInc = value.x_odd_inc;
+  out.skipping.xEvenInc = value.x_even_inc;
+  out.skipping.yOddInc = value.y_odd_inc;
+  out.skipping.yEvenInc = value.y_even_inc;
+  out.outputSize = value.output_size;
+  config.sensorConfig = out;
+}
+
+void camera_configuration_clear_sensor_config(CameraConfiguration &config) {
+  config.sensorConfig.reset();
+}
+
+int camera_configuration_orientation(const CameraConfiguration &config) {
+  return static_cast<int>(config.orientation);
+}
+
+void camera_configuration_set_orientation(CameraConfiguration &config,
+                                          int value) {
+  config.orientation = static_cast<Orientation>(value);
+}
+
 std::unique_ptr<FrameBufferAllocator> new_frame_buffer_allocator(
     std::shared_ptr<Camera> camera) {
   return std::make_unique<FrameBufferAllocator>(camera);
@@ -119,6 +193,19 @@ rust::Vec<::FrameBufferPlane> frame_buffer_planes(const FrameBuffer &buffer) {
   return out;
 }
 
+int frame_buffer_release_fence(FrameBuffer &buffer) {
+  std::unique_ptr<Fence> fence = buffer.releaseFence();
+  if (!fence) {
+    return -1;
+  }
+
+  return fence->release().release();
+}
+
+std::unique_ptr<Fence> new_fence(int fd) {
+  return std::make_unique<Fence>(UniqueFD(fd));
+}
+
 rust::String pixel_format_to_string(const PixelFormat &format) {
   return rust::String(format.toString());
 }
@@ -172,6 +259,11 @@ rust::String request_to_string(const Request &request) {
   return rust::String(request.toString());
 }
 
+const ControlList &request_metadata(const Request &request) {
+  // NOTE: libcamera only exposes a non-const accessor.
+  return const_cast<Request &>(request).metadata();
+}
+
 rust::Vec<::ControlInfoMapEntry> control_info_map_entries(
     const ControlInfoMap &map) {
   rust::Vec<::ControlInfoMapEntry> out;
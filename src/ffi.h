@@ -30,6 +30,21 @@ rust::Vec<CameraPtr> list_cameras(const CameraManager &camera_manager);
 std::unique_ptr<CameraConfiguration> generate_camera_configuration(
     Camera &camera, rust::Slice<const StreamRole> stream_roles);
 
+void camera_configuration_add(CameraConfiguration &config);
+std::unique_ptr<CameraConfiguration> camera_configuration_without(
+    Camera &camera, const CameraConfiguration &config, uint32_t index);
+
+bool camera_configuration_has_sensor_config(const CameraConfiguration &config);
+::SensorConfiguration camera_configuration_sensor_config(
+    const CameraConfiguration &config);
+void camera_configuration_set_sensor_config(
+    CameraConfiguration &config, const ::SensorConfiguration &value);
+void camera_configuration_clear_sensor_config(CameraConfiguration &config);
+
+int camera_configuration_orientation(const CameraConfiguration &config);
+void camera_configuration_set_orientation(CameraConfiguration &config,
+                                          int value);
+
 std::unique_ptr<FrameBufferAllocator> new_frame_buffer_allocator(
     std::shared_ptr<Camera> camera);
 
@@ -60,6 +75,10 @@ rust::Vec<::FrameBufferPlane> frame_buffer_planes(const FrameBuffer &buffer);
 
 ::FrameMetadata frame_buffer_metadata(const FrameBuffer &buffer);
 
+int frame_buffer_release_fence(FrameBuffer &buffer);
+
+std::unique_ptr<Fence> new_fence(int fd);
+
 rust::String pixel_format_to_string(const PixelFormat &format);
 PixelFormat pixel_format_from_string(rust::String string);
 
@@ -103,6 +122,8 @@ bool camera_contains_stream(const Camera &camera, Stream *stream);
 
 rust::String request_to_string(const Request &request);
 
+const ControlList &request_metadata(const Request &request);
+
 rust::Vec<::ControlInfoMapEntry> control_info_map_entries(
     const ControlInfoMap &map);
 
@@ -86,6 +86,20 @@ mod ffi {
         planes: Vec<FramePlaneMetadataWrap>,
     }
 
+    /// A mirror of libcamera::SensorConfiguration
+    #[derive(Debug, Clone, Copy)]
+    struct SensorConfiguration {
+        bit_depth: u32,
+        analog_crop: Rectangle,
+        bin_x: u32,
+        bin_y: u32,
+        x_odd_inc: u32,
+        x_even_inc: u32,
+        y_odd_inc: u32,
+        y_even_inc: u32,
+        output_size: Size,
+    }
+
     // TODO: Standard on 'XShared' nameing here?
 
     // Wrapper to work around https://github.com/dtolnay/cxx/issues/741
@@ -225,12 +239,16 @@ mod ffi {
 
         fn request_to_string(request: &Request) -> String;
 
+        fn request_metadata(request: &Request) -> &ControlList;
+
         fn hasPendingBuffers(self: &Request) -> bool;
 
         //////////////////////////////////////
 
         type Fence;
 
+        fn new_fence(fd: i32) -> UniquePtr<Fence>;
+
         //////////////////////////////////////
 
         type CameraConfiguration;
@@ -244,6 +262,25 @@ mod ffi {
 
         fn validate(self: Pin<&mut CameraConfiguration>) -> CameraConfigurationStatus;
 
+        fn camera_configuration_add(config: Pin<&mut CameraConfiguration>);
+
+        fn camera_configuration_without(
+            camera: Pin<&mut Camera>,
+            config: &CameraConfiguration,
+            index: u32,
+        ) -> UniquePtr<CameraConfiguration>;
+
+        fn camera_configuration_has_sensor_config(config: &CameraConfiguration) -> bool;
+        fn camera_configuration_sensor_config(config: &CameraConfiguration) -> SensorConfiguration;
+        fn camera_configuration_set_sensor_config(
+            config: Pin<&mut CameraConfiguration>,
+            value: &SensorConfiguration,
+        );
+        fn camera_configuration_clear_sensor_config(config: Pin<&mut CameraConfiguration>);
+
+        fn camera_configuration_orientation(config: &CameraConfiguration) -> i32;
+        fn camera_configuration_set_orientation(config: Pin<&mut CameraConfiguration>, value: i32);
+
         //////////////////////////////////////
 
         type StreamConfiguration;
@@ -412,6 +449,8 @@ mod ffi {
 
         fn set(self: Pin<&mut ControlList>, id: u32, value: &ControlValue);
 
+        fn clear(self: Pin<&mut ControlList>);
+
         fn control_list_entries(list: &ControlList) -> Vec<ControlListEntry>;
 
         fn idMap(self: &ControlList) -> *const ControlIdMap;
@@ -471,6 +510,10 @@ mod ffi {
 
         fn frame_buffer_metadata(buffer: &FrameBuffer) -> FrameMetadata;
 
+        /// Returns the fd of the fence still attached to the buffer or -1 if
+        /// there is none.
+        fn frame_buffer_release_fence(buffer: Pin<&mut FrameBuffer>) -> i32;
+
         //////////////////////////////////////
 
         type FrameBufferAllocator;
@@ -0,0 +1,106 @@
+//! Named pixel formats mirroring libcamera's formats.h.
+//!
+//! Unlike the extern statics in the C++ library, these are plain constants so
+//! they can be used in const contexts and match patterns.
+
+use crate::pixel_format::PixelFormat;
+
+const fn fourcc(code: &[u8; 4]) -> u32 {
+    u32::from_le_bytes(*code)
+}
+
+/// Equivalent of DRM_FORMAT_BIG_ENDIAN.
+const BIG_ENDIAN: u32 = 1 << 31;
+
+/// Equivalent of MIPI_FORMAT_MOD_CSI2_PACKED.
+const MIPI_CSI2_PACKED: u64 = (0x0b << 56) | 1;
+
+/// Equivalent of IPU3_FORMAT_MOD_PACKED.
+const IPU3_PACKED: u64 = (0x01 << 56) | 13;
+
+// RGB formats.
+pub const RGB565: PixelFormat = PixelFormat::new(fourcc(b"RG16"), 0);
+pub const RGB565_BE: PixelFormat = PixelFormat::new(fourcc(b"RG16") | BIG_ENDIAN, 0);
+pub const BGR888: PixelFormat = PixelFormat::new(fourcc(b"BG24"), 0);
+pub const RGB888: PixelFormat = PixelFormat::new(fourcc(b"RG24"), 0);
+pub const XRGB8888: PixelFormat = PixelFormat::new(fourcc(b"XR24"), 0);
+pub const XBGR8888: PixelFormat = PixelFormat::new(fourcc(b"XB24"), 0);
+pub const RGBX8888: PixelFormat = PixelFormat::new(fourcc(b"RX24"), 0);
+pub const BGRX8888: PixelFormat = PixelFormat::new(fourcc(b"BX24"), 0);
+pub const ARGB8888: PixelFormat = PixelFormat::new(fourcc(b"AR24"), 0);
+pub const ABGR8888: PixelFormat = PixelFormat::new(fourcc(b"AB24"), 0);
+pub const RGBA8888: PixelFormat = PixelFormat::new(fourcc(b"RA24"), 0);
+pub const BGRA8888: PixelFormat = PixelFormat::new(fourcc(b"BA24"), 0);
+
+// YUV packed formats.
+pub const YUYV: PixelFormat = PixelFormat::new(fourcc(b"YUYV"), 0);
+pub const YVYU: PixelFormat = PixelFormat::new(fourcc(b"YVYU"), 0);
+pub const UYVY: PixelFormat = PixelFormat::new(fourcc(b"UYVY"), 0);
+pub const VYUY: PixelFormat = PixelFormat::new(fourcc(b"VYUY"), 0);
+pub const AVUY8888: PixelFormat = PixelFormat::new(fourcc(b"AVUY"), 0);
+pub const XVUY8888: PixelFormat = PixelFormat::new(fourcc(b"XVUY"), 0);
+
+// YUV semi-planar formats.
+pub const NV12: PixelFormat = PixelFormat::new(fourcc(b"NV12"), 0);
+pub const NV21: PixelFormat = PixelFormat::new(fourcc(b"NV21"), 0);
+pub const NV16: PixelFormat = PixelFormat::new(fourcc(b"NV16"), 0);
+pub const NV61: PixelFormat = PixelFormat::new(fourcc(b"NV61"), 0);
+pub const NV24: PixelFormat = PixelFormat::new(fourcc(b"NV24"), 0);
+pub const NV42: PixelFormat = PixelFormat::new(fourcc(b"NV42"), 0);
+
+// YUV planar formats.
+pub const YUV420: PixelFormat = PixelFormat::new(fourcc(b"YU12"), 0);
+pub const YVU420: PixelFormat = PixelFormat::new(fourcc(b"YV12"), 0);
+pub const YUV422: PixelFormat = PixelFormat::new(fourcc(b"YU16"), 0);
+pub const YVU422: PixelFormat = PixelFormat::new(fourcc(b"YV16"), 0);
+pub const YUV444: PixelFormat = PixelFormat::new(fourcc(b"YU24"), 0);
+pub const YVU444: PixelFormat = PixelFormat::new(fourcc(b"YV24"), 0);
+
+// Greyscale formats.
+pub const R8: PixelFormat = PixelFormat::new(fourcc(b"R8  "), 0);
+pub const R10: PixelFormat = PixelFormat::new(fourcc(b"R10 "), 0);
+pub const R12: PixelFormat = PixelFormat::new(fourcc(b"R12 "), 0);
+pub const R16: PixelFormat = PixelFormat::new(fourcc(b"R16 "), 0);
+pub const R10_CSI2P: PixelFormat = PixelFormat::new(fourcc(b"R10 "), MIPI_CSI2_PACKED);
+pub const R12_CSI2P: PixelFormat = PixelFormat::new(fourcc(b"R12 "), MIPI_CSI2_PACKED);
+
+// Bayer formats.
+pub const SRGGB8: PixelFormat = PixelFormat::new(fourcc(b"RGGB"), 0);
+pub const SRGGB10: PixelFormat = PixelFormat::new(fourcc(b"RG10"), 0);
+pub const SRGGB10_CSI2P: PixelFormat = PixelFormat::new(fourcc(b"RG10"), MIPI_CSI2_PACKED);
+pub const SRGGB10_IPU3: PixelFormat = PixelFormat::new(fourcc(b"RG10"), IPU3_PACKED);
+pub const SRGGB12: PixelFormat = PixelFormat::new(fourcc(b"RG12"), 0);
+pub const SRGGB12_CSI2P: PixelFormat = PixelFormat::new(fourcc(b"RG12"), MIPI_CSI2_PACKED);
+pub const SRGGB14: PixelFormat = PixelFormat::new(fourcc(b"RG14"), 0);
+pub const SRGGB14_CSI2P: PixelFormat = PixelFormat::new(fourcc(b"RG14"), MIPI_CSI2_PACKED);
+pub const SRGGB16: PixelFormat = PixelFormat::new(fourcc(b"RGB6"), 0);
+pub const SGRBG8: PixelFormat = PixelFormat::new(fourcc(b"GRBG"), 0);
+pub const SGRBG10: PixelFormat = PixelFormat::new(fourcc(b"BA10"), 0);
+pub const SGRBG10_CSI2P: PixelFormat = PixelFormat::new(fourcc(b"BA10"), MIPI_CSI2_PACKED);
+pub const SGRBG10_IPU3: PixelFormat = PixelFormat::new(fourcc(b"BA10"), IPU3_PACKED);
+pub const SGRBG12: PixelFormat = PixelFormat::new(fourcc(b"BA12"), 0);
+pub const SGRBG12_CSI2P: PixelFormat = PixelFormat::new(fourcc(b"BA12"), MIPI_CSI2_PACKED);
+pub const SGRBG14: PixelFormat = PixelFormat::new(fourcc(b"BA14"), 0);
+pub const SGRBG14_CSI2P: PixelFormat = PixelFormat::new(fourcc(b"BA14"), MIPI_CSI2_PACKED);
+pub const SGRBG16: PixelFormat = PixelFormat::new(fourcc(b"GR16"), 0);
+pub const SGBRG8: PixelFormat = PixelFormat::new(fourcc(b"GBRG"), 0);
+pub const SGBRG10: PixelFormat = PixelFormat::new(fourcc(b"GB10"), 0);
+pub const SGBRG10_CSI2P: PixelFormat = PixelFormat::new(fourcc(b"GB10"), MIPI_CSI2_PACKED);
+pub const SGBRG10_IPU3: PixelFormat = PixelFormat::new(fourcc(b"GB10"), IPU3_PACKED);
+pub const SGBRG12: PixelFormat = PixelFormat::new(fourcc(b"GB12"), 0);
+pub const SGBRG12_CSI2P: PixelFormat = PixelFormat::new(fourcc(b"GB12"), MIPI_CSI2_PACKED);
+pub const SGBRG14: PixelFormat = PixelFormat::new(fourcc(b"GB14"), 0);
+pub const SGBRG14_CSI2P: PixelFormat = PixelFormat::new(fourcc(b"GB14"), MIPI_CSI2_PACKED);
+pub const SGBRG16: PixelFormat = PixelFormat::new(fourcc(b"GB16"), 0);
+pub const SBGGR8: PixelFormat = PixelFormat::new(fourcc(b"BA81"), 0);
+pub const SBGGR10: PixelFormat = PixelFormat::new(fourcc(b"BG10"), 0);
+pub const SBGGR10_CSI2P: PixelFormat = PixelFormat::new(fourcc(b"BG10"), MIPI_CSI2_PACKED);
+pub const SBGGR10_IPU3: PixelFormat = PixelFormat::new(fourcc(b"BG10"), IPU3_PACKED);
+pub const SBGGR12: PixelFormat = PixelFormat::new(fourcc(b"BG12"), 0);
+pub const SBGGR12_CSI2P: PixelFormat = PixelFormat::new(fourcc(b"BG12"), MIPI_CSI2_PACKED);
+pub const SBGGR14: PixelFormat = PixelFormat::new(fourcc(b"BG14"), 0);
+pub const SBGGR14_CSI2P: PixelFormat = PixelFormat::new(fourcc(b"BG14"), MIPI_CSI2_PACKED);
+pub const SBGGR16: PixelFormat = PixelFormat::new(fourcc(b"BYR2"), 0);
+
+// Compressed formats.
+pub const MJPEG: PixelFormat = PixelFormat::new(fourcc(b"MJPG"), 0);
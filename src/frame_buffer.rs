@@ -1,13 +1,18 @@
 use std::num::NonZeroUsize;
+use std::os::fd::{FromRawFd, OwnedFd};
+use std::pin::Pin;
 use std::sync::Arc;
 use std::sync::Mutex;
 
 use nix::sys::mman::*;
 
+use crate::convert::{self, ConvertError, FrameLayout, OutputFormat};
 use crate::errors::*;
 use crate::ffi;
-use crate::frame_buffer_allocator::FrameBufferAllocatorInner;
+use crate::frame_buffer_allocator::StreamAllocation;
+use crate::frame_metadata::BufferMetadata;
 use crate::stream::Stream;
+use crate::stream_configuration::StreamConfigurationOpaque;
 
 pub use ffi::{FrameBufferPlane, FrameMetadata, FramePlaneMetadata, FrameStatus};
 
@@ -16,8 +21,10 @@ pub use ffi::{FrameBufferPlane, FrameMetadata, FramePlaneMetadata, FrameStatus};
 /// Exclusive access to a FrameBuffer instance is required to mutate the
 /// internal memory.
 pub struct FrameBuffer {
+    /// Keeps the allocator alive and prevents the stream's buffers from being
+    /// freed while this buffer exists.
     #[allow(unused)]
-    allocator: Arc<FrameBufferAllocatorInner>,
+    allocation: Arc<StreamAllocation>,
 
     /// Reference to the stream for which this frame buffer was created.
     ///
@@ -37,7 +44,7 @@ unsafe impl Sync for FrameBuffer {}
 
 impl FrameBuffer {
     pub(crate) fn new(
-        allocator: Arc<FrameBufferAllocatorInner>,
+        allocation: Arc<StreamAllocation>,
         stream: &Stream,
         raw: *mut ffi::FrameBuffer,
     ) -> Self {
@@ -45,7 +52,7 @@ impl FrameBuffer {
         let planes = ffi::frame_buffer_planes(unsafe { &*raw });
 
         Self {
-            allocator,
+            allocation,
             stream: unsafe { stream.as_static() },
             raw,
             planes,
@@ -61,6 +68,25 @@ impl FrameBuffer {
         ffi::frame_buffer_metadata(unsafe { &*self.raw })
     }
 
+    /// Same as metadata() but with typed timestamps and plane sizes.
+    pub fn buffer_metadata(&self) -> BufferMetadata {
+        self.metadata().into()
+    }
+
+    /// Takes back ownership of the acquire fence attached to this buffer.
+    ///
+    /// libcamera resets fences once they have been signalled, so this will only
+    /// return a value if the fence failed to signal before the request was
+    /// processed.
+    pub(crate) fn release_fence(&mut self) -> Option<OwnedFd> {
+        let fd = ffi::frame_buffer_release_fence(unsafe { Pin::new_unchecked(&mut *self.raw) });
+        if fd < 0 {
+            return None;
+        }
+
+        Some(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+
     /// Gets a reference to the complete segment of memory in this buffer.
     ///
     /// Not all of the memory may have actually been used in the most recent
@@ -76,6 +102,50 @@ impl FrameBuffer {
         self.memory.as_ref().map(|v| &v[..])
     }
 
+    /// Gets the memory of each plane in this buffer.
+    ///
+    /// This will return None until the memory is mmap'ed using map_memory().
+    pub fn plane_memory<'a>(&'a self) -> Option<Vec<&'a [u8]>> {
+        let memory = self.memory()?;
+
+        // Walk the planes in the same way as map_all_memory() to find which
+        // mapped segment each plane is in.
+        let mut out = vec![];
+        let mut segment_index = 0;
+        let mut segment_start: Option<FrameBufferPlane> = None;
+        let mut last_plane: Option<FrameBufferPlane> = None;
+
+        for plane in self.planes() {
+            if let Some(last) = &last_plane {
+                if last.fd != plane.fd || last.offset + last.length != plane.offset {
+                    segment_index += 1;
+                    segment_start = None;
+                }
+            }
+
+            let start = *segment_start.get_or_insert(*plane);
+            let offset = (plane.offset - start.offset) as usize;
+
+            out.push(&memory[segment_index][offset..(offset + plane.length as usize)]);
+            last_plane = Some(*plane);
+        }
+
+        Some(out)
+    }
+
+    /// Converts the frame most recently captured into this buffer into a
+    /// tightly packed 8-bit image.
+    ///
+    /// The memory must first be mapped using map_memory().
+    pub fn convert(
+        &self,
+        config: &StreamConfigurationOpaque,
+        output: OutputFormat,
+    ) -> std::result::Result<Vec<u8>, ConvertError> {
+        let planes = self.plane_memory().ok_or(ConvertError::NotMapped)?;
+        convert::convert(&planes, &FrameLayout::from_stream_config(config), output)
+    }
+
     /// Attempts to retrieve all the occupied memory as one contigous memory
     /// slice.
     ///
@@ -106,6 +176,23 @@ impl FrameBuffer {
         Some(&memory[0][0..size])
     }
 
+    /// Gets the data captured into a single plane buffer (e.g. a compressed
+    /// frame).
+    ///
+    /// Some devices report a bytesused larger than the plane, so the data is
+    /// clamped to the plane's length. Returns None if the memory isn't mapped
+    /// or the buffer has more than one plane.
+    pub fn single_plane_memory(&self) -> Option<&[u8]> {
+        let planes = self.plane_memory()?;
+        let data = match planes[..] {
+            [data] => data,
+            _ => return None,
+        };
+
+        let used = self.metadata().planes.first()?.inner.bytesused as usize;
+        Some(&data[..used.min(data.len())])
+    }
+
     /// mmap's this frame buffer's data into the current process so that it can
     /// be accessed via Self::memory().
     pub fn map_memory(&mut self) -> Result<()> {
@@ -1,6 +1,8 @@
 use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::sync::Weak;
 
 use cxx::UniquePtr;
 
@@ -12,12 +14,12 @@ use crate::stream::Stream;
 
 /// Allocates FrameBuffers for storing camera response data.
 ///
-/// NOTE: Compared to the C++ API, we do not allow explicitly freeing buffers
-/// associated with a specific stream. Exposing this would add the risk of
-/// freeing buffers still associated with a request. Instead all buffers in the
-/// FrameBufferAllocator will be freed at once when all references to them are
-/// dropped. If you need to create more buffers earlier than than, prefer to use
-/// a different FrameBufferAllocator instance.
+/// NOTE: Compared to the C++ API, buffers associated with a specific stream
+/// can only be freed once all FrameBuffers allocated for that stream have been
+/// dropped (including those still attached to requests). This is tracked by a
+/// reference count shared by the buffers in each StreamBuffers so that we never
+/// free memory which libcamera may still be writing to. All remaining buffers
+/// are freed once the FrameBufferAllocator and all its buffers are dropped.
 pub struct FrameBufferAllocator {
     inner: Arc<FrameBufferAllocatorInner>,
 }
@@ -33,6 +35,17 @@ pub(crate) struct FrameBufferAllocatorInner {
 
 struct FrameBufferAllocatorState {
     raw: UniquePtr<ffi::FrameBufferAllocator>,
+
+    /// Streams which currently have buffers allocated. Key is the stream id.
+    allocations: HashMap<u64, Weak<StreamAllocation>>,
+}
+
+/// Shared by all FrameBuffers created in a single call to
+/// FrameBufferAllocator::allocate().
+pub(crate) struct StreamAllocation {
+    /// The underlying ffi::FrameBuffers are owned by the allocator.
+    #[allow(unused)]
+    allocator: Arc<FrameBufferAllocatorInner>,
 }
 
 impl FrameBufferAllocator {
@@ -40,12 +53,15 @@ impl FrameBufferAllocator {
         Self {
             inner: Arc::new(FrameBufferAllocatorInner {
                 camera,
-                state: Mutex::new(FrameBufferAllocatorState { raw }),
+                state: Mutex::new(FrameBufferAllocatorState {
+                    raw,
+                    allocations: HashMap::new(),
+                }),
             }),
         }
     }
 
-    pub fn allocate(&mut self, stream: &Stream) -> Result<Vec<FrameBuffer>> {
+    pub fn allocate(&mut self, stream: &Stream) -> Result<StreamBuffers> {
         assert!(self.inner.camera.contains_stream(stream));
 
         let mut state = self.inner.state.lock().unwrap();
@@ -55,13 +71,101 @@ impl FrameBufferAllocator {
         let n = to_result(unsafe { state.raw.as_mut().unwrap().allocate(stream.as_mut_ptr()) })?
             as usize;
 
+        let allocation = Arc::new(StreamAllocation {
+            allocator: self.inner.clone(),
+        });
+
         let buffers = unsafe { ffi::get_allocated_frame_buffers(&state.raw, stream.as_mut_ptr()) }
             .into_iter()
-            .map(|b| FrameBuffer::new(self.inner.clone(), stream, b.buffer))
+            .map(|b| FrameBuffer::new(allocation.clone(), stream, b.buffer))
             .collect::<Vec<_>>();
 
         assert_eq!(buffers.len(), n);
 
-        Ok(buffers)
+        state
+            .allocations
+            .insert(stream.id(), Arc::downgrade(&allocation));
+
+        Ok(StreamBuffers {
+            allocation,
+            stream: stream as *const Stream,
+            buffers,
+        })
+    }
+
+    /// Frees the buffers allocated for the given stream so that allocate() can
+    /// be called again for it (e.g. after changing its buffer count).
+    ///
+    /// Returns EBUSY if any FrameBuffer from the stream's StreamBuffers is still
+    /// alive or EINVAL if no buffers are allocated for the stream.
+    pub fn free(&mut self, stream: &Stream) -> Result<()> {
+        let mut state = self.inner.state.lock().unwrap();
+
+        let allocation = state.allocations.get(&stream.id()).ok_or(Error::EINVAL)?;
+
+        // NOTE: No new references can be created once this reaches zero as we
+        // never upgrade the weak reference.
+        if allocation.strong_count() != 0 {
+            return Err(Error::EBUSY);
+        }
+
+        ok_if_zero(unsafe { state.raw.as_mut().unwrap().free(stream.as_mut_ptr()) })?;
+
+        state.allocations.remove(&stream.id());
+
+        Ok(())
+    }
+}
+
+/// The set of FrameBuffers allocated for a single stream.
+///
+/// Individual buffers can be moved out (e.g. to attach them to requests). The
+/// stream's memory can only be freed with FrameBufferAllocator::free() once all
+/// of them and the StreamBuffers itself have been dropped.
+pub struct StreamBuffers {
+    /// Keeps the allocator (and through it the camera owning the stream)
+    /// alive even if all buffers have been moved out.
+    #[allow(unused)]
+    allocation: Arc<StreamAllocation>,
+
+    /// Owned by the camera which is kept alive by 'allocation'.
+    stream: *const Stream,
+
+    buffers: Vec<FrameBuffer>,
+}
+
+unsafe impl Send for StreamBuffers {}
+unsafe impl Sync for StreamBuffers {}
+
+impl StreamBuffers {
+    pub fn stream(&self) -> &Stream {
+        unsafe { &*self.stream }
+    }
+
+    pub fn into_vec(self) -> Vec<FrameBuffer> {
+        self.buffers
+    }
+}
+
+impl Deref for StreamBuffers {
+    type Target = Vec<FrameBuffer>;
+
+    fn deref(&self) -> &Vec<FrameBuffer> {
+        &self.buffers
+    }
+}
+
+impl DerefMut for StreamBuffers {
+    fn deref_mut(&mut self) -> &mut Vec<FrameBuffer> {
+        &mut self.buffers
+    }
+}
+
+impl IntoIterator for StreamBuffers {
+    type Item = FrameBuffer;
+    type IntoIter = std::vec::IntoIter<FrameBuffer>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.buffers.into_iter()
     }
 }
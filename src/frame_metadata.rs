@@ -0,0 +1,463 @@
+use std::collections::VecDeque;
+use std::ops::{Add, Sub};
+use std::time::Duration;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::control_list::ControlList;
+use crate::controls::{self, AeStateEnum, AfStateEnum};
+use crate::ffi;
+use crate::frame_buffer::FrameStatus;
+
+/// Number of frame intervals used to compute FPS and jitter by default.
+const DEFAULT_WINDOW: usize = 30;
+
+/// Time at which a frame was captured, as reported by libcamera.
+///
+/// This is in nanoseconds of CLOCK_BOOTTIME (which keeps counting while the
+/// system is suspended) and is comparable with the SensorTimestamp control.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct Timestamp(u64);
+
+impl Timestamp {
+    pub const fn from_nanos(nanos: u64) -> Self {
+        Self(nanos)
+    }
+
+    pub const fn as_nanos(&self) -> u64 {
+        self.0
+    }
+
+    /// Time elapsed since the start of the clock.
+    pub const fn as_duration(&self) -> Duration {
+        Duration::from_nanos(self.0)
+    }
+
+    /// Gets the time elapsed from 'earlier' to this timestamp, or None if
+    /// 'earlier' is later than this timestamp.
+    pub fn checked_duration_since(&self, earlier: Timestamp) -> Option<Duration> {
+        self.0.checked_sub(earlier.0).map(Duration::from_nanos)
+    }
+
+    /// Returns the timestamp 'duration' after this one, or None if it can't
+    /// be represented.
+    pub fn checked_add(&self, duration: Duration) -> Option<Timestamp> {
+        let nanos = u64::try_from(duration.as_nanos()).ok()?;
+        self.0.checked_add(nanos).map(Self)
+    }
+
+    /// Returns the timestamp 'duration' before this one, or None if it would
+    /// be before the start of the clock.
+    pub fn checked_sub(&self, duration: Duration) -> Option<Timestamp> {
+        let nanos = u64::try_from(duration.as_nanos()).ok()?;
+        self.0.checked_sub(nanos).map(Self)
+    }
+
+    /// Same as checked_duration_since() but returns zero instead of None.
+    pub fn saturating_duration_since(&self, earlier: Timestamp) -> Duration {
+        self.checked_duration_since(earlier).unwrap_or_default()
+    }
+}
+
+impl Add<Duration> for Timestamp {
+    type Output = Self;
+
+    /// # Panics
+    ///
+    /// Panics if the result doesn't fit in a Timestamp. See checked_add() for
+    /// a version without panics.
+    fn add(self, rhs: Duration) -> Self {
+        self.checked_add(rhs)
+            .expect("overflow when adding duration to timestamp")
+    }
+}
+
+impl Sub<Duration> for Timestamp {
+    type Output = Self;
+
+    /// # Panics
+    ///
+    /// Panics if the result would be before the start of the clock. See
+    /// checked_sub() for a version without panics.
+    fn sub(self, rhs: Duration) -> Self {
+        self.checked_sub(rhs)
+            .expect("overflow when subtracting duration from timestamp")
+    }
+}
+
+/// Typed version of the FrameMetadata reported for a completed frame buffer.
+#[derive(Debug, Clone)]
+pub struct BufferMetadata {
+    pub status: FrameStatus,
+
+    /// Frame sequence number from the sensor. Gaps between consecutive
+    /// frames indicate that frames were dropped.
+    pub sequence: u32,
+
+    pub timestamp: Timestamp,
+
+    /// Number of bytes written to each plane of the buffer.
+    pub bytes_used: Vec<u32>,
+}
+
+impl From<ffi::FrameMetadata> for BufferMetadata {
+    fn from(value: ffi::FrameMetadata) -> Self {
+        Self {
+            status: value.status,
+            sequence: value.sequence,
+            timestamp: Timestamp::from_nanos(value.timestamp),
+            bytes_used: value
+                .planes
+                .iter()
+                .map(|plane| plane.inner.bytesused)
+                .collect(),
+        }
+    }
+}
+
+/// Commonly used values from the metadata of a completed request.
+///
+/// Each field is None if the camera didn't report it for the frame.
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FrameInfo {
+    /// Time at which the first line of the frame started being exposed.
+    pub sensor_timestamp: Option<Timestamp>,
+    pub exposure_time: Option<Duration>,
+    pub analogue_gain: Option<f32>,
+    pub digital_gain: Option<f32>,
+
+    /// Red and blue white balance gains.
+    pub colour_gains: Option<[f32; 2]>,
+
+    /// Estimated colour temperature in Kelvin.
+    pub colour_temperature: Option<i32>,
+    pub lux: Option<f32>,
+
+    /// Focus figure of merit (higher is sharper).
+    pub focus_fom: Option<i32>,
+
+    /// Lens position in dioptres.
+    pub lens_position: Option<f32>,
+    pub af_state: Option<AfStateEnum>,
+    pub ae_state: Option<AeStateEnum>,
+    pub frame_duration: Option<Duration>,
+
+    /// Sensor temperature in degrees Celsius.
+    pub sensor_temperature: Option<f32>,
+}
+
+impl FrameInfo {
+    pub fn from_metadata(metadata: &ControlList) -> Self {
+        let micros = |v: i64| Duration::from_micros(v.max(0) as u64);
+
+        Self {
+            sensor_timestamp: metadata
+                .get(controls::SensorTimestamp)
+                .map(|v| Timestamp::from_nanos(v.max(0) as u64)),
+            exposure_time: metadata
+                .get(controls::ExposureTime)
+                .map(|v| micros(v as i64)),
+            analogue_gain: metadata.get(controls::AnalogueGain),
+            digital_gain: metadata.get(controls::DigitalGain),
+            colour_gains: metadata.get(controls::ColourGains),
+            colour_temperature: metadata.get(controls::ColourTemperature),
+            lux: metadata.get(controls::Lux),
+            focus_fom: metadata.get(controls::FocusFoM),
+            lens_position: metadata.get(controls::LensPosition),
+            af_state: metadata.get(controls::AfState),
+            ae_state: metadata.get(controls::AeState),
+            frame_duration: metadata.get(controls::FrameDuration).map(micros),
+            sensor_temperature: metadata.get(controls::SensorTemperature),
+        }
+    }
+}
+
+/// Counters accumulated by a FrameTracker.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FrameStats {
+    /// Frames which were captured successfully.
+    pub frames: u64,
+
+    /// Frames which are missing based on gaps in the sequence numbers.
+    pub dropped: u64,
+
+    /// Number of gaps in the sequence numbers (each of which may include
+    /// several dropped frames).
+    pub gaps: u64,
+
+    pub cancelled: u64,
+    pub errors: u64,
+}
+
+/// Result of passing a frame to FrameTracker::observe().
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameEvent {
+    /// The frame directly followed the previous one.
+    Ok,
+
+    /// The given number of frames were dropped before this one.
+    Dropped(u32),
+
+    /// The sequence number went backwards (e.g. the camera was restarted).
+    Reset,
+
+    Cancelled,
+    Error,
+}
+
+/// Monitors the frames of a single stream for dropped frames and measures
+/// the frame rate.
+#[derive(Debug, Clone)]
+pub struct FrameTracker {
+    last: Option<(u32, Timestamp)>,
+
+    /// Recent intervals between frames divided by the number of sensor
+    /// frames they cover (so dropped frames don't count as jitter).
+    intervals: VecDeque<Duration>,
+    window: usize,
+
+    stats: FrameStats,
+}
+
+impl FrameTracker {
+    pub fn new() -> Self {
+        Self::with_window(DEFAULT_WINDOW)
+    }
+
+    /// Creates a tracker which computes FPS and jitter from the last 'window'
+    /// frame intervals.
+    pub fn with_window(window: usize) -> Self {
+        Self {
+            last: None,
+            intervals: VecDeque::new(),
+            window: window.max(1),
+            stats: FrameStats::default(),
+        }
+    }
+
+    /// Records the metadata of the next completed frame of the stream.
+    pub fn observe(&mut self, metadata: &BufferMetadata) -> FrameEvent {
+        match metadata.status {
+            FrameStatus::FrameCancelled => {
+                self.stats.cancelled += 1;
+                return FrameEvent::Cancelled;
+            }
+            FrameStatus::FrameError => {
+                self.stats.errors += 1;
+                return FrameEvent::Error;
+            }
+            _ => {}
+        }
+
+        self.stats.frames += 1;
+
+        let last = self.last.replace((metadata.sequence, metadata.timestamp));
+        let (last_sequence, last_timestamp) = match last {
+            Some(last) => last,
+            None => return FrameEvent::Ok,
+        };
+
+        if metadata.sequence <= last_sequence {
+            self.intervals.clear();
+            return FrameEvent::Reset;
+        }
+
+        let steps = metadata.sequence - last_sequence;
+        if let Some(interval) = metadata.timestamp.checked_duration_since(last_timestamp) {
+            if self.intervals.len() == self.window {
+                self.intervals.pop_front();
+            }
+            self.intervals.push_back(interval / steps);
+        }
+
+        if steps == 1 {
+            return FrameEvent::Ok;
+        }
+
+        let dropped = steps - 1;
+        self.stats.dropped += dropped as u64;
+        self.stats.gaps += 1;
+        FrameEvent::Dropped(dropped)
+    }
+
+    pub fn stats(&self) -> FrameStats {
+        self.stats
+    }
+
+    /// Average interval between sensor frames over the recent window.
+    pub fn frame_interval(&self) -> Option<Duration> {
+        if self.intervals.is_empty() {
+            return None;
+        }
+
+        Some(self.intervals.iter().sum::<Duration>() / self.intervals.len() as u32)
+    }
+
+    /// Measured sensor frame rate over the recent window.
+    pub fn fps(&self) -> Option<f64> {
+        let interval = self.frame_interval()?.as_secs_f64();
+        if interval == 0.0 {
+            return None;
+        }
+
+        Some(1.0 / interval)
+    }
+
+    /// Standard deviation of the interval between frames over the recent
+    /// window.
+    pub fn jitter(&self) -> Option<Duration> {
+        let mean = self.frame_interval()?.as_secs_f64();
+
+        let variance = self
+            .intervals
+            .iter()
+            .map(|interval| (interval.as_secs_f64() - mean).powi(2))
+            .sum::<f64>()
+            / self.intervals.len() as f64;
+
+        Some(Duration::from_secs_f64(variance.sqrt()))
+    }
+}
+
+impl Default for FrameTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(status: FrameStatus, sequence: u32, millis: u64) -> BufferMetadata {
+        BufferMetadata {
+            status,
+            sequence,
+            timestamp: Timestamp::from_nanos(millis * 1_000_000),
+            bytes_used: vec![],
+        }
+    }
+
+    fn ok(sequence: u32, millis: u64) -> BufferMetadata {
+        frame(FrameStatus::FrameSuccess, sequence, millis)
+    }
+
+    #[test]
+    fn timestamp_arithmetic() {
+        let t = Timestamp::from_nanos(1_000);
+        assert_eq!(t + Duration::from_nanos(500), Timestamp::from_nanos(1_500));
+        assert_eq!(t - Duration::from_nanos(500), Timestamp::from_nanos(500));
+
+        assert_eq!(t.checked_sub(Duration::from_nanos(1_001)), None);
+        assert_eq!(
+            Timestamp::from_nanos(u64::MAX).checked_add(Duration::from_nanos(1)),
+            None
+        );
+
+        // Durations longer than u64::MAX nanoseconds must not be truncated.
+        assert_eq!(t.checked_add(Duration::MAX), None);
+        assert_eq!(t.checked_sub(Duration::MAX), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn timestamp_sub_panics_before_start_of_clock() {
+        let _ = Timestamp::from_nanos(1_000) - Duration::from_micros(2);
+    }
+
+    #[test]
+    fn consecutive_frames() {
+        let mut tracker = FrameTracker::new();
+        for sequence in 0..4 {
+            assert_eq!(
+                tracker.observe(&ok(sequence, sequence as u64 * 40)),
+                FrameEvent::Ok
+            );
+        }
+
+        assert_eq!(
+            tracker.stats(),
+            FrameStats {
+                frames: 4,
+                ..Default::default()
+            }
+        );
+        assert_eq!(tracker.frame_interval(), Some(Duration::from_millis(40)));
+        assert_eq!(tracker.fps(), Some(25.0));
+        assert_eq!(tracker.jitter(), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn gap_reports_dropped_frames() {
+        let mut tracker = FrameTracker::new();
+        tracker.observe(&ok(10, 0));
+
+        // Frames 11 to 13 are missing. The interval is spread over the four
+        // sensor frames so the frame rate is unaffected.
+        assert_eq!(tracker.observe(&ok(14, 160)), FrameEvent::Dropped(3));
+        assert_eq!(tracker.observe(&ok(15, 200)), FrameEvent::Ok);
+        assert_eq!(tracker.observe(&ok(17, 280)), FrameEvent::Dropped(1));
+
+        let stats = tracker.stats();
+        assert_eq!(stats.frames, 4);
+        assert_eq!(stats.dropped, 4);
+        assert_eq!(stats.gaps, 2);
+        assert_eq!(tracker.frame_interval(), Some(Duration::from_millis(40)));
+        assert_eq!(tracker.jitter(), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn sequence_reset() {
+        let mut tracker = FrameTracker::new();
+        tracker.observe(&ok(100, 0));
+        tracker.observe(&ok(101, 40));
+
+        assert_eq!(tracker.observe(&ok(0, 1_000)), FrameEvent::Reset);
+        assert_eq!(tracker.frame_interval(), None);
+
+        assert_eq!(tracker.observe(&ok(1, 1_030)), FrameEvent::Ok);
+        assert_eq!(tracker.frame_interval(), Some(Duration::from_millis(30)));
+        assert_eq!(tracker.stats().dropped, 0);
+    }
+
+    #[test]
+    fn cancelled_and_failed_frames_are_counted() {
+        let mut tracker = FrameTracker::new();
+        tracker.observe(&ok(0, 0));
+
+        assert_eq!(
+            tracker.observe(&frame(FrameStatus::FrameCancelled, 1, 40)),
+            FrameEvent::Cancelled
+        );
+        assert_eq!(
+            tracker.observe(&frame(FrameStatus::FrameError, 2, 80)),
+            FrameEvent::Error
+        );
+
+        // Frames which weren't captured don't count towards the sequence.
+        assert_eq!(tracker.observe(&ok(3, 120)), FrameEvent::Dropped(2));
+
+        let stats = tracker.stats();
+        assert_eq!(stats.frames, 2);
+        assert_eq!(stats.cancelled, 1);
+        assert_eq!(stats.errors, 1);
+    }
+
+    #[test]
+    fn window_evicts_old_intervals() {
+        let mut tracker = FrameTracker::with_window(2);
+        tracker.observe(&ok(0, 0));
+        tracker.observe(&ok(1, 100));
+        tracker.observe(&ok(2, 140));
+        assert_eq!(tracker.frame_interval(), Some(Duration::from_millis(70)));
+        assert_eq!(tracker.jitter(), Some(Duration::from_millis(30)));
+
+        tracker.observe(&ok(3, 180));
+        assert_eq!(tracker.frame_interval(), Some(Duration::from_millis(40)));
+        assert_eq!(tracker.jitter(), Some(Duration::ZERO));
+    }
+}
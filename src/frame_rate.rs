@@ -0,0 +1,124 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use crate::camera::Camera;
+use crate::control_value::{ControlArrayValue, ControlPrimitiveValue, ControlValue};
+use crate::controls;
+use crate::frame_metadata::FrameInfo;
+
+const MICROS_PER_SECOND: f64 = 1_000_000.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FrameRateError {
+    /// The camera doesn't advertise the FrameDurationLimits control.
+    Unsupported,
+
+    /// The range is empty or contains non-positive rates.
+    InvalidRange(RangeInclusive<f64>),
+
+    /// The range isn't within the frame rates supported by the camera.
+    OutOfRange {
+        requested: RangeInclusive<f64>,
+        supported: RangeInclusive<f64>,
+    },
+}
+
+impl fmt::Display for FrameRateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameRateError::Unsupported => {
+                write!(f, "camera doesn't support setting the frame rate")
+            }
+            FrameRateError::InvalidRange(range) => {
+                write!(f, "invalid frame rate range {:?}", range)
+            }
+            FrameRateError::OutOfRange {
+                requested,
+                supported,
+            } => write!(
+                f,
+                "frame rate range {:?} is outside of the supported range {:?}",
+                requested, supported
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FrameRateError {}
+
+impl Camera {
+    /// Gets the range of frame durations (in microseconds) which can be
+    /// requested, from the camera's FrameDurationLimits control info.
+    pub(crate) fn frame_duration_limits(&self) -> Option<(i64, i64)> {
+        let info = self.controls().get(controls::FrameDurationLimits)?;
+
+        // Pipelines advertise the limits either as scalars or as a
+        // [min, max] array matching the control's value.
+        let (min, max) = match (info.min(), info.max()) {
+            (
+                ControlValue::Primitive(ControlPrimitiveValue::Int64(min)),
+                ControlValue::Primitive(ControlPrimitiveValue::Int64(max)),
+            ) => (min, max),
+            (
+                ControlValue::Array(ControlArrayValue::Int64(min)),
+                ControlValue::Array(ControlArrayValue::Int64(max)),
+            ) => (*min.first()?, *max.last()?),
+            _ => return None,
+        };
+
+        if min <= 0 || min > max {
+            return None;
+        }
+
+        Some((min, max))
+    }
+
+    /// Gets the range of frame rates (in frames per second) supported by the
+    /// camera.
+    pub fn frame_rate_limits(&self) -> Option<RangeInclusive<f64>> {
+        let (min, max) = self.frame_duration_limits()?;
+        Some(MICROS_PER_SECOND / max as f64..=MICROS_PER_SECOND / min as f64)
+    }
+
+    /// Converts a frame rate range to the FrameDurationLimits value which
+    /// requests it, checking it against the camera's limits.
+    pub(crate) fn frame_rate_to_durations(
+        &self,
+        range: RangeInclusive<f64>,
+    ) -> Result<[i64; 2], FrameRateError> {
+        let (start, end) = (*range.start(), *range.end());
+        if !(start > 0.0 && start <= end && end.is_finite()) {
+            return Err(FrameRateError::InvalidRange(range));
+        }
+
+        let (min, max) = self
+            .frame_duration_limits()
+            .ok_or(FrameRateError::Unsupported)?;
+
+        // The fastest rate gives the shortest frame duration.
+        let min_duration = (MICROS_PER_SECOND / end).round() as i64;
+        let max_duration = (MICROS_PER_SECOND / start).round() as i64;
+
+        if min_duration < min || max_duration > max {
+            return Err(FrameRateError::OutOfRange {
+                requested: range,
+                supported: MICROS_PER_SECOND / max as f64..=MICROS_PER_SECOND / min as f64,
+            });
+        }
+
+        Ok([min_duration, max_duration])
+    }
+}
+
+impl FrameInfo {
+    /// Frame rate the camera was running at when the frame was captured,
+    /// computed from the reported FrameDuration.
+    pub fn frame_rate(&self) -> Option<f64> {
+        let duration = self.frame_duration?.as_secs_f64();
+        if duration == 0.0 {
+            return None;
+        }
+
+        Some(1.0 / duration)
+    }
+}
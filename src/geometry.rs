@@ -0,0 +1,439 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Div, Mul, Neg};
+
+pub use crate::ffi::{Rectangle, Size, SizeRange};
+
+/// A position in a 2D plane. Mirrors libcamera::Point.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Point {
+    pub const fn new(x: i32, y: i32) -> Self {
+        Self { x, y }
+    }
+}
+
+impl Neg for Point {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::new(-self.x, -self.y)
+    }
+}
+
+impl fmt::Display for Point {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({}, {})", self.x, self.y)
+    }
+}
+
+impl Size {
+    pub const fn new(width: u32, height: u32) -> Self {
+        Self { width, height }
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.width == 0 && self.height == 0
+    }
+
+    fn area(&self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+
+    /// Rounds each dimension down to a multiple of the given alignment.
+    pub fn aligned_down_to(&self, h_alignment: u32, v_alignment: u32) -> Self {
+        Self::new(
+            self.width / h_alignment * h_alignment,
+            self.height / v_alignment * v_alignment,
+        )
+    }
+
+    /// Rounds each dimension up to a multiple of the given alignment.
+    pub fn aligned_up_to(&self, h_alignment: u32, v_alignment: u32) -> Self {
+        Self::new(
+            (self.width + h_alignment - 1) / h_alignment * h_alignment,
+            (self.height + v_alignment - 1) / v_alignment * v_alignment,
+        )
+    }
+
+    /// Gets the size with each dimension no larger than that of 'bound'.
+    pub fn bounded_to(&self, bound: Size) -> Self {
+        Self::new(self.width.min(bound.width), self.height.min(bound.height))
+    }
+
+    /// Gets the size with each dimension no smaller than that of 'expand'.
+    pub fn expanded_to(&self, expand: Size) -> Self {
+        Self::new(self.width.max(expand.width), self.height.max(expand.height))
+    }
+
+    /// Gets the largest size with the aspect ratio of 'ratio' which fits
+    /// within this size.
+    pub fn bounded_to_aspect_ratio(&self, ratio: Size) -> Self {
+        let ratio1 = self.width as u64 * ratio.height as u64;
+        let ratio2 = ratio.width as u64 * self.height as u64;
+
+        match ratio1.cmp(&ratio2) {
+            Ordering::Greater => Self::new((ratio2 / ratio.height as u64) as u32, self.height),
+            Ordering::Less => Self::new(self.width, (ratio1 / ratio.width as u64) as u32),
+            Ordering::Equal => *self,
+        }
+    }
+
+    /// Gets the smallest size with the aspect ratio of 'ratio' which
+    /// contains this size.
+    pub fn expanded_to_aspect_ratio(&self, ratio: Size) -> Self {
+        let ratio1 = self.width as u64 * ratio.height as u64;
+        let ratio2 = ratio.width as u64 * self.height as u64;
+
+        match ratio1.cmp(&ratio2) {
+            Ordering::Less => Self::new((ratio2 / ratio.height as u64) as u32, self.height),
+            Ordering::Greater => Self::new(self.width, (ratio1 / ratio.width as u64) as u32),
+            Ordering::Equal => *self,
+        }
+    }
+
+    /// Gets a rectangle of this size whose center is at the given point.
+    pub fn centered_to(&self, center: Point) -> Rectangle {
+        Rectangle {
+            x: center.x - (self.width / 2) as i32,
+            y: center.y - (self.height / 2) as i32,
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    /// Scales each dimension by numerator / denominator.
+    pub fn scaled_by(&self, numerator: Size, denominator: Size) -> Self {
+        Self::new(
+            (self.width as u64 * numerator.width as u64 / denominator.width as u64) as u32,
+            (self.height as u64 * numerator.height as u64 / denominator.height as u64) as u32,
+        )
+    }
+}
+
+impl Mul<f32> for Size {
+    type Output = Self;
+
+    fn mul(self, factor: f32) -> Self {
+        Self::new(
+            (self.width as f32 * factor) as u32,
+            (self.height as f32 * factor) as u32,
+        )
+    }
+}
+
+impl Div<f32> for Size {
+    type Output = Self;
+
+    fn div(self, factor: f32) -> Self {
+        Self::new(
+            (self.width as f32 / factor) as u32,
+            (self.height as f32 / factor) as u32,
+        )
+    }
+}
+
+/// Sizes are ordered by area, then width, then height.
+///
+/// For non-zero sizes this is the same as libcamera's ordering (where a size
+/// which is smaller in both dimensions is always smaller).
+impl Ord for Size {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.area(), self.width, self.height).cmp(&(other.area(), other.width, other.height))
+    }
+}
+
+impl PartialOrd for Size {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+impl fmt::Display for SizeRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "({})-({})/(+{},+{})",
+            self.min, self.max, self.hStep, self.vStep
+        )
+    }
+}
+
+impl Rectangle {
+    pub const fn new(x: i32, y: i32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn from_size(size: Size) -> Self {
+        Self::new(0, 0, size.width, size.height)
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.width == 0 && self.height == 0
+    }
+
+    pub fn size(&self) -> Size {
+        Size::new(self.width, self.height)
+    }
+
+    pub fn top_left(&self) -> Point {
+        Point::new(self.x, self.y)
+    }
+
+    pub fn center(&self) -> Point {
+        Point::new(
+            (self.x as i64 + self.width as i64 / 2) as i32,
+            (self.y as i64 + self.height as i64 / 2) as i32,
+        )
+    }
+
+    /// Checks whether a point lies within the rectangle.
+    pub fn contains(&self, point: Point) -> bool {
+        let x = point.x as i64 - self.x as i64;
+        let y = point.y as i64 - self.y as i64;
+        x >= 0 && y >= 0 && x < self.width as i64 && y < self.height as i64
+    }
+
+    /// Gets the rectangle moved by the given offset.
+    pub fn translated_by(&self, offset: Point) -> Self {
+        Self::new(
+            self.x + offset.x,
+            self.y + offset.y,
+            self.width,
+            self.height,
+        )
+    }
+
+    /// Scales the position and size of the rectangle by numerator /
+    /// denominator, e.g. to convert between two resolutions.
+    pub fn scaled_by(&self, numerator: Size, denominator: Size) -> Self {
+        Self::new(
+            (self.x as i64 * numerator.width as i64 / denominator.width as i64) as i32,
+            (self.y as i64 * numerator.height as i64 / denominator.height as i64) as i32,
+            (self.width as u64 * numerator.width as u64 / denominator.width as u64) as u32,
+            (self.height as u64 * numerator.height as u64 / denominator.height as u64) as u32,
+        )
+    }
+
+    /// Gets the area covered by both rectangles, or None if they don't
+    /// overlap.
+    pub fn intersect(&self, other: &Rectangle) -> Option<Self> {
+        let x0 = (self.x as i64).max(other.x as i64);
+        let y0 = (self.y as i64).max(other.y as i64);
+        let x1 = (self.x as i64 + self.width as i64).min(other.x as i64 + other.width as i64);
+        let y1 = (self.y as i64 + self.height as i64).min(other.y as i64 + other.height as i64);
+
+        if x1 <= x0 || y1 <= y0 {
+            return None;
+        }
+
+        Some(Self::new(
+            x0 as i32,
+            y0 as i32,
+            (x1 - x0) as u32,
+            (y1 - y0) as u32,
+        ))
+    }
+
+    /// Gets a rectangle no larger than 'boundary' which has been moved (if
+    /// needed) to lie entirely within it.
+    pub fn enclosed_in(&self, boundary: &Rectangle) -> Self {
+        let size = self.size().bounded_to(boundary.size());
+        let max_x = boundary.x as i64 + (boundary.width - size.width) as i64;
+        let max_y = boundary.y as i64 + (boundary.height - size.height) as i64;
+
+        Self::new(
+            (self.x as i64).clamp(boundary.x as i64, max_x) as i32,
+            (self.y as i64).clamp(boundary.y as i64, max_y) as i32,
+            size.width,
+            size.height,
+        )
+    }
+
+    /// Maps the rectangle from the coordinate space described by 'source'
+    /// into the one described by 'target'.
+    ///
+    /// e.g. a region selected in a scaled output image can be mapped back
+    /// into sensor coordinates (for ScalerCrop or AfWindows) with
+    /// `region.transformed_between(&Rectangle::from_size(output_size),
+    /// &scaler_crop)`.
+    pub fn transformed_between(&self, source: &Rectangle, target: &Rectangle) -> Self {
+        let sx = target.width as f64 / source.width as f64;
+        let sy = target.height as f64 / source.height as f64;
+
+        Self::new(
+            ((self.x as f64 - source.x as f64) * sx) as i32 + target.x,
+            ((self.y as f64 - source.y as f64) * sy) as i32 + target.y,
+            (self.width as f64 * sx) as u32,
+            (self.height as f64 * sy) as u32,
+        )
+    }
+}
+
+impl fmt::Display for Rectangle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.top_left(), self.size())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn size_alignment() {
+        let size = Size::new(1927, 1083);
+        assert_eq!(size.aligned_down_to(16, 8), Size::new(1920, 1080));
+        assert_eq!(size.aligned_up_to(16, 8), Size::new(1936, 1088));
+    }
+
+    #[test]
+    fn size_bounds() {
+        let size = Size::new(1920, 1080);
+        assert_eq!(
+            size.bounded_to(Size::new(1280, 1440)),
+            Size::new(1280, 1080)
+        );
+        assert_eq!(
+            size.expanded_to(Size::new(1280, 1440)),
+            Size::new(1920, 1440)
+        );
+    }
+
+    #[test]
+    fn size_aspect_ratio() {
+        let sensor = Size::new(4056, 3040);
+        let ratio = Size::new(16, 9);
+        assert_eq!(sensor.bounded_to_aspect_ratio(ratio), Size::new(4056, 2281));
+        assert_eq!(
+            sensor.expanded_to_aspect_ratio(ratio),
+            Size::new(5404, 3040)
+        );
+
+        let size = Size::new(1920, 1080);
+        assert_eq!(
+            size.bounded_to_aspect_ratio(Size::new(4, 3)),
+            Size::new(1440, 1080)
+        );
+        assert_eq!(
+            size.expanded_to_aspect_ratio(Size::new(4, 3)),
+            Size::new(1920, 1440)
+        );
+        assert_eq!(size.bounded_to_aspect_ratio(ratio), size);
+    }
+
+    #[test]
+    fn size_scaling() {
+        let size = Size::new(1920, 1080);
+        assert_eq!(
+            size.scaled_by(Size::new(1, 1), Size::new(2, 3)),
+            Size::new(960, 360)
+        );
+        assert_eq!(Size::new(100, 50) * 1.5, Size::new(150, 75));
+        assert_eq!(Size::new(100, 50) / 4.0, Size::new(25, 12));
+    }
+
+    #[test]
+    fn size_ordering() {
+        assert!(Size::new(10, 10) > Size::new(99, 1));
+        assert!(Size::new(4, 1) < Size::new(1, 5));
+        assert!(Size::new(2, 3) < Size::new(3, 2));
+        assert_eq!(
+            Size::new(640, 480).max(Size::new(480, 640)),
+            Size::new(640, 480)
+        );
+    }
+
+    #[test]
+    fn size_centered_to() {
+        assert_eq!(
+            Size::new(100, 50).centered_to(Point::new(10, 20)),
+            Rectangle::new(-40, -5, 100, 50)
+        );
+    }
+
+    #[test]
+    fn rectangle_points() {
+        let rect = Rectangle::new(10, 20, 100, 50);
+        assert_eq!(rect.top_left(), Point::new(10, 20));
+        assert_eq!(rect.center(), Point::new(60, 45));
+
+        assert!(rect.contains(Point::new(10, 20)));
+        assert!(rect.contains(Point::new(109, 69)));
+        assert!(!rect.contains(Point::new(110, 20)));
+        assert!(!rect.contains(Point::new(9, 20)));
+
+        assert_eq!(
+            rect.translated_by(Point::new(-10, 5)),
+            Rectangle::new(0, 25, 100, 50)
+        );
+        assert_eq!(
+            rect.translated_by(-Point::new(10, 20)),
+            Rectangle::new(0, 0, 100, 50)
+        );
+    }
+
+    #[test]
+    fn rectangle_scaling() {
+        let rect = Rectangle::new(10, 20, 100, 50);
+        assert_eq!(
+            rect.scaled_by(Size::new(2, 1), Size::new(1, 2)),
+            Rectangle::new(20, 10, 200, 25)
+        );
+    }
+
+    #[test]
+    fn rectangle_intersect() {
+        let rect = Rectangle::new(0, 0, 100, 100);
+        assert_eq!(
+            rect.intersect(&Rectangle::new(50, -10, 100, 50)),
+            Some(Rectangle::new(50, 0, 50, 40))
+        );
+        assert_eq!(rect.intersect(&Rectangle::new(100, 0, 10, 10)), None);
+    }
+
+    #[test]
+    fn rectangle_enclosed_in() {
+        let boundary = Rectangle::new(0, 0, 100, 100);
+        assert_eq!(
+            Rectangle::new(-5, 95, 20, 20).enclosed_in(&boundary),
+            Rectangle::new(0, 80, 20, 20)
+        );
+        assert_eq!(
+            Rectangle::new(10, 10, 200, 50).enclosed_in(&boundary),
+            Rectangle::new(0, 10, 100, 50)
+        );
+    }
+
+    #[test]
+    fn rectangle_transformed_between() {
+        let region = Rectangle::new(480, 270, 960, 540);
+        let output = Rectangle::from_size(Size::new(1920, 1080));
+        let crop = Rectangle::new(100, 200, 3840, 2160);
+        assert_eq!(
+            region.transformed_between(&output, &crop),
+            Rectangle::new(1060, 740, 1920, 1080)
+        );
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(Size::new(1920, 1080).to_string(), "1920x1080");
+        assert_eq!(Rectangle::new(-1, 2, 3, 4).to_string(), "(-1, 2)/3x4");
+    }
+}
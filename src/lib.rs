@@ -1,21 +1,45 @@
+mod autofocus;
+pub mod bayer;
+mod bracket;
 mod camera;
 mod camera_configuration;
+mod camera_configuration_spec;
 mod camera_manager;
+mod clock;
+mod completion;
 mod control;
 mod control_info;
 mod control_info_map;
+mod control_latency;
 mod control_list;
 mod control_value;
 pub mod controls;
+pub mod convert;
+pub mod dng;
+#[cfg(feature = "image")]
+mod encode;
 mod errors;
+mod exposure;
 mod ffi;
+pub mod formats;
 mod frame_buffer;
 mod frame_buffer_allocator;
+mod frame_metadata;
+mod frame_rate;
+mod geometry;
+#[cfg(feature = "mjpeg")]
+pub mod mjpeg;
 mod pixel_format;
+mod pixel_format_info;
+pub mod properties;
 mod request;
+mod sensor_configuration;
 mod stream;
 mod stream_configuration;
 mod stream_formats;
+mod synced_capture;
+mod transform;
+mod zoom;
 
 mod bindings {
     #![allow(non_upper_case_globals)]
@@ -30,23 +54,39 @@ mod bindings {
     pub use raw::root::libcamera::*;
 }
 
-pub use bindings::formats;
+pub use autofocus::*;
+pub use bracket::*;
 pub use camera::*;
 pub use camera_configuration::*;
+pub use camera_configuration_spec::*;
 pub use camera_manager::*;
+pub use clock::*;
+pub use completion::*;
 pub use control::Control;
 pub use control::*;
 pub use control_info::*;
 pub use control_info_map::*;
+pub use control_latency::*;
 pub use control_list::*;
 pub use control_value::*;
+#[cfg(feature = "image")]
+pub use encode::*;
 pub use errors::*;
+pub use exposure::*;
 pub use frame_buffer::*;
 pub use frame_buffer_allocator::*;
+pub use frame_metadata::*;
+pub use frame_rate::*;
+pub use geometry::*;
 pub use pixel_format::*;
+pub use pixel_format_info::*;
 pub use request::*;
+pub use sensor_configuration::*;
 pub use stream::*;
 pub use stream_configuration::*;
 pub use stream_formats::*;
+pub use synced_capture::*;
+pub use transform::*;
+pub use zoom::*;
 
 pub use crate::ffi::{CameraConfigurationStatus, FrameBufferPlane, StreamRole};
@@ -104,6 +104,14 @@ fn main() -> Result<()> {
     println!("Timestamp: {}", frame_buffer.metadata().timestamp);
     println!("Size: {}", used_memory.len());
 
+    #[cfg(feature = "image")]
+    frame_buffer
+        .save_jpeg(stream_config, "image.jpeg", 90)
+        .unwrap();
+
+    // Without the image feature, this only produces a valid file if the
+    // stream happens to be MJPEG.
+    #[cfg(not(feature = "image"))]
     std::fs::write("image.jpeg", used_memory).unwrap();
 
     println!("Written!");
@@ -0,0 +1,423 @@
+//! Validation and decoding of frames from MJPEG streams.
+//!
+//! Only available with the "mjpeg" feature.
+//!
+//! Many UVC cameras produce frames which aren't standalone JPEG files: the
+//! Huffman tables are often omitted (the standard tables from Annex K of the
+//! JPEG spec are implied) and the buffer may contain padding after the end of
+//! the image. Frames may also be truncated if the device dropped data.
+
+use std::borrow::Cow;
+use std::fmt;
+
+use jpeg_decoder::{ColorTransform, Decoder};
+
+use crate::frame_buffer::{FrameBuffer, FrameStatus};
+
+#[derive(Debug)]
+pub enum MjpegError {
+    /// libcamera reported that the frame wasn't captured successfully.
+    FrameFailed(FrameStatus),
+
+    /// The frame buffer's memory hasn't been mapped with
+    /// FrameBuffer::map_memory().
+    NotMapped,
+
+    /// The frame buffer has more than one plane, so doesn't contain a single
+    /// compressed image.
+    NotContiguous,
+
+    /// The data doesn't start with a JPEG SOI marker.
+    MissingStartOfImage,
+
+    /// The data doesn't contain a JPEG EOI marker (usually because the frame
+    /// was truncated).
+    MissingEndOfImage,
+
+    /// The marker segments before the image data are malformed.
+    Malformed(&'static str),
+
+    /// The decoder failed to decode the entropy coded data.
+    Decode(jpeg_decoder::Error),
+}
+
+impl fmt::Display for MjpegError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MjpegError::FrameFailed(status) => write!(f, "Frame not captured: {:?}", status),
+            MjpegError::NotMapped => write!(f, "Frame buffer memory is not mapped"),
+            MjpegError::NotContiguous => write!(f, "Frame buffer has more than one plane"),
+            MjpegError::MissingStartOfImage => write!(f, "Missing JPEG start of image marker"),
+            MjpegError::MissingEndOfImage => write!(f, "Missing JPEG end of image marker"),
+            MjpegError::Malformed(reason) => write!(f, "Malformed JPEG: {}", reason),
+            MjpegError::Decode(e) => write!(f, "Failed to decode JPEG: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for MjpegError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MjpegError::Decode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Colour space of the decoded pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MjpegOutput {
+    /// 3 bytes per pixel in R, G, B order.
+    Rgb8,
+    /// 3 bytes per pixel in Y, Cb, Cr order (chroma is upsampled to full
+    /// resolution).
+    Yuv444,
+}
+
+/// Pixel format of a DecodedFrame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodedFormat {
+    Rgb8,
+    Yuv444,
+    /// Greyscale JPEGs are always decoded to 1 byte per pixel.
+    Gray8,
+}
+
+#[derive(Debug, Clone)]
+pub struct DecodedFrame {
+    pub width: u32,
+    pub height: u32,
+    pub format: DecodedFormat,
+    /// Tightly packed pixels.
+    pub data: Vec<u8>,
+}
+
+/// Checks that the data contains a complete JPEG image.
+///
+/// Returns the data trimmed to end at the EOI marker.
+pub fn validate(data: &[u8]) -> Result<&[u8], MjpegError> {
+    if data.len() < 2 || data[0] != 0xFF || data[1] != SOI {
+        return Err(MjpegError::MissingStartOfImage);
+    }
+
+    let scan = find_scan(data)?;
+
+    // NOTE: 0xFF bytes in the entropy coded data are always followed by 0x00
+    // or a restart marker, so the first EOI after the scan starts is the end
+    // of the image. Anything after it is padding or stale data left in the
+    // buffer by an earlier, larger frame.
+    let end = data[scan.offset..]
+        .windows(2)
+        .position(|w| w[0] == 0xFF && w[1] == EOI)
+        .ok_or(MjpegError::MissingEndOfImage)?;
+
+    Ok(&data[..(scan.offset + end + 2)])
+}
+
+/// Validates the data and inserts the standard Huffman tables if the image
+/// doesn't define any.
+///
+/// The returned data is a standalone JPEG file.
+pub fn repair(data: &[u8]) -> Result<Cow<'_, [u8]>, MjpegError> {
+    let data = validate(data)?;
+    let scan = find_scan(data)?;
+
+    if scan.has_huffman_tables {
+        return Ok(Cow::Borrowed(data));
+    }
+
+    let mut out = Vec::with_capacity(data.len() + 512);
+    out.extend_from_slice(&data[..scan.offset]);
+    append_default_huffman_tables(&mut out);
+    out.extend_from_slice(&data[scan.offset..]);
+    Ok(Cow::Owned(out))
+}
+
+/// Validates, repairs and decodes a single MJPEG frame.
+pub fn decode(data: &[u8], output: MjpegOutput) -> Result<DecodedFrame, MjpegError> {
+    let data = repair(data)?;
+
+    let mut decoder = Decoder::new(&data[..]);
+    if output == MjpegOutput::Yuv444 {
+        decoder.set_color_transform(ColorTransform::None);
+    }
+
+    let pixels = decoder.decode().map_err(MjpegError::Decode)?;
+
+    // Info is always available after a successful decode.
+    let info = decoder.info().unwrap();
+
+    let format = match info.pixel_format {
+        jpeg_decoder::PixelFormat::L8 => DecodedFormat::Gray8,
+        jpeg_decoder::PixelFormat::RGB24 => match output {
+            MjpegOutput::Rgb8 => DecodedFormat::Rgb8,
+            MjpegOutput::Yuv444 => DecodedFormat::Yuv444,
+        },
+        _ => return Err(MjpegError::Malformed("unsupported number of components")),
+    };
+
+    Ok(DecodedFrame {
+        width: info.width as u32,
+        height: info.height as u32,
+        format,
+        data: pixels,
+    })
+}
+
+impl FrameBuffer {
+    /// Decodes the MJPEG frame most recently captured into this buffer.
+    ///
+    /// The memory must first be mapped using map_memory().
+    pub fn decode_mjpeg(&self, output: MjpegOutput) -> Result<DecodedFrame, MjpegError> {
+        let status = self.metadata().status;
+        if status != FrameStatus::FrameSuccess {
+            return Err(MjpegError::FrameFailed(status));
+        }
+
+        if self.memory().is_none() {
+            return Err(MjpegError::NotMapped);
+        }
+
+        // NOTE: Some devices report bytesused as the full buffer length, so
+        // the data is always trimmed to the EOI marker.
+        let data = self
+            .single_plane_memory()
+            .ok_or(MjpegError::NotContiguous)?;
+        decode(data, output)
+    }
+}
+
+const SOI: u8 = 0xD8;
+const EOI: u8 = 0xD9;
+const SOS: u8 = 0xDA;
+const DHT: u8 = 0xC4;
+
+struct ScanInfo {
+    /// Offset of the first SOS marker.
+    offset: usize,
+
+    /// Whether any DHT segments appear before the first scan.
+    has_huffman_tables: bool,
+}
+
+/// Walks the marker segments before the first scan.
+fn find_scan(data: &[u8]) -> Result<ScanInfo, MjpegError> {
+    let mut has_huffman_tables = false;
+
+    // Skip SOI.
+    let mut i = 2;
+
+    loop {
+        if i >= data.len() || data[i] != 0xFF {
+            return Err(MjpegError::Malformed("expected marker"));
+        }
+
+        // Markers may be preceded by any number of fill bytes.
+        let start = i;
+        while i < data.len() && data[i] == 0xFF {
+            i += 1;
+        }
+
+        let marker = *data
+            .get(i)
+            .ok_or(MjpegError::Malformed("truncated marker"))?;
+        i += 1;
+
+        match marker {
+            SOS => {
+                return Ok(ScanInfo {
+                    offset: start,
+                    has_huffman_tables,
+                })
+            }
+            EOI => return Err(MjpegError::Malformed("no image data")),
+            // Standalone markers without a length.
+            0x01 | 0xD0..=0xD7 => continue,
+            0x00 | SOI => return Err(MjpegError::Malformed("unexpected marker")),
+            DHT => has_huffman_tables = true,
+            _ => {}
+        }
+
+        if i + 2 > data.len() {
+            return Err(MjpegError::Malformed("truncated segment"));
+        }
+
+        let length = u16::from_be_bytes([data[i], data[i + 1]]) as usize;
+        if length < 2 || i + length > data.len() {
+            return Err(MjpegError::Malformed("invalid segment length"));
+        }
+
+        i += length;
+    }
+}
+
+/// Appends a DHT segment containing the tables in section K.3 of the JPEG
+/// spec. These are assigned to slot 0 for luminance and 1 for chrominance.
+fn append_default_huffman_tables(out: &mut Vec<u8>) {
+    let tables: [(u8, &[u8; 16], &[u8]); 4] = [
+        (0x00, &DC_LUMINANCE_BITS, &DC_LUMINANCE_VALUES),
+        (0x10, &AC_LUMINANCE_BITS, &AC_LUMINANCE_VALUES),
+        (0x01, &DC_CHROMINANCE_BITS, &DC_CHROMINANCE_VALUES),
+        (0x11, &AC_CHROMINANCE_BITS, &AC_CHROMINANCE_VALUES),
+    ];
+
+    let length: usize = 2 + tables
+        .iter()
+        .map(|(_, bits, values)| 1 + bits.len() + values.len())
+        .sum::<usize>();
+
+    out.extend_from_slice(&[0xFF, DHT]);
+    out.extend_from_slice(&(length as u16).to_be_bytes());
+
+    for (class_and_id, bits, values) in tables {
+        out.push(class_and_id);
+        out.extend_from_slice(bits);
+        out.extend_from_slice(values);
+    }
+}
+
+const DC_LUMINANCE_BITS: [u8; 16] = [0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0];
+const DC_LUMINANCE_VALUES: [u8; 12] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+
+const DC_CHROMINANCE_BITS: [u8; 16] = [0, 3, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0];
+const DC_CHROMINANCE_VALUES: [u8; 12] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+
+const AC_LUMINANCE_BITS: [u8; 16] = [0, 2, 1, 3, 3, 2, 4, 3, 5, 5, 4, 4, 0, 0, 1, 0x7d];
+#[rustfmt::skip]
+const AC_LUMINANCE_VALUES: [u8; 162] = [
+    0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12, 0x21, 0x31, 0x41, 0x06, 0x13, 0x51, 0x61, 0x07,
+    0x22, 0x71, 0x14, 0x32, 0x81, 0x91, 0xa1, 0x08, 0x23, 0x42, 0xb1, 0xc1, 0x15, 0x52, 0xd1, 0xf0,
+    0x24, 0x33, 0x62, 0x72, 0x82, 0x09, 0x0a, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x25, 0x26, 0x27, 0x28,
+    0x29, 0x2a, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49,
+    0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69,
+    0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89,
+    0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7,
+    0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3, 0xc4, 0xc5,
+    0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda, 0xe1, 0xe2,
+    0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8,
+    0xf9, 0xfa,
+];
+
+const AC_CHROMINANCE_BITS: [u8; 16] = [0, 2, 1, 2, 4, 4, 3, 4, 7, 5, 4, 4, 0, 1, 2, 0x77];
+#[rustfmt::skip]
+const AC_CHROMINANCE_VALUES: [u8; 162] = [
+    0x00, 0x01, 0x02, 0x03, 0x11, 0x04, 0x05, 0x21, 0x31, 0x06, 0x12, 0x41, 0x51, 0x07, 0x61, 0x71,
+    0x13, 0x22, 0x32, 0x81, 0x08, 0x14, 0x42, 0x91, 0xa1, 0xb1, 0xc1, 0x09, 0x23, 0x33, 0x52, 0xf0,
+    0x15, 0x62, 0x72, 0xd1, 0x0a, 0x16, 0x24, 0x34, 0xe1, 0x25, 0xf1, 0x17, 0x18, 0x19, 0x1a, 0x26,
+    0x27, 0x28, 0x29, 0x2a, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48,
+    0x49, 0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68,
+    0x69, 0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87,
+    0x88, 0x89, 0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5,
+    0xa6, 0xa7, 0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3,
+    0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda,
+    0xe2, 0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8,
+    0xf9, 0xfa,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An 8x8 greyscale baseline JPEG without Huffman tables, whose single
+    /// block has no DC difference and no AC coefficients (i.e. mid grey).
+    fn frame() -> Vec<u8> {
+        let mut data = vec![0xFF, SOI];
+
+        // DQT: 8-bit table 0 with all quantizers set to 1.
+        data.extend_from_slice(&[0xFF, 0xDB, 0x00, 0x43, 0x00]);
+        data.extend_from_slice(&[1; 64]);
+
+        // SOF0: 8x8, one component with id 1, no subsampling, table 0.
+        data.extend_from_slice(&[
+            0xFF, 0xC0, 0x00, 0x0B, 0x08, 0x00, 0x08, 0x00, 0x08, 0x01, 0x01, 0x11, 0x00,
+        ]);
+
+        // SOS: component 1 using Huffman tables 0.
+        data.extend_from_slice(&[0xFF, SOS, 0x00, 0x08, 0x01, 0x01, 0x00, 0x00, 0x3F, 0x00]);
+
+        // DC category 0 ("00") followed by EOB ("1010"), padded with 1s.
+        data.push(0b0010_1011);
+
+        data.extend_from_slice(&[0xFF, EOI]);
+        data
+    }
+
+    #[test]
+    fn missing_start_of_image() {
+        let data = frame();
+        assert!(matches!(
+            validate(&data[2..]),
+            Err(MjpegError::MissingStartOfImage)
+        ));
+        assert!(matches!(
+            validate(&[]),
+            Err(MjpegError::MissingStartOfImage)
+        ));
+    }
+
+    #[test]
+    fn missing_end_of_image() {
+        let data = frame();
+        assert!(matches!(
+            validate(&data[..data.len() - 2]),
+            Err(MjpegError::MissingEndOfImage)
+        ));
+    }
+
+    #[test]
+    fn trims_at_first_end_of_image() {
+        let image = frame();
+
+        // Padding followed by the tail of a stale, larger frame.
+        let mut data = image.clone();
+        data.extend_from_slice(&[0x00; 16]);
+        data.extend_from_slice(&[0x12, 0x34, 0xFF, 0x00, 0x56, 0xFF, EOI]);
+
+        assert_eq!(validate(&data).unwrap(), &image[..]);
+    }
+
+    #[test]
+    fn inserts_default_huffman_tables() {
+        let data = frame();
+        let scan = find_scan(&data).unwrap();
+        assert!(!scan.has_huffman_tables);
+
+        let repaired = repair(&data).unwrap();
+        assert!(matches!(repaired, Cow::Owned(_)));
+        assert!(find_scan(&repaired).unwrap().has_huffman_tables);
+
+        // The tables go right before the scan, which is otherwise unchanged.
+        assert_eq!(&repaired[..scan.offset], &data[..scan.offset]);
+        assert_eq!(&repaired[scan.offset..scan.offset + 2], &[0xFF, DHT]);
+        assert!(repaired.ends_with(&data[scan.offset..]));
+
+        // Images which already have tables are passed through.
+        assert!(matches!(repair(&repaired).unwrap(), Cow::Borrowed(_)));
+
+        let decoded = decode(&data, MjpegOutput::Rgb8).unwrap();
+        assert_eq!((decoded.width, decoded.height), (8, 8));
+        assert_eq!(decoded.format, DecodedFormat::Gray8);
+        assert!(decoded.data.iter().all(|v| *v == 128), "{:?}", decoded.data);
+    }
+
+    #[test]
+    fn truncated_segment_is_malformed() {
+        let data = frame();
+
+        // Cut inside the DQT segment's length and inside its payload.
+        for len in [7, 20] {
+            let mut truncated = data[..len].to_vec();
+            truncated.extend_from_slice(&[0xFF, EOI]);
+            assert!(
+                matches!(validate(&truncated), Err(MjpegError::Malformed(_))),
+                "{}",
+                len
+            );
+        }
+
+        // A segment claiming to be longer than the data.
+        let mut data = frame();
+        data[5] = 0xFF;
+        assert!(matches!(validate(&data), Err(MjpegError::Malformed(_))));
+    }
+}
@@ -1,31 +1,97 @@
-use std::fmt::{write, Debug};
+use std::fmt::Debug;
+use std::str::FromStr;
 
 use crate::ffi;
+use crate::pixel_format_info::PixelFormatInfo;
 
 pub use ffi::PixelFormat;
 
+// NOTE: PartialEq, Eq, PartialOrd, Ord and Hash are derived by bindgen (see
+// build.rs). Like in C++, formats are ordered by fourcc and then modifier.
+
+impl PixelFormat {
+    pub const fn new(fourcc: u32, modifier: u64) -> Self {
+        Self {
+            fourcc_: fourcc,
+            modifier_: modifier,
+        }
+    }
+
+    /// Creates a format with no modifier from its four character code (e.g.
+    /// `*b"NV12"`).
+    pub const fn from_fourcc(fourcc: [u8; 4]) -> Self {
+        Self::new(u32::from_le_bytes(fourcc), 0)
+    }
+
+    /// Gets the DRM four character code of this format.
+    pub fn fourcc(&self) -> [u8; 4] {
+        self.fourcc_.to_le_bytes()
+    }
+
+    /// Gets the DRM format modifier (e.g. for CSI-2 packed variants of a
+    /// format).
+    pub fn modifier(&self) -> u64 {
+        self.modifier_
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.fourcc_ != 0
+    }
+
+    /// Looks up information about the memory layout of this format.
+    ///
+    /// Returns None for formats which aren't known to this crate.
+    pub fn info(&self) -> Option<&'static PixelFormatInfo> {
+        PixelFormatInfo::lookup(*self)
+    }
+}
+
 impl ToString for PixelFormat {
     fn to_string(&self) -> String {
         ffi::pixel_format_to_string(self)
     }
 }
 
+/// NOTE: Unknown names produce an invalid format. Prefer using str::parse()
+/// which checks for this.
 impl From<String> for PixelFormat {
     fn from(s: String) -> Self {
         ffi::pixel_format_from_string(s)
     }
 }
 
-impl Debug for PixelFormat {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.to_string())
+impl FromStr for PixelFormat {
+    type Err = ParsePixelFormatError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let format = ffi::pixel_format_from_string(s.to_string());
+        if !format.is_valid() {
+            return Err(ParsePixelFormatError {
+                name: s.to_string(),
+            });
+        }
+
+        Ok(format)
     }
 }
 
-impl PartialEq for PixelFormat {
-    fn eq(&self, other: &Self) -> bool {
-        self.fourcc_ == other.fourcc_ && self.modifier_ == other.modifier_
+/// Error returned when parsing the name of a pixel format which isn't known to
+/// libcamera.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsePixelFormatError {
+    name: String,
+}
+
+impl std::fmt::Display for ParsePixelFormatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unrecognized pixel format: {}", self.name)
     }
 }
 
-impl Eq for PixelFormat {}
+impl std::error::Error for ParsePixelFormatError {}
+
+impl Debug for PixelFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_string())
+    }
+}
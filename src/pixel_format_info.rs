@@ -0,0 +1,444 @@
+use crate::formats;
+use crate::pixel_format::PixelFormat;
+use crate::stream_configuration::Size;
+
+/// Describes how color is represented in a format's samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColourEncoding {
+    Rgb,
+    Yuv,
+    /// Unprocessed sensor data (usually in a Bayer pattern).
+    Raw,
+}
+
+/// Order of the color filters in the top-left 2x2 block of a Bayer pattern
+/// (read left to right, top to bottom).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BayerOrder {
+    Rggb,
+    Grbg,
+    Gbrg,
+    Bggr,
+}
+
+/// How samples which aren't a multiple of 8 bits are stored in memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Packing {
+    /// Each sample is stored in the least significant bits of a whole number
+    /// of bytes.
+    None,
+    /// MIPI CSI-2 packing: the 8 most significant bits of each sample are
+    /// stored first, followed by a byte holding the remaining low bits of the
+    /// preceding samples.
+    Csi2,
+    /// Intel IPU3 packing: 25 10-bit samples stored in 32 bytes.
+    Ipu3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlaneInfo {
+    /// Number of bytes used to store one group of pixels in a single line of
+    /// this plane.
+    pub bytes_per_group: u32,
+
+    /// Number of image lines which map to a single line of this plane.
+    pub vertical_sub_sampling: u32,
+}
+
+/// Information about the memory layout of a PixelFormat.
+///
+/// This mirrors libcamera's internal PixelFormatInfo which isn't part of the
+/// public C++ API.
+#[derive(Debug, Clone, Copy)]
+pub struct PixelFormatInfo {
+    pub format: PixelFormat,
+
+    /// Average number of bits used to store each pixel. For raw formats, this
+    /// is the bit depth of each sample (regardless of padding). Zero for
+    /// compressed formats.
+    pub bits_per_pixel: u32,
+
+    pub colour_encoding: ColourEncoding,
+
+    pub packing: Packing,
+
+    /// Only set for raw Bayer formats.
+    pub bayer_order: Option<BayerOrder>,
+
+    /// Smallest number of horizontally adjacent pixels which are stored
+    /// together and which each line's width should be a multiple of.
+    pub pixels_per_group: u32,
+
+    /// Chroma subsampling factors. Always 1 for non-YUV formats.
+    pub horizontal_sub_sampling: u32,
+    pub vertical_sub_sampling: u32,
+
+    /// Unused trailing planes have all fields set to 0.
+    planes: [PlaneInfo; 3],
+}
+
+impl PixelFormatInfo {
+    pub fn lookup(format: PixelFormat) -> Option<&'static PixelFormatInfo> {
+        FORMAT_INFO.iter().find(|info| info.format == format)
+    }
+
+    /// Looks up a format by its libcamera name (e.g. "NV12" or
+    /// "SRGGB10_CSI2P").
+    pub fn from_name(name: &str) -> Option<&'static PixelFormatInfo> {
+        Self::lookup(name.parse().ok()?)
+    }
+
+    /// Gets the libcamera name of the format.
+    pub fn name(&self) -> String {
+        self.format.to_string()
+    }
+
+    pub fn planes(&self) -> &[PlaneInfo] {
+        let n = self
+            .planes
+            .iter()
+            .take_while(|p| p.bytes_per_group != 0)
+            .count();
+
+        &self.planes[0..n]
+    }
+
+    pub fn num_planes(&self) -> usize {
+        self.planes().len()
+    }
+
+    /// Computes the minimum number of bytes needed to store one line of the
+    /// given plane for an image of the given width, rounded up to a multiple
+    /// of 'align' bytes.
+    pub fn stride(&self, width: u32, plane: usize, align: u32) -> u32 {
+        let plane = match self.planes().get(plane) {
+            Some(p) => p,
+            None => return 0,
+        };
+
+        let align = align.max(1);
+
+        let stride =
+            (width + self.pixels_per_group - 1) / self.pixels_per_group * plane.bytes_per_group;
+
+        (stride + align - 1) / align * align
+    }
+
+    /// Derives the stride of the given plane from the stride of the first
+    /// plane (which is what libcamera reports in StreamConfiguration::stride).
+    pub fn plane_stride(&self, stride: u32, plane: usize) -> u32 {
+        let planes = self.planes();
+        match planes.get(plane) {
+            Some(p) => stride * p.bytes_per_group / planes[0].bytes_per_group,
+            None => 0,
+        }
+    }
+
+    /// Computes the number of bytes needed to store the given plane of an
+    /// image with the given height where each line of the plane is 'stride'
+    /// bytes long.
+    pub fn plane_size_with_stride(&self, height: u32, plane: usize, stride: u32) -> u32 {
+        let plane = match self.planes().get(plane) {
+            Some(p) => p,
+            None => return 0,
+        };
+
+        stride * ((height + plane.vertical_sub_sampling - 1) / plane.vertical_sub_sampling)
+    }
+
+    /// Computes the number of bytes needed to store the given plane of an
+    /// image with lines aligned to 'align' bytes.
+    pub fn plane_size(&self, size: Size, plane: usize, align: u32) -> u32 {
+        let stride = self.stride(size.width, plane, align);
+        self.plane_size_with_stride(size.height, plane, stride)
+    }
+
+    /// Computes the number of bytes needed to store all planes of an image with
+    /// lines aligned to 'align' bytes.
+    pub fn frame_size(&self, size: Size, align: u32) -> u32 {
+        (0..self.num_planes())
+            .map(|i| self.plane_size(size, i, align))
+            .sum()
+    }
+
+    /// Computes the number of bytes needed to store all planes of an image
+    /// given the stride of its first plane.
+    pub fn frame_size_with_stride(&self, size: Size, stride: u32) -> u32 {
+        (0..self.num_planes())
+            .map(|i| self.plane_size_with_stride(size.height, i, self.plane_stride(stride, i)))
+            .sum()
+    }
+}
+
+const NO_PLANE: PlaneInfo = plane(0, 0);
+
+const fn plane(bytes_per_group: u32, vertical_sub_sampling: u32) -> PlaneInfo {
+    PlaneInfo {
+        bytes_per_group,
+        vertical_sub_sampling,
+    }
+}
+
+const fn rgb(format: PixelFormat, bytes_per_pixel: u32) -> PixelFormatInfo {
+    PixelFormatInfo {
+        format,
+        bits_per_pixel: bytes_per_pixel * 8,
+        colour_encoding: ColourEncoding::Rgb,
+        packing: Packing::None,
+        bayer_order: None,
+        pixels_per_group: 1,
+        horizontal_sub_sampling: 1,
+        vertical_sub_sampling: 1,
+        planes: [plane(bytes_per_pixel, 1), NO_PLANE, NO_PLANE],
+    }
+}
+
+const fn yuv(
+    format: PixelFormat,
+    bits_per_pixel: u32,
+    pixels_per_group: u32,
+    sub_sampling: (u32, u32),
+    planes: [PlaneInfo; 3],
+) -> PixelFormatInfo {
+    PixelFormatInfo {
+        format,
+        bits_per_pixel,
+        colour_encoding: ColourEncoding::Yuv,
+        packing: Packing::None,
+        bayer_order: None,
+        pixels_per_group,
+        horizontal_sub_sampling: sub_sampling.0,
+        vertical_sub_sampling: sub_sampling.1,
+        planes,
+    }
+}
+
+/// Single channel greyscale formats. Like libcamera, these are considered to
+/// be YUV formats with no chroma planes.
+const fn grey(
+    format: PixelFormat,
+    bits_per_pixel: u32,
+    packing: Packing,
+    pixels_per_group: u32,
+    bytes_per_group: u32,
+) -> PixelFormatInfo {
+    PixelFormatInfo {
+        format,
+        bits_per_pixel,
+        colour_encoding: ColourEncoding::Yuv,
+        packing,
+        bayer_order: None,
+        pixels_per_group,
+        horizontal_sub_sampling: 1,
+        vertical_sub_sampling: 1,
+        planes: [plane(bytes_per_group, 1), NO_PLANE, NO_PLANE],
+    }
+}
+
+const fn bayer(
+    format: PixelFormat,
+    order: BayerOrder,
+    bits_per_pixel: u32,
+    packing: Packing,
+    pixels_per_group: u32,
+    bytes_per_group: u32,
+) -> PixelFormatInfo {
+    PixelFormatInfo {
+        format,
+        bits_per_pixel,
+        colour_encoding: ColourEncoding::Raw,
+        packing,
+        bayer_order: Some(order),
+        pixels_per_group,
+        horizontal_sub_sampling: 1,
+        vertical_sub_sampling: 1,
+        planes: [plane(bytes_per_group, 1), NO_PLANE, NO_PLANE],
+    }
+}
+
+// Keep in sync with libcamera's src/libcamera/formats.cpp.
+#[rustfmt::skip]
+static FORMAT_INFO: &[PixelFormatInfo] = &[
+    // RGB formats.
+    rgb(formats::RGB565, 2),
+    rgb(formats::RGB565_BE, 2),
+    rgb(formats::BGR888, 3),
+    rgb(formats::RGB888, 3),
+    rgb(formats::XRGB8888, 4),
+    rgb(formats::XBGR8888, 4),
+    rgb(formats::RGBX8888, 4),
+    rgb(formats::BGRX8888, 4),
+    rgb(formats::ARGB8888, 4),
+    rgb(formats::ABGR8888, 4),
+    rgb(formats::RGBA8888, 4),
+    rgb(formats::BGRA8888, 4),
+    // YUV packed formats.
+    yuv(formats::YUYV, 16, 2, (2, 1), [plane(4, 1), NO_PLANE, NO_PLANE]),
+    yuv(formats::YVYU, 16, 2, (2, 1), [plane(4, 1), NO_PLANE, NO_PLANE]),
+    yuv(formats::UYVY, 16, 2, (2, 1), [plane(4, 1), NO_PLANE, NO_PLANE]),
+    yuv(formats::VYUY, 16, 2, (2, 1), [plane(4, 1), NO_PLANE, NO_PLANE]),
+    yuv(formats::AVUY8888, 32, 1, (1, 1), [plane(4, 1), NO_PLANE, NO_PLANE]),
+    yuv(formats::XVUY8888, 32, 1, (1, 1), [plane(4, 1), NO_PLANE, NO_PLANE]),
+    // YUV semi-planar formats.
+    yuv(formats::NV12, 12, 2, (2, 2), [plane(2, 1), plane(2, 2), NO_PLANE]),
+    yuv(formats::NV21, 12, 2, (2, 2), [plane(2, 1), plane(2, 2), NO_PLANE]),
+    yuv(formats::NV16, 16, 2, (2, 1), [plane(2, 1), plane(2, 1), NO_PLANE]),
+    yuv(formats::NV61, 16, 2, (2, 1), [plane(2, 1), plane(2, 1), NO_PLANE]),
+    yuv(formats::NV24, 24, 1, (1, 1), [plane(1, 1), plane(2, 1), NO_PLANE]),
+    yuv(formats::NV42, 24, 1, (1, 1), [plane(1, 1), plane(2, 1), NO_PLANE]),
+    // YUV planar formats.
+    yuv(formats::YUV420, 12, 2, (2, 2), [plane(2, 1), plane(1, 2), plane(1, 2)]),
+    yuv(formats::YVU420, 12, 2, (2, 2), [plane(2, 1), plane(1, 2), plane(1, 2)]),
+    yuv(formats::YUV422, 16, 2, (2, 1), [plane(2, 1), plane(1, 1), plane(1, 1)]),
+    yuv(formats::YVU422, 16, 2, (2, 1), [plane(2, 1), plane(1, 1), plane(1, 1)]),
+    yuv(formats::YUV444, 24, 1, (1, 1), [plane(1, 1), plane(1, 1), plane(1, 1)]),
+    yuv(formats::YVU444, 24, 1, (1, 1), [plane(1, 1), plane(1, 1), plane(1, 1)]),
+    // Greyscale formats.
+    grey(formats::R8, 8, Packing::None, 1, 1),
+    grey(formats::R10, 10, Packing::None, 1, 2),
+    grey(formats::R12, 12, Packing::None, 1, 2),
+    grey(formats::R16, 16, Packing::None, 1, 2),
+    grey(formats::R10_CSI2P, 10, Packing::Csi2, 4, 5),
+    grey(formats::R12_CSI2P, 12, Packing::Csi2, 2, 3),
+    // Bayer formats.
+    bayer(formats::SRGGB8, BayerOrder::Rggb, 8, Packing::None, 2, 2),
+    bayer(formats::SRGGB10, BayerOrder::Rggb, 10, Packing::None, 2, 4),
+    bayer(formats::SRGGB10_CSI2P, BayerOrder::Rggb, 10, Packing::Csi2, 4, 5),
+    bayer(formats::SRGGB10_IPU3, BayerOrder::Rggb, 10, Packing::Ipu3, 25, 32),
+    bayer(formats::SRGGB12, BayerOrder::Rggb, 12, Packing::None, 2, 4),
+    bayer(formats::SRGGB12_CSI2P, BayerOrder::Rggb, 12, Packing::Csi2, 2, 3),
+    bayer(formats::SRGGB14, BayerOrder::Rggb, 14, Packing::None, 2, 4),
+    bayer(formats::SRGGB14_CSI2P, BayerOrder::Rggb, 14, Packing::Csi2, 4, 7),
+    bayer(formats::SRGGB16, BayerOrder::Rggb, 16, Packing::None, 2, 4),
+    bayer(formats::SGRBG8, BayerOrder::Grbg, 8, Packing::None, 2, 2),
+    bayer(formats::SGRBG10, BayerOrder::Grbg, 10, Packing::None, 2, 4),
+    bayer(formats::SGRBG10_CSI2P, BayerOrder::Grbg, 10, Packing::Csi2, 4, 5),
+    bayer(formats::SGRBG10_IPU3, BayerOrder::Grbg, 10, Packing::Ipu3, 25, 32),
+    bayer(formats::SGRBG12, BayerOrder::Grbg, 12, Packing::None, 2, 4),
+    bayer(formats::SGRBG12_CSI2P, BayerOrder::Grbg, 12, Packing::Csi2, 2, 3),
+    bayer(formats::SGRBG14, BayerOrder::Grbg, 14, Packing::None, 2, 4),
+    bayer(formats::SGRBG14_CSI2P, BayerOrder::Grbg, 14, Packing::Csi2, 4, 7),
+    bayer(formats::SGRBG16, BayerOrder::Grbg, 16, Packing::None, 2, 4),
+    bayer(formats::SGBRG8, BayerOrder::Gbrg, 8, Packing::None, 2, 2),
+    bayer(formats::SGBRG10, BayerOrder::Gbrg, 10, Packing::None, 2, 4),
+    bayer(formats::SGBRG10_CSI2P, BayerOrder::Gbrg, 10, Packing::Csi2, 4, 5),
+    bayer(formats::SGBRG10_IPU3, BayerOrder::Gbrg, 10, Packing::Ipu3, 25, 32),
+    bayer(formats::SGBRG12, BayerOrder::Gbrg, 12, Packing::None, 2, 4),
+    bayer(formats::SGBRG12_CSI2P, BayerOrder::Gbrg, 12, Packing::Csi2, 2, 3),
+    bayer(formats::SGBRG14, BayerOrder::Gbrg, 14, Packing::None, 2, 4),
+    bayer(formats::SGBRG14_CSI2P, BayerOrder::Gbrg, 14, Packing::Csi2, 4, 7),
+    bayer(formats::SGBRG16, BayerOrder::Gbrg, 16, Packing::None, 2, 4),
+    bayer(formats::SBGGR8, BayerOrder::Bggr, 8, Packing::None, 2, 2),
+    bayer(formats::SBGGR10, BayerOrder::Bggr, 10, Packing::None, 2, 4),
+    bayer(formats::SBGGR10_CSI2P, BayerOrder::Bggr, 10, Packing::Csi2, 4, 5),
+    bayer(formats::SBGGR10_IPU3, BayerOrder::Bggr, 10, Packing::Ipu3, 25, 32),
+    bayer(formats::SBGGR12, BayerOrder::Bggr, 12, Packing::None, 2, 4),
+    bayer(formats::SBGGR12_CSI2P, BayerOrder::Bggr, 12, Packing::Csi2, 2, 3),
+    bayer(formats::SBGGR14, BayerOrder::Bggr, 14, Packing::None, 2, 4),
+    bayer(formats::SBGGR14_CSI2P, BayerOrder::Bggr, 14, Packing::Csi2, 4, 7),
+    bayer(formats::SBGGR16, BayerOrder::Bggr, 16, Packing::None, 2, 4),
+    // Compressed formats.
+    yuv(formats::MJPEG, 0, 1, (1, 1), [plane(1, 1), NO_PLANE, NO_PLANE]),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(format: PixelFormat) -> &'static PixelFormatInfo {
+        PixelFormatInfo::lookup(format).unwrap()
+    }
+
+    fn size(width: u32, height: u32) -> Size {
+        Size { width, height }
+    }
+
+    // Expected values below are the ones computed by libcamera's
+    // PixelFormatInfo::stride(), planeSize() and frameSize().
+
+    #[test]
+    fn nv12() {
+        let nv12 = info(formats::NV12);
+        assert_eq!(nv12.num_planes(), 2);
+
+        assert_eq!(nv12.stride(1920, 0, 1), 1920);
+        assert_eq!(nv12.stride(1920, 1, 1), 1920);
+        assert_eq!(nv12.plane_size(size(1920, 1080), 0, 1), 2_073_600);
+        assert_eq!(nv12.plane_size(size(1920, 1080), 1, 1), 1_036_800);
+        assert_eq!(nv12.frame_size(size(1920, 1080), 1), 3_110_400);
+
+        // Odd sizes round up to whole chroma samples.
+        assert_eq!(nv12.stride(641, 0, 1), 642);
+        assert_eq!(nv12.plane_size(size(641, 481), 1, 1), 642 * 241);
+
+        assert_eq!(nv12.plane_stride(2048, 1), 2048);
+        assert_eq!(nv12.plane_size_with_stride(1080, 1, 2048), 2048 * 540);
+        assert_eq!(
+            nv12.frame_size_with_stride(size(1920, 1080), 2048),
+            2048 * 1620
+        );
+    }
+
+    #[test]
+    fn yuv420() {
+        let yuv420 = info(formats::YUV420);
+        assert_eq!(yuv420.num_planes(), 3);
+
+        assert_eq!(yuv420.stride(1920, 0, 1), 1920);
+        assert_eq!(yuv420.stride(1920, 1, 1), 960);
+        assert_eq!(yuv420.stride(1920, 2, 1), 960);
+        assert_eq!(yuv420.stride(1000, 1, 64), 512);
+        assert_eq!(yuv420.plane_size(size(1920, 1080), 0, 1), 2_073_600);
+        assert_eq!(yuv420.plane_size(size(1920, 1080), 2, 1), 518_400);
+        assert_eq!(yuv420.frame_size(size(1920, 1080), 1), 3_110_400);
+
+        assert_eq!(yuv420.plane_stride(2048, 1), 1024);
+        assert_eq!(yuv420.plane_size_with_stride(1081, 2, 1024), 1024 * 541);
+        assert_eq!(
+            yuv420.frame_size_with_stride(size(1920, 1080), 2048),
+            2048 * 1080 + 2 * 1024 * 540
+        );
+    }
+
+    #[test]
+    fn rgb888() {
+        let rgb888 = info(formats::RGB888);
+        assert_eq!(rgb888.num_planes(), 1);
+
+        assert_eq!(rgb888.stride(640, 0, 1), 1920);
+        assert_eq!(rgb888.stride(100, 0, 1), 300);
+        assert_eq!(rgb888.stride(100, 0, 64), 320);
+        assert_eq!(rgb888.stride(100, 1, 1), 0);
+        assert_eq!(rgb888.plane_size(size(100, 10), 0, 64), 3200);
+        assert_eq!(rgb888.plane_size(size(100, 10), 1, 64), 0);
+        assert_eq!(rgb888.frame_size(size(640, 480), 1), 921_600);
+    }
+
+    #[test]
+    fn raw10_csi2() {
+        let raw10 = info(formats::SRGGB10_CSI2P);
+        assert_eq!(raw10.bits_per_pixel, 10);
+        assert_eq!(raw10.packing, Packing::Csi2);
+        assert_eq!(raw10.bayer_order, Some(BayerOrder::Rggb));
+
+        // 4 pixels are packed into 5 bytes.
+        assert_eq!(raw10.stride(640, 0, 1), 800);
+        assert_eq!(raw10.stride(642, 0, 1), 805);
+        assert_eq!(raw10.stride(642, 0, 32), 832);
+        assert_eq!(raw10.plane_size(size(640, 480), 0, 1), 384_000);
+        assert_eq!(raw10.frame_size_with_stride(size(640, 480), 832), 832 * 480);
+    }
+
+    #[test]
+    fn formats_are_unique() {
+        for (i, info) in FORMAT_INFO.iter().enumerate() {
+            assert!(
+                FORMAT_INFO[..i]
+                    .iter()
+                    .all(|other| other.format != info.format),
+                "{:?}",
+                info.format.fourcc()
+            );
+        }
+    }
+}
@@ -0,0 +1,11 @@
+//! Properties describing the static capabilities of a camera.
+//!
+//! These are read from Camera::properties().
+
+use std::pin::Pin;
+
+use crate::controls::{control, control_enum};
+use crate::ffi;
+use crate::{AssignToRawControlValue, FromRawControlValue, Rectangle, Size};
+
+include!(concat!(env!("OUT_DIR"), "/properties.rs"));
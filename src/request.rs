@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use std::future::Future;
 use std::ops::{Deref, DerefMut};
+use std::os::fd::{AsFd, BorrowedFd, IntoRawFd, OwnedFd};
 use std::pin::Pin;
 use std::sync::Arc;
 use std::sync::Mutex;
@@ -10,9 +11,11 @@ use cxx::UniquePtr;
 
 use crate::camera::{Camera, RequestQueueEntry};
 use crate::control_list::ControlList;
+use crate::control_value::ControlValue;
 use crate::errors::*;
 use crate::ffi;
 use crate::frame_buffer::FrameBuffer;
+use crate::frame_metadata::FrameInfo;
 use crate::stream::Stream;
 
 pub use ffi::{RequestReuseFlag, RequestStatus};
@@ -24,7 +27,6 @@ pub use ffi::{RequestReuseFlag, RequestStatus};
 ///
 /// Will also get canclled by C++ if the camera is stopped.
 pub struct Request {
-    #[allow(unused)]
     camera: Arc<Camera>,
 
     pub(crate) raw: UniquePtr<ffi::Request>,
@@ -32,6 +34,9 @@ pub struct Request {
     /// All the buffers associated with this request.
     /// Key is the stream id.
     buffers: HashMap<u64, FrameBuffer>,
+
+    /// Snapshot of the controls taken when the request was last enqueued.
+    requested_controls: Vec<(u32, ControlValue)>,
 }
 
 impl Request {
@@ -40,20 +45,36 @@ impl Request {
             camera,
             raw,
             buffers: HashMap::new(),
+            requested_controls: vec![],
         }
     }
 
     pub fn add_buffer(&mut self, buffer: FrameBuffer) -> Result<()> {
+        self.add_buffer_impl(buffer, UniquePtr::null())
+    }
+
+    /// Same as add_buffer() but libcamera will wait for the given acquire fence
+    /// (a sync_file fd) to be signalled before writing to the buffer.
+    ///
+    /// If the fence doesn't signal in time, the request will be cancelled and
+    /// the fence is handed back through CompletedRequest::failed_fence().
+    pub fn add_buffer_with_fence(&mut self, buffer: FrameBuffer, fence: OwnedFd) -> Result<()> {
+        let fence = ffi::new_fence(fence.into_raw_fd());
+        assert!(!fence.is_null());
+
+        self.add_buffer_impl(buffer, fence)
+    }
+
+    fn add_buffer_impl(&mut self, buffer: FrameBuffer, fence: UniquePtr<ffi::Fence>) -> Result<()> {
         assert!(self.camera.contains_stream(buffer.stream));
 
         // libcamera's addBuffer return EEXIST if the request already has a buffer
         // associated with the stream.
         ok_if_zero(unsafe {
-            self.raw.as_mut().unwrap().addBuffer(
-                buffer.stream.as_mut_ptr(),
-                buffer.raw,
-                UniquePtr::null(),
-            )
+            self.raw
+                .as_mut()
+                .unwrap()
+                .addBuffer(buffer.stream.as_mut_ptr(), buffer.raw, fence)
         })?;
 
         self.buffers.insert(buffer.stream.id(), buffer);
@@ -61,6 +82,11 @@ impl Request {
         Ok(())
     }
 
+    /// Checks whether the request was created by the given camera.
+    pub(crate) fn belongs_to(&self, camera: &Camera) -> bool {
+        std::ptr::eq(Arc::as_ptr(&self.camera), camera)
+    }
+
     pub fn status(&self) -> RequestStatus {
         self.raw.status()
     }
@@ -73,6 +99,12 @@ impl Request {
         self.raw.sequence()
     }
 
+    /// Metadata reported by the pipeline handler. This is only populated once
+    /// the request has completed.
+    pub fn metadata<'a>(&'a self) -> &'a ControlList {
+        ffi::request_metadata(&self.raw).into()
+    }
+
     // TODO: Change to read only and only allow on a completed request.
     pub fn metadata_mut<'a>(&'a mut self) -> &'a mut ControlList {
         unsafe {
@@ -85,6 +117,12 @@ impl Request {
         }
     }
 
+    /// Gets the controls (keyed by control id) which were set on this request
+    /// at the time it was enqueued.
+    pub fn requested_controls(&self) -> &[(u32, ControlValue)] {
+        &self.requested_controls
+    }
+
     pub fn controls_mut<'a>(&'a mut self) -> &'a mut ControlList {
         unsafe {
             self.raw
@@ -120,6 +158,10 @@ impl NewRequest {
     /// libcamera internal threads.
     pub fn enqueue(mut self) -> Result<PendingRequest> {
         let camera = self.request.camera.clone();
+        camera.apply_pending_controls(&mut self.request);
+
+        self.request.requested_controls = self.request.controls_mut().iter().collect();
+
         let entry = camera.queue_request(&mut self.request)?;
 
         Ok(PendingRequest {
@@ -158,9 +200,7 @@ impl PendingRequest {
     /// otherwise, returns the same PendingRequest.
     pub fn try_complete(mut self) -> std::result::Result<CompletedRequest, PendingRequest> {
         if self.entry.lock().unwrap().done {
-            Ok(CompletedRequest {
-                request: self.request.take().unwrap(),
-            })
+            Ok(CompletedRequest::new(self.request.take().unwrap()))
         } else {
             Err(self)
         }
@@ -175,9 +215,7 @@ impl Future for PendingRequest {
         if state.done {
             drop(state);
 
-            return Poll::Ready(CompletedRequest {
-                request: self.request.take().unwrap(),
-            });
+            return Poll::Ready(CompletedRequest::new(self.request.take().unwrap()));
         }
 
         state.waker = Some(cx.waker().clone());
@@ -187,6 +225,9 @@ impl Future for PendingRequest {
 
 pub struct CompletedRequest {
     request: Request,
+
+    /// Acquire fences which were never signalled. Key is the stream id.
+    failed_fences: HashMap<u64, OwnedFd>,
 }
 
 impl Deref for CompletedRequest {
@@ -198,6 +239,22 @@ impl Deref for CompletedRequest {
 }
 
 impl CompletedRequest {
+    fn new(mut request: Request) -> Self {
+        // libcamera leaves fences which failed to signal attached to their
+        // buffers, so reclaim them before the buffers can be re-used.
+        let mut failed_fences = HashMap::new();
+        for (stream_id, buffer) in request.buffers.iter_mut() {
+            if let Some(fence) = buffer.release_fence() {
+                failed_fences.insert(*stream_id, fence);
+            }
+        }
+
+        Self {
+            request,
+            failed_fences,
+        }
+    }
+
     /// Re-uses the request object as a new request.
     ///
     /// Any buffers added at the request already will be retained for the new
@@ -214,6 +271,16 @@ impl CompletedRequest {
         }
     }
 
+    /// Gets the metadata reported by the camera for this request.
+    pub fn metadata(&self) -> &ControlList {
+        self.request.metadata()
+    }
+
+    /// Extracts common per-frame values from the metadata.
+    pub fn frame_info(&self) -> FrameInfo {
+        FrameInfo::from_metadata(self.metadata())
+    }
+
     pub fn buffer(&self, stream: &Stream) -> Option<&FrameBuffer> {
         self.request.buffers.get(&stream.id())
     }
@@ -221,4 +288,17 @@ impl CompletedRequest {
     pub fn get_buffer(&self, stream_id: u64) -> Option<&FrameBuffer> {
         self.request.buffers.get(&stream_id)
     }
+
+    /// Gets the acquire fence passed to Request::add_buffer_with_fence() for
+    /// the given stream if it failed to signal.
+    pub fn failed_fence(&self, stream: &Stream) -> Option<BorrowedFd<'_>> {
+        self.failed_fences.get(&stream.id()).map(|fd| fd.as_fd())
+    }
+
+    /// Iterates over all fences which failed to signal keyed by stream id.
+    pub fn failed_fences(&self) -> impl Iterator<Item = (u64, BorrowedFd<'_>)> {
+        self.failed_fences
+            .iter()
+            .map(|(stream_id, fd)| (*stream_id, fd.as_fd()))
+    }
 }
@@ -0,0 +1,101 @@
+use crate::geometry::Rectangle;
+use crate::pixel_format::PixelFormat;
+use crate::pixel_format_info::ColourEncoding;
+use crate::stream_configuration::Size;
+use crate::stream_formats::StreamFormats;
+
+pub use crate::ffi::SensorConfiguration;
+
+impl SensorConfiguration {
+    /// Creates a configuration requesting the sensor mode with the given bit
+    /// depth and output size, with no binning or skipping constraints.
+    ///
+    /// The analog crop is chosen by the pipeline handler.
+    pub fn new(bit_depth: u32, output_size: Size) -> Self {
+        Self {
+            bit_depth,
+            analog_crop: Rectangle {
+                x: 0,
+                y: 0,
+                width: 0,
+                height: 0,
+            },
+            bin_x: 1,
+            bin_y: 1,
+            x_odd_inc: 1,
+            x_even_inc: 1,
+            y_odd_inc: 1,
+            y_even_inc: 1,
+            output_size,
+        }
+    }
+
+    /// Mirrors libcamera::SensorConfiguration::isValid().
+    pub fn is_valid(&self) -> bool {
+        self.bit_depth != 0
+            && self.bin_x != 0
+            && self.bin_y != 0
+            && self.x_odd_inc != 0
+            && self.x_even_inc != 0
+            && self.y_odd_inc != 0
+            && self.y_even_inc != 0
+            && self.output_size.width != 0
+            && self.output_size.height != 0
+    }
+}
+
+/// A readout mode supported by a camera's sensor.
+#[derive(Debug, Clone)]
+pub struct SensorMode {
+    /// Size of the image output by the sensor.
+    pub size: Size,
+
+    /// Bits per sample produced by the sensor.
+    pub bit_depth: u32,
+
+    /// Raw pixel formats which can be used to capture this mode in a Raw
+    /// stream (e.g. packed and unpacked variants).
+    pub pixel_formats: Vec<PixelFormat>,
+}
+
+impl SensorMode {
+    /// Gets a configuration which can be passed to
+    /// CameraConfiguration::set_sensor_config() to select this mode.
+    pub fn to_sensor_config(&self) -> SensorConfiguration {
+        SensorConfiguration::new(self.bit_depth, self.size)
+    }
+
+    /// Lists the sensor modes advertised in the formats of a Raw stream.
+    ///
+    /// Modes are sorted by bit depth and then by size.
+    pub fn from_stream_formats(formats: &StreamFormats) -> Vec<SensorMode> {
+        let mut modes: Vec<SensorMode> = vec![];
+
+        for pixel_format in formats.pixel_formats() {
+            let bit_depth = match pixel_format.info() {
+                Some(info) if info.colour_encoding == ColourEncoding::Raw => info.bits_per_pixel,
+                _ => continue,
+            };
+
+            for size in formats.sizes(pixel_format) {
+                let existing = modes.iter_mut().find(|mode| {
+                    mode.bit_depth == bit_depth
+                        && mode.size.width == size.width
+                        && mode.size.height == size.height
+                });
+
+                match existing {
+                    Some(mode) => mode.pixel_formats.push(pixel_format),
+                    None => modes.push(SensorMode {
+                        size,
+                        bit_depth,
+                        pixel_formats: vec![pixel_format],
+                    }),
+                }
+            }
+        }
+
+        modes.sort_by_key(|mode| (mode.bit_depth, mode.size.width, mode.size.height));
+        modes
+    }
+}
@@ -1,8 +1,7 @@
 use crate::ffi;
+use crate::geometry::{Size, SizeRange};
 use crate::pixel_format::PixelFormat;
 
-pub use crate::ffi::{Size, SizeRange};
-
 #[repr(transparent)]
 pub struct StreamFormats {
     raw: ffi::StreamFormats,
@@ -26,4 +25,222 @@ impl StreamFormats {
     pub fn range(&self, pixel_format: PixelFormat) -> SizeRange {
         self.raw.range(&pixel_format.into())
     }
+
+    /// Gets the sizes supported by a pixel format.
+    ///
+    /// libcamera doesn't say whether a format was described by a list of
+    /// sizes or by a range. A format is treated as a list if the bounds of
+    /// range() are both in sizes(), which always holds for lists. Ranges
+    /// which happen to be bounded by two standard sizes are therefore
+    /// reported as the list of standard sizes within them.
+    pub fn format_sizes(&self, pixel_format: PixelFormat) -> FormatSizes {
+        let sizes = self.sizes(pixel_format);
+        let range = self.range(pixel_format);
+
+        if range.min == range.max || (sizes.contains(&range.min) && sizes.contains(&range.max)) {
+            FormatSizes::Discrete(sizes)
+        } else {
+            FormatSizes::Range(range)
+        }
+    }
+
+    /// Iterates over every supported pixel format along with the sizes it
+    /// supports.
+    pub fn iter(&self) -> impl Iterator<Item = (PixelFormat, FormatSizes)> + '_ {
+        self.pixel_formats()
+            .into_iter()
+            .map(move |pixel_format| (pixel_format, self.format_sizes(pixel_format)))
+    }
+}
+
+/// The sizes supported by a single pixel format in a StreamFormats.
+#[derive(Debug, Clone)]
+pub enum FormatSizes {
+    /// Only the listed sizes are supported.
+    Discrete(Vec<Size>),
+
+    /// Any size between min and max (inclusive) which is a multiple of the
+    /// step sizes away from min is supported.
+    Range(SizeRange),
+}
+
+impl FormatSizes {
+    /// Checks whether a size is supported.
+    pub fn contains(&self, size: Size) -> bool {
+        match self {
+            FormatSizes::Discrete(sizes) => sizes.contains(&size),
+            FormatSizes::Range(range) => {
+                in_steps(size.width, range.min.width, range.max.width, range.hStep)
+                    && in_steps(size.height, range.min.height, range.max.height, range.vStep)
+            }
+        }
+    }
+
+    /// Gets the supported size which is closest to the given size.
+    ///
+    /// Returns None only if there are no supported sizes.
+    pub fn closest(&self, size: Size) -> Option<Size> {
+        match self {
+            FormatSizes::Discrete(sizes) => sizes.iter().copied().min_by_key(|s| {
+                let dw = s.width.abs_diff(size.width) as u64;
+                let dh = s.height.abs_diff(size.height) as u64;
+                // Prefer the larger size when two are equally far away.
+                (dw * dw + dh * dh, u64::MAX - area(*s))
+            }),
+            FormatSizes::Range(range) => Some(Size {
+                width: nearest_step(size.width, range.min.width, range.max.width, range.hStep),
+                height: nearest_step(size.height, range.min.height, range.max.height, range.vStep),
+            }),
+        }
+    }
+
+    /// Gets the supported size no larger than 'bound' in either dimension with
+    /// the largest area for which the filter returns true.
+    ///
+    /// e.g. the largest 16:9 size which fits in 1920x1080:
+    /// `sizes.largest(Size { width: 1920, height: 1080 }, |s| s.width * 9 == s.height * 16)`
+    ///
+    /// For ranges, the largest size within the bound is tried first and then
+    /// the remaining sizes are walked downwards per dimension, only calling the
+    /// filter for sizes larger than the best match found so far. Limits on
+    /// the size should therefore be passed as the bound rather than checked by
+    /// the filter.
+    pub fn largest<F: FnMut(Size) -> bool>(&self, bound: Size, mut filter: F) -> Option<Size> {
+        match self {
+            FormatSizes::Discrete(sizes) => sizes
+                .iter()
+                .copied()
+                .filter(|s| s.width <= bound.width && s.height <= bound.height)
+                .filter(|s| filter(*s))
+                .max_by_key(|s| area(*s)),
+            FormatSizes::Range(range) => {
+                let max_width = range.max.width.min(bound.width);
+                let max_height = range.max.height.min(bound.height);
+                if max_width < range.min.width || max_height < range.min.height {
+                    return None;
+                }
+
+                let widths = || steps_descending(range.min.width, max_width, range.hStep);
+                let heights = || steps_descending(range.min.height, max_height, range.vStep);
+
+                let corner = Size {
+                    width: widths().next()?,
+                    height: heights().next()?,
+                };
+                if filter(corner) {
+                    return Some(corner);
+                }
+
+                let mut best: Option<Size> = None;
+                let mut best_area = 0;
+
+                for width in widths() {
+                    if width as u64 * corner.height as u64 <= best_area {
+                        break;
+                    }
+
+                    // Heights are walked downwards, so the first match is the
+                    // largest for this width. Stop once no height could beat
+                    // the current best.
+                    for height in heights() {
+                        let size = Size { width, height };
+                        if area(size) <= best_area {
+                            break;
+                        }
+
+                        if filter(size) {
+                            best = Some(size);
+                            best_area = area(size);
+                            break;
+                        }
+                    }
+                }
+
+                best
+            }
+        }
+    }
+}
+
+fn area(size: Size) -> u64 {
+    size.width as u64 * size.height as u64
+}
+
+fn in_steps(value: u32, min: u32, max: u32, step: u32) -> bool {
+    value >= min && value <= max && (value - min) % step.max(1) == 0
+}
+
+fn nearest_step(value: u32, min: u32, max: u32, step: u32) -> u32 {
+    let step = step.max(1);
+    let offset = value.clamp(min, max.max(min)) - min;
+    let rounded = min + (offset + step / 2) / step * step;
+    if rounded > max {
+        rounded - step
+    } else {
+        rounded
+    }
+}
+
+fn steps_descending(min: u32, max: u32, step: u32) -> impl Iterator<Item = u32> {
+    let step = step.max(1);
+    let count = if max >= min {
+        (max - min) / step + 1
+    } else {
+        0
+    };
+    (0..count).rev().map(move |i| min + i * step)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn size(width: u32, height: u32) -> Size {
+        Size { width, height }
+    }
+
+    fn range(step: u32) -> FormatSizes {
+        FormatSizes::Range(SizeRange {
+            min: size(64, 64),
+            max: size(4056, 3040),
+            hStep: step,
+            vStep: step,
+        })
+    }
+
+    #[test]
+    fn largest_tries_max_first() {
+        let mut calls = 0;
+        let largest = range(1).largest(size(u32::MAX, u32::MAX), |_| {
+            calls += 1;
+            true
+        });
+
+        assert_eq!(largest, Some(size(4056, 3040)));
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn largest_prunes_smaller_sizes() {
+        let mut calls = 0u64;
+        let largest = range(1).largest(size(1920, 1080), |s| {
+            calls += 1;
+            s.width * 3 == s.height * 4
+        });
+
+        assert_eq!(largest, Some(size(1440, 1080)));
+        // Only sizes within the bound and larger than 1440x1080 are tried,
+        // rather than every size in the range.
+        let candidates = (1920 - 63) * (1080 - 63);
+        assert!(calls < candidates / 3, "{} filter calls", calls);
+    }
+
+    #[test]
+    fn largest_respects_steps() {
+        let largest = range(4).largest(size(1922, 1081), |_| true);
+        assert_eq!(largest, Some(size(1920, 1080)));
+
+        let largest = range(4).largest(size(63, 1080), |_| true);
+        assert_eq!(largest, None);
+    }
 }
@@ -0,0 +1,455 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::{poll_fn, Future};
+use std::pin::Pin;
+use std::task::Poll;
+use std::time::Duration;
+
+use crate::camera::RunningCamera;
+use crate::errors::Error;
+use crate::frame_metadata::Timestamp;
+use crate::request::{CompletedRequest, NewRequest, PendingRequest, RequestStatus};
+
+/// Fraction of the measured offset which is corrected by each nudge.
+const ALIGNMENT_GAIN: f64 = 0.5;
+
+/// Largest change to a camera's frame duration made by a single nudge, as a
+/// fraction of the nominal frame duration.
+const MAX_CORRECTION: f64 = 0.1;
+
+/// Frames from every camera which were captured at (nearly) the same time.
+pub struct SyncedFrames {
+    /// One completed request per camera, in the order the cameras were given
+    /// to SyncedCapture::new().
+    pub requests: Vec<CompletedRequest>,
+
+    /// SensorTimestamp of each request.
+    pub timestamps: Vec<Timestamp>,
+}
+
+impl SyncedFrames {
+    /// Difference between the earliest and latest timestamps.
+    pub fn skew(&self) -> Duration {
+        let min = self.timestamps.iter().min().copied();
+        let max = self.timestamps.iter().max().copied();
+
+        match (min, max) {
+            (Some(min), Some(max)) => max.saturating_duration_since(min),
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+/// Result of SyncedCapture::next().
+pub enum SyncEvent {
+    Matched(SyncedFrames),
+
+    /// A frame which had no counterpart from the other cameras within the
+    /// tolerance, was cancelled or didn't report a SensorTimestamp.
+    Unmatched {
+        /// Index of the camera which captured the frame.
+        camera: usize,
+        request: CompletedRequest,
+    },
+}
+
+#[derive(Debug)]
+pub enum SyncError {
+    /// There is no camera with the given index.
+    NoSuchCamera(usize),
+
+    /// The request was created by a different camera than the one with the
+    /// given index.
+    WrongCamera(usize),
+
+    Camera(Error),
+}
+
+impl fmt::Display for SyncError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncError::NoSuchCamera(index) => write!(f, "no camera with index {}", index),
+            SyncError::WrongCamera(index) => {
+                write!(f, "request wasn't created by camera {}", index)
+            }
+            SyncError::Camera(e) => write!(f, "failed to enqueue request: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SyncError {}
+
+impl From<Error> for SyncError {
+    fn from(e: Error) -> Self {
+        SyncError::Camera(e)
+    }
+}
+
+struct SyncedCamera<'a> {
+    camera: &'a RunningCamera,
+
+    in_flight: VecDeque<PendingRequest>,
+
+    /// Completed frames waiting to be matched, in order of completion.
+    ready: VecDeque<(Timestamp, CompletedRequest)>,
+
+    /// Number of matched frames to skip before nudging this camera again, so
+    /// that the previous nudge has time to take effect.
+    cooldown: u32,
+
+    /// Whether the camera is currently running at a corrected frame duration.
+    nudged: bool,
+}
+
+/// Captures from several running cameras and pairs up frames which were
+/// captured at the same time based on their SensorTimestamp.
+///
+/// Requests for each camera are enqueued through enqueue() and completed
+/// frames are retrieved with next(). Frames from different cameras are
+/// matched if their timestamps differ by at most the tolerance. This should
+/// be less than half the frame interval so that a frame can only ever be
+/// matched with the nearest frame from each other camera.
+pub struct SyncedCapture<'a> {
+    cameras: Vec<SyncedCamera<'a>>,
+    tolerance: Duration,
+
+    /// If set, the frame duration of cameras which drift further than this
+    /// from the first camera is adjusted to bring them back in line.
+    alignment_threshold: Option<Duration>,
+}
+
+impl<'a> SyncedCapture<'a> {
+    pub fn new(cameras: Vec<&'a RunningCamera>, tolerance: Duration) -> Self {
+        Self {
+            cameras: cameras
+                .into_iter()
+                .map(|camera| SyncedCamera {
+                    camera,
+                    in_flight: VecDeque::new(),
+                    ready: VecDeque::new(),
+                    cooldown: 0,
+                    nudged: false,
+                })
+                .collect(),
+            tolerance,
+            alignment_threshold: None,
+        }
+    }
+
+    /// Enables nudging the frame duration of cameras which lag behind or run
+    /// ahead of the first camera by more than 'threshold'. Pass None to
+    /// disable it again.
+    ///
+    /// Cameras are nudged by setting FrameDurationLimits, so once aligned
+    /// their frame duration is fixed to that reported by the first camera.
+    /// This only keeps free running sensors aligned in software and is no
+    /// replacement for hardware synchronization.
+    pub fn set_alignment(&mut self, threshold: Option<Duration>) {
+        self.alignment_threshold = threshold;
+    }
+
+    /// Enqueues a request on the camera with the given index. The request
+    /// must have been created by that camera.
+    pub fn enqueue(&mut self, camera: usize, request: NewRequest) -> Result<(), SyncError> {
+        let synced = self
+            .cameras
+            .get_mut(camera)
+            .ok_or(SyncError::NoSuchCamera(camera))?;
+        if !request.belongs_to(synced.camera) {
+            return Err(SyncError::WrongCamera(camera));
+        }
+
+        synced.in_flight.push_back(request.enqueue()?);
+        Ok(())
+    }
+
+    /// Number of requests which have been enqueued and not yet returned by
+    /// next().
+    pub fn outstanding(&self) -> usize {
+        self.cameras
+            .iter()
+            .map(|c| c.in_flight.len() + c.ready.len())
+            .sum()
+    }
+
+    /// Waits for the next set of matched frames or unmatched frame.
+    ///
+    /// Returns None once every enqueued request has been returned.
+    pub async fn next(&mut self) -> Option<SyncEvent> {
+        loop {
+            if let Some(event) = self.try_match() {
+                return Some(event);
+            }
+
+            if self.cameras.iter().all(|c| c.in_flight.is_empty()) {
+                return None;
+            }
+
+            let (index, request) = poll_fn(|cx| {
+                for (index, camera) in self.cameras.iter_mut().enumerate() {
+                    if let Some(pending) = camera.in_flight.front_mut() {
+                        if let Poll::Ready(request) = Pin::new(pending).poll(cx) {
+                            camera.in_flight.pop_front();
+                            return Poll::Ready((index, request));
+                        }
+                    }
+                }
+
+                Poll::Pending
+            })
+            .await;
+
+            let timestamp = match request.status() {
+                RequestStatus::RequestComplete => request.frame_info().sensor_timestamp,
+                _ => None,
+            };
+
+            match timestamp {
+                Some(timestamp) => self.cameras[index].ready.push_back((timestamp, request)),
+                None => {
+                    return Some(SyncEvent::Unmatched {
+                        camera: index,
+                        request,
+                    })
+                }
+            }
+        }
+    }
+
+    /// Matches the frames which have completed so far, if possible.
+    fn try_match(&mut self) -> Option<SyncEvent> {
+        let queues: Vec<_> = self
+            .cameras
+            .iter()
+            .map(|c| (&c.ready, !c.in_flight.is_empty()))
+            .collect();
+
+        match match_frames(&queues, self.tolerance)? {
+            Match::Unmatched(camera) => {
+                let (_, request) = self.cameras[camera].ready.pop_front().unwrap();
+                Some(SyncEvent::Unmatched { camera, request })
+            }
+            Match::Matched => {
+                let (timestamps, requests) = self
+                    .cameras
+                    .iter_mut()
+                    .map(|c| c.ready.pop_front().unwrap())
+                    .unzip();
+
+                let frames = SyncedFrames {
+                    requests,
+                    timestamps,
+                };
+                self.align(&frames);
+
+                Some(SyncEvent::Matched(frames))
+            }
+        }
+    }
+
+    /// Nudges the frame duration of cameras which have drifted away from the
+    /// first camera.
+    fn align(&mut self, frames: &SyncedFrames) {
+        let threshold = match self.alignment_threshold {
+            Some(threshold) => threshold,
+            None => return,
+        };
+
+        let nominal = match frames.requests[0].frame_info().frame_duration {
+            Some(duration) => duration.as_micros() as i64,
+            None => return,
+        };
+        let reference = frames.timestamps[0].as_nanos() as i64;
+
+        for (i, camera) in self.cameras.iter_mut().enumerate().skip(1) {
+            if camera.cooldown > 0 {
+                camera.cooldown -= 1;
+                continue;
+            }
+
+            // Positive if this camera is lagging behind the reference.
+            let offset_us = (frames.timestamps[i].as_nanos() as i64 - reference) / 1000;
+
+            let duration = match nudge(offset_us, nominal, threshold, &mut camera.nudged) {
+                Some(duration) => duration,
+                None => continue,
+            };
+
+            if camera.camera.set_frame_duration(duration) {
+                camera.cooldown = camera.camera.max_latency() + 1;
+            }
+        }
+    }
+}
+
+/// Outcome of match_frames().
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Match {
+    /// The front frames of all queues were captured at the same time.
+    Matched,
+
+    /// The front frame of the queue with the given index can't be matched.
+    Unmatched(usize),
+}
+
+/// Decides whether the frames at the front of each camera's queue of
+/// completed frames match.
+///
+/// Each queue is given along with whether more frames are still expected from
+/// that camera. Returns None if more frames are needed to decide.
+fn match_frames<T>(
+    queues: &[(&VecDeque<(Timestamp, T)>, bool)],
+    tolerance: Duration,
+) -> Option<Match> {
+    // The earliest frame from any camera.
+    let (earliest_queue, earliest) = queues
+        .iter()
+        .enumerate()
+        .filter_map(|(i, (queue, _))| queue.front().map(|(t, _)| (i, *t)))
+        .min_by_key(|(_, t)| *t)?;
+
+    for (i, (queue, more_coming)) in queues.iter().enumerate() {
+        if i == earliest_queue {
+            continue;
+        }
+
+        let front = match queue.front() {
+            Some((t, _)) => *t,
+            // No frame to match against yet. If none are coming, the earliest
+            // frame will never be matched.
+            None if !more_coming => return Some(Match::Unmatched(earliest_queue)),
+            None => return None,
+        };
+
+        // Other frames are all later than the earliest, so the front of each
+        // queue is the nearest candidate for it. It may however be nearer to
+        // the next frame from the same camera as the earliest.
+        let distance = front.saturating_duration_since(earliest);
+        let closer_to_next = queues[earliest_queue]
+            .0
+            .get(1)
+            .map_or(false, |(next, _)| abs_diff(*next, front) < distance);
+
+        if distance > tolerance || closer_to_next {
+            return Some(Match::Unmatched(earliest_queue));
+        }
+    }
+
+    Some(Match::Matched)
+}
+
+/// Computes the frame duration (in microseconds) to set on a camera whose
+/// frames are captured 'offset_us' after those of the reference camera.
+///
+/// 'nudged' tracks whether the camera is running at a corrected duration.
+/// Returns None if the camera's frame duration should be left as is.
+fn nudge(offset_us: i64, nominal_us: i64, threshold: Duration, nudged: &mut bool) -> Option<i64> {
+    if offset_us.unsigned_abs() as u128 > threshold.as_micros() {
+        // Shorten the frames of a lagging camera (or lengthen them if it is
+        // ahead) until it catches up.
+        let max_correction = (nominal_us as f64 * MAX_CORRECTION) as i64;
+        let correction =
+            ((offset_us as f64 * ALIGNMENT_GAIN) as i64).clamp(-max_correction, max_correction);
+        *nudged = true;
+        Some(nominal_us - correction)
+    } else if *nudged {
+        *nudged = false;
+        Some(nominal_us)
+    } else {
+        None
+    }
+}
+
+fn abs_diff(a: Timestamp, b: Timestamp) -> Duration {
+    Duration::from_nanos(a.as_nanos().abs_diff(b.as_nanos()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOLERANCE: Duration = Duration::from_millis(5);
+
+    fn queue(millis: &[u64]) -> VecDeque<(Timestamp, ())> {
+        millis
+            .iter()
+            .map(|ms| (Timestamp::from_nanos(ms * 1_000_000), ()))
+            .collect()
+    }
+
+    fn run(queues: &[(&[u64], bool)]) -> Option<Match> {
+        let queues: Vec<_> = queues
+            .iter()
+            .map(|(millis, more_coming)| (queue(millis), *more_coming))
+            .collect();
+        let queues: Vec<_> = queues.iter().map(|(q, more)| (q, *more)).collect();
+        match_frames(&queues, TOLERANCE)
+    }
+
+    #[test]
+    fn matches_frames_within_tolerance() {
+        assert_eq!(run(&[(&[100], true), (&[103], true)]), Some(Match::Matched));
+        assert_eq!(
+            run(&[(&[103], true), (&[100], true), (&[104], true)]),
+            Some(Match::Matched)
+        );
+    }
+
+    #[test]
+    fn earliest_frame_outside_tolerance_is_unmatched() {
+        assert_eq!(
+            run(&[(&[100], true), (&[110], true)]),
+            Some(Match::Unmatched(0))
+        );
+        assert_eq!(
+            run(&[(&[110], true), (&[100], true)]),
+            Some(Match::Unmatched(1))
+        );
+    }
+
+    #[test]
+    fn waits_for_frames_still_in_flight() {
+        assert_eq!(run(&[(&[], true), (&[], true)]), None);
+        assert_eq!(run(&[(&[100], true), (&[], true)]), None);
+
+        // No frame will arrive to match the one from camera 0.
+        assert_eq!(
+            run(&[(&[100], true), (&[], false)]),
+            Some(Match::Unmatched(0))
+        );
+    }
+
+    #[test]
+    fn prefers_nearest_frame() {
+        // The frame at 104 is within the tolerance of 100 but nearer to 105,
+        // which is the next frame from the same camera.
+        assert_eq!(
+            run(&[(&[100, 105], true), (&[104], true)]),
+            Some(Match::Unmatched(0))
+        );
+        assert_eq!(run(&[(&[105], true), (&[104], true)]), Some(Match::Matched));
+    }
+
+    #[test]
+    fn nudges_drifting_camera() {
+        let threshold = Duration::from_micros(500);
+        let mut nudged = false;
+
+        // Within the threshold, nothing changes.
+        assert_eq!(nudge(400, 33_333, threshold, &mut nudged), None);
+        assert!(!nudged);
+
+        // A lagging camera gets shorter frames, a leading one longer frames.
+        assert_eq!(nudge(1_000, 33_333, threshold, &mut nudged), Some(32_833));
+        assert!(nudged);
+        assert_eq!(nudge(-1_000, 33_333, threshold, &mut nudged), Some(33_833));
+
+        // Large offsets are corrected gradually.
+        assert_eq!(nudge(100_000, 33_333, threshold, &mut nudged), Some(30_000));
+
+        // Once aligned, the nominal duration is restored once.
+        assert_eq!(nudge(100, 33_333, threshold, &mut nudged), Some(33_333));
+        assert!(!nudged);
+        assert_eq!(nudge(100, 33_333, threshold, &mut nudged), None);
+    }
+}
@@ -0,0 +1,300 @@
+use std::ops::{Div, Mul};
+
+/// A combination of horizontal/vertical flips and transposition which can be
+/// applied to an image. Mirrors libcamera::Transform.
+///
+/// When combined, the flips are applied before the transposition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Transform(u8);
+
+impl Transform {
+    pub const IDENTITY: Self = Self(0);
+    pub const HFLIP: Self = Self(1);
+    pub const VFLIP: Self = Self(2);
+    pub const HVFLIP: Self = Self(3);
+    pub const ROT180: Self = Self(3);
+    pub const TRANSPOSE: Self = Self(4);
+    pub const ROT270: Self = Self(5);
+    pub const ROT90: Self = Self(6);
+    pub const ROT180_TRANSPOSE: Self = Self(7);
+
+    /// Gets the transform which rotates an image clockwise by the given
+    /// number of degrees. Returns None if the angle isn't a multiple of 90.
+    pub fn from_rotation(angle: i32) -> Option<Self> {
+        Some(match angle.rem_euclid(360) {
+            0 => Self::IDENTITY,
+            90 => Self::ROT90,
+            180 => Self::ROT180,
+            270 => Self::ROT270,
+            _ => return None,
+        })
+    }
+
+    pub fn hflip(&self) -> bool {
+        self.0 & Self::HFLIP.0 != 0
+    }
+
+    pub fn vflip(&self) -> bool {
+        self.0 & Self::VFLIP.0 != 0
+    }
+
+    pub fn transpose(&self) -> bool {
+        self.0 & Self::TRANSPOSE.0 != 0
+    }
+
+    /// Gets the transform which undoes this one.
+    pub fn inverse(&self) -> Self {
+        // All transforms are self-inverses except for the 90 degree rotations.
+        match *self {
+            Self::ROT90 => Self::ROT270,
+            Self::ROT270 => Self::ROT90,
+            t => t,
+        }
+    }
+}
+
+/// Composes two transforms such that 'self' is applied first and then 'rhs'.
+impl Mul for Transform {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        // If self transposes, reorder the operations so that the transpose
+        // happens after rhs's flips. This swaps rhs's horizontal and vertical
+        // flips after which all the bits can be combined with xor.
+        let mut reordered = rhs;
+        if self.transpose() {
+            reordered = Self(rhs.0 & Self::TRANSPOSE.0);
+            if rhs.hflip() {
+                reordered.0 |= Self::VFLIP.0;
+            }
+            if rhs.vflip() {
+                reordered.0 |= Self::HFLIP.0;
+            }
+        }
+
+        Self(reordered.0 ^ self.0)
+    }
+}
+
+/// Orientation of an image, using the same numbering as the EXIF Orientation
+/// tag. Mirrors libcamera::Orientation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(i32)]
+pub enum Orientation {
+    Rotate0 = 1,
+    Rotate0Mirror = 2,
+    Rotate180 = 3,
+    Rotate180Mirror = 4,
+    Rotate90Mirror = 5,
+    Rotate270 = 6,
+    Rotate270Mirror = 7,
+    Rotate90 = 8,
+}
+
+impl Orientation {
+    pub fn from_exif(value: i32) -> Option<Self> {
+        use Orientation::*;
+
+        Some(match value {
+            1 => Rotate0,
+            2 => Rotate0Mirror,
+            3 => Rotate180,
+            4 => Rotate180Mirror,
+            5 => Rotate90Mirror,
+            6 => Rotate270,
+            7 => Rotate270Mirror,
+            8 => Rotate90,
+            _ => return None,
+        })
+    }
+
+    pub fn to_exif(&self) -> i32 {
+        *self as i32
+    }
+
+    /// Gets the orientation of an image rotated clockwise by the given number
+    /// of degrees. Returns None if the angle isn't a multiple of 90.
+    pub fn from_rotation(angle: i32) -> Option<Self> {
+        Transform::from_rotation(angle).map(Self::from_transform)
+    }
+
+    /// Gets the orientation of an image after applying a transform to an
+    /// image in the Rotate0 orientation.
+    pub fn from_transform(transform: Transform) -> Self {
+        use Orientation::*;
+
+        match transform {
+            Transform::IDENTITY => Rotate0,
+            Transform::HFLIP => Rotate0Mirror,
+            Transform::ROT180 => Rotate180,
+            Transform::VFLIP => Rotate180Mirror,
+            Transform::TRANSPOSE => Rotate90Mirror,
+            Transform::ROT90 => Rotate90,
+            Transform::ROT180_TRANSPOSE => Rotate270Mirror,
+            Transform::ROT270 => Rotate270,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Gets the transform which converts a Rotate0 image to this orientation.
+    pub fn to_transform(&self) -> Transform {
+        use Orientation::*;
+
+        match self {
+            Rotate0 => Transform::IDENTITY,
+            Rotate0Mirror => Transform::HFLIP,
+            Rotate180 => Transform::ROT180,
+            Rotate180Mirror => Transform::VFLIP,
+            Rotate90Mirror => Transform::TRANSPOSE,
+            Rotate90 => Transform::ROT90,
+            Rotate270Mirror => Transform::ROT180_TRANSPOSE,
+            Rotate270 => Transform::ROT270,
+        }
+    }
+}
+
+impl Default for Orientation {
+    fn default() -> Self {
+        Orientation::Rotate0
+    }
+}
+
+/// Gets the orientation after applying a transform to an image in the given
+/// orientation (e.g. Orientation::Rotate180 * Transform::HFLIP).
+impl Mul<Transform> for Orientation {
+    type Output = Orientation;
+
+    fn mul(self, rhs: Transform) -> Orientation {
+        Orientation::from_transform(self.to_transform() * rhs)
+    }
+}
+
+/// Gets the transform which converts an image in the 'rhs' orientation to the
+/// 'self' orientation.
+impl Div for Orientation {
+    type Output = Transform;
+
+    fn div(self, rhs: Orientation) -> Transform {
+        rhs.to_transform().inverse() * self.to_transform()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [Transform; 8] = [
+        Transform::IDENTITY,
+        Transform::HFLIP,
+        Transform::VFLIP,
+        Transform::ROT180,
+        Transform::TRANSPOSE,
+        Transform::ROT270,
+        Transform::ROT90,
+        Transform::ROT180_TRANSPOSE,
+    ];
+
+    #[test]
+    fn inverse_undoes_transform() {
+        for t in ALL {
+            assert_eq!(t * t.inverse(), Transform::IDENTITY, "{:?}", t);
+            assert_eq!(t.inverse() * t, Transform::IDENTITY, "{:?}", t);
+        }
+    }
+
+    #[test]
+    fn composition_matches_libcamera() {
+        // (first, second, composed), as computed by libcamera's operator*.
+        let table = [
+            (Transform::ROT90, Transform::ROT270, Transform::IDENTITY),
+            (Transform::ROT270, Transform::ROT90, Transform::IDENTITY),
+            (Transform::ROT90, Transform::ROT90, Transform::ROT180),
+            (Transform::ROT180, Transform::ROT90, Transform::ROT270),
+            (Transform::HFLIP, Transform::VFLIP, Transform::ROT180),
+            (Transform::HFLIP, Transform::TRANSPOSE, Transform::ROT270),
+            (Transform::TRANSPOSE, Transform::HFLIP, Transform::ROT90),
+            (Transform::TRANSPOSE, Transform::VFLIP, Transform::ROT270),
+            (Transform::ROT90, Transform::HFLIP, Transform::TRANSPOSE),
+            (
+                Transform::HFLIP,
+                Transform::ROT90,
+                Transform::ROT180_TRANSPOSE,
+            ),
+        ];
+
+        for (first, second, composed) in table {
+            assert_eq!(first * second, composed, "{:?} * {:?}", first, second);
+        }
+    }
+
+    #[test]
+    fn composition_is_associative() {
+        for a in ALL {
+            for b in ALL {
+                for c in ALL {
+                    assert_eq!((a * b) * c, a * (b * c));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rotations_add_up() {
+        for a in (0..360).step_by(90) {
+            for b in (0..360).step_by(90) {
+                assert_eq!(
+                    Transform::from_rotation(a).unwrap() * Transform::from_rotation(b).unwrap(),
+                    Transform::from_rotation(a + b).unwrap()
+                );
+            }
+        }
+        assert_eq!(Transform::from_rotation(-90), Some(Transform::ROT270));
+        assert_eq!(Transform::from_rotation(45), None);
+    }
+
+    #[test]
+    fn orientation_conversions() {
+        for value in 1..=8 {
+            let orientation = Orientation::from_exif(value).unwrap();
+            assert_eq!(orientation.to_exif(), value);
+            assert_eq!(
+                Orientation::from_transform(orientation.to_transform()),
+                orientation
+            );
+        }
+        assert_eq!(Orientation::from_exif(0), None);
+        assert_eq!(
+            Orientation::from_rotation(270),
+            Some(Orientation::Rotate270)
+        );
+    }
+
+    #[test]
+    fn orientation_times_transform() {
+        use Orientation::*;
+
+        assert_eq!(Rotate0 * Transform::ROT90, Rotate90);
+        assert_eq!(Rotate90 * Transform::ROT90, Rotate180);
+        assert_eq!(Rotate180 * Transform::HFLIP, Rotate180Mirror);
+        assert_eq!(Rotate90 * Transform::HFLIP, Rotate90Mirror);
+        assert_eq!(Rotate0Mirror * Transform::TRANSPOSE, Rotate270);
+    }
+
+    #[test]
+    fn orientation_divided_by_orientation() {
+        use Orientation::*;
+
+        assert_eq!(Rotate90 / Rotate0, Transform::ROT90);
+        assert_eq!(Rotate0 / Rotate90, Transform::ROT270);
+        assert_eq!(Rotate180 / Rotate0Mirror, Transform::VFLIP);
+
+        // The quotient is the transform which turns the divisor into the
+        // dividend.
+        for a in ALL.map(Orientation::from_transform) {
+            for b in ALL.map(Orientation::from_transform) {
+                assert_eq!(b * (a / b), a, "{:?} / {:?}", a, b);
+            }
+        }
+    }
+}
@@ -0,0 +1,274 @@
+use std::fmt;
+
+use crate::camera::{Camera, RunningCamera};
+use crate::control_value::{ControlPrimitiveValue, ControlValue};
+use crate::controls;
+use crate::geometry::{Point, Rectangle, Size};
+use crate::properties;
+use crate::request::Request;
+
+/// Computes ScalerCrop rectangles for digital zoom and pan.
+///
+/// The crop always has the aspect ratio of the output stream and stays within
+/// the region of the sensor which the pipeline can crop from.
+#[derive(Clone)]
+pub struct ZoomController<'a> {
+    /// Camera to which crop changes are sent, if created with new().
+    camera: Option<&'a Camera>,
+
+    /// Largest crop (in sensor pixels) which can be requested.
+    bounds: Rectangle,
+
+    /// Smallest crop size supported by the pipeline.
+    min_size: Size,
+
+    /// Crop size at a zoom factor of 1.
+    full_size: Size,
+
+    zoom: f32,
+
+    /// Normalized (0 to 1) position of the crop center within 'bounds'.
+    pan: (f32, f32),
+}
+
+impl<'a> ZoomController<'a> {
+    /// Creates a controller using the ScalerCrop limits advertised by a
+    /// running camera.
+    ///
+    /// 'output_size' should be the size of the stream being zoomed into
+    /// (usually the largest configured stream). Changes to the zoom and pan
+    /// are added to the next request enqueued on the camera.
+    ///
+    /// Returns None if the camera doesn't support ScalerCrop.
+    pub fn new(camera: &'a RunningCamera, output_size: Size) -> Option<Self> {
+        let info = camera.controls().get(controls::ScalerCrop)?;
+
+        let max = match info.max() {
+            ControlValue::Primitive(ControlPrimitiveValue::Rectangle(r)) if !r.is_null() => Some(r),
+            _ => None,
+        };
+
+        // Fallback to the sensor properties if the pipeline doesn't report a
+        // maximum. ScalerCrop is relative to the active area so only its size
+        // is used.
+        let bounds = max
+            .or_else(|| camera.properties().get(properties::ScalerCropMaximum))
+            .or_else(|| {
+                camera
+                    .properties()
+                    .get(properties::PixelArrayActiveAreas)
+                    .and_then(|areas| areas.first().copied())
+                    .map(|area| Rectangle::from_size(area.size()))
+            })?;
+
+        let min_size = match info.min() {
+            ControlValue::Primitive(ControlPrimitiveValue::Rectangle(r)) => r.size(),
+            _ => Size::new(0, 0),
+        };
+
+        Some(Self {
+            camera: Some(camera),
+            ..Self::from_limits(bounds, min_size, output_size)
+        })
+    }
+
+    /// Creates a controller from explicit crop limits. The crop must be set
+    /// on requests with apply().
+    pub fn from_limits(bounds: Rectangle, min_size: Size, output_size: Size) -> Self {
+        let full_size = if output_size.width == 0 || output_size.height == 0 {
+            bounds.size()
+        } else {
+            bounds.size().bounded_to_aspect_ratio(output_size)
+        };
+
+        Self {
+            camera: None,
+            bounds,
+            min_size,
+            full_size,
+            zoom: 1.0,
+            pan: (0.5, 0.5),
+        }
+    }
+
+    pub fn bounds(&self) -> Rectangle {
+        self.bounds
+    }
+
+    pub fn zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    /// Gets the largest zoom factor allowed by the minimum crop size.
+    pub fn max_zoom(&self) -> f32 {
+        let x = self.full_size.width as f32 / self.min_size.width.max(1) as f32;
+        let y = self.full_size.height as f32 / self.min_size.height.max(1) as f32;
+        x.min(y).max(1.0)
+    }
+
+    /// Sets the zoom factor, where 1 shows the full field of view and 2 shows
+    /// half the width and height. The factor is clamped to [1, max_zoom()].
+    pub fn set_zoom(&mut self, factor: f32) {
+        self.zoom = if factor.is_nan() {
+            1.0
+        } else {
+            factor.clamp(1.0, self.max_zoom())
+        };
+        self.update();
+    }
+
+    pub fn pan(&self) -> (f32, f32) {
+        self.pan
+    }
+
+    /// Sets the center of the crop as a fraction of the sensor's width and
+    /// height, where (0.5, 0.5) is the center of the sensor.
+    ///
+    /// The crop is moved as needed to stay within bounds, so panning has no
+    /// effect when not zoomed in.
+    pub fn set_pan(&mut self, x: f32, y: f32) {
+        self.pan = (
+            if x.is_nan() { 0.5 } else { x.clamp(0.0, 1.0) },
+            if y.is_nan() { 0.5 } else { y.clamp(0.0, 1.0) },
+        );
+        self.update();
+    }
+
+    /// Gets the ScalerCrop rectangle for the current zoom and pan.
+    pub fn crop(&self) -> Rectangle {
+        let size = (self.full_size / self.zoom)
+            .expanded_to(self.min_size)
+            .bounded_to(self.bounds.size());
+
+        let center = Point::new(
+            self.bounds.x + (self.bounds.width as f32 * self.pan.0).round() as i32,
+            self.bounds.y + (self.bounds.height as f32 * self.pan.1).round() as i32,
+        );
+
+        size.centered_to(center).enclosed_in(&self.bounds)
+    }
+
+    /// Sets the ScalerCrop control of a request to the current crop. This is
+    /// only needed for controllers created with from_limits().
+    pub fn apply(&self, request: &mut Request) {
+        request
+            .controls_mut()
+            .set(controls::ScalerCrop, self.crop());
+    }
+
+    /// Sends the current crop to the camera, if any.
+    fn update(&self) {
+        if let Some(camera) = self.camera {
+            camera.set_scaler_crop(self.crop());
+        }
+    }
+}
+
+impl fmt::Debug for ZoomController<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ZoomController")
+            .field("bounds", &self.bounds)
+            .field("min_size", &self.min_size)
+            .field("full_size", &self.full_size)
+            .field("zoom", &self.zoom)
+            .field("pan", &self.pan)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sensor_16_9() -> ZoomController<'static> {
+        ZoomController::from_limits(
+            Rectangle::new(0, 0, 4056, 3040),
+            Size::new(64, 64),
+            Size::new(1920, 1080),
+        )
+    }
+
+    #[test]
+    fn crop_has_output_aspect_ratio() {
+        let zoom = sensor_16_9();
+        assert_eq!(zoom.crop(), Rectangle::new(0, 380, 4056, 2281));
+
+        let zoom = ZoomController::from_limits(
+            Rectangle::new(0, 0, 4056, 3040),
+            Size::new(64, 64),
+            Size::new(640, 480),
+        );
+        assert_eq!(zoom.crop(), Rectangle::new(2, 0, 4053, 3040));
+
+        // Without an output size the whole area is used.
+        let zoom = ZoomController::from_limits(
+            Rectangle::new(0, 0, 4056, 3040),
+            Size::new(64, 64),
+            Size::new(0, 0),
+        );
+        assert_eq!(zoom.crop(), Rectangle::new(0, 0, 4056, 3040));
+    }
+
+    #[test]
+    fn zoom_is_centered() {
+        let mut zoom = sensor_16_9();
+        zoom.set_zoom(2.0);
+        assert_eq!(zoom.zoom(), 2.0);
+        assert_eq!(zoom.crop(), Rectangle::new(1014, 950, 2028, 1140));
+    }
+
+    #[test]
+    fn zoom_is_clamped() {
+        let mut zoom = sensor_16_9();
+
+        zoom.set_zoom(0.5);
+        assert_eq!(zoom.zoom(), 1.0);
+
+        zoom.set_zoom(f32::NAN);
+        assert_eq!(zoom.zoom(), 1.0);
+
+        zoom.set_zoom(1000.0);
+        assert_eq!(zoom.zoom(), zoom.max_zoom());
+        assert_eq!(zoom.max_zoom(), 2281.0 / 64.0);
+    }
+
+    #[test]
+    fn min_size_limits_zoom() {
+        let mut zoom = ZoomController::from_limits(
+            Rectangle::new(0, 0, 4056, 3040),
+            Size::new(1024, 768),
+            Size::new(1920, 1080),
+        );
+
+        zoom.set_zoom(10.0);
+        assert_eq!(zoom.zoom(), 2281.0 / 768.0);
+
+        let crop = zoom.crop();
+        assert!(crop.width >= 1024 && crop.height == 768, "{}", crop);
+    }
+
+    #[test]
+    fn pan_stays_within_bounds() {
+        let mut zoom = sensor_16_9();
+        zoom.set_zoom(2.0);
+
+        zoom.set_pan(0.0, 0.0);
+        assert_eq!(zoom.crop(), Rectangle::new(0, 0, 2028, 1140));
+
+        zoom.set_pan(1.0, 1.0);
+        assert_eq!(zoom.crop(), Rectangle::new(2028, 1900, 2028, 1140));
+
+        zoom.set_pan(-5.0, f32::NAN);
+        assert_eq!(zoom.pan(), (0.0, 0.5));
+        assert_eq!(zoom.crop(), Rectangle::new(0, 950, 2028, 1140));
+    }
+
+    #[test]
+    fn pan_has_no_effect_at_full_view() {
+        let bounds = Rectangle::new(16, 8, 1920, 1080);
+        let mut zoom = ZoomController::from_limits(bounds, Size::new(64, 64), Size::new(1280, 720));
+
+        zoom.set_pan(0.0, 1.0);
+        assert_eq!(zoom.crop(), bounds);
+    }
+}